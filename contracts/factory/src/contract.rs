@@ -1,13 +1,13 @@
 use std::u128;
 
-use cosmwasm_std::{Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HandleResult, HumanAddr, InitResponse, Querier, QueryResult, ReadonlyStorage, StdError, StdResult, Storage, Uint128, to_binary};
+use cosmwasm_std::{Api, Binary, CanonicalAddr, Env, Extern, HandleResponse, HandleResult, HumanAddr, InitResponse, Querier, QueryResult, ReadonlyStorage, StdError, StdResult, Storage, Uint128, log, to_binary};
 
-use crate::{msg::{AmmAssetInfo, AmmPairResponse, AmmQueryMsg, AssetInfo, HandleAnswer, HandleMsg, InitMsg, QueryAnswer, QueryMsg, ResponseStatus::Success, SecretOrderBookContract, SecretOrderBookContractInitMsg, ChangeFeeMsg, Token}, rand::sha_256};
-use crate::state::{save, load, may_load};
+use crate::{msg::{AmmAssetInfo, AmmPairResponse, AmmQueryMsg, AssetInfo, HandleAnswer, HandleMsg, InitMsg, NativeToken, QueryAnswer, QueryMsg, ResponseStatus::Success, SecretOrderBookContract, SecretOrderBookContractInitMsg, ChangeDepthConfigMsg, ChangeFeeMsg, ChangeFeeScheduleMsg, ChangeScanLimitMsg, ContractStatus, ContractStatusMsg, DepthConfig, FeeSchedule, MigrationContract, StartMigrationMsg, Token, TreasuryMsg}, rand::sha_256};
+use crate::state::{save, load, may_load, remove};
 use crate::viewing_key::{ViewingKey, VIEWING_KEY_SIZE};
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
  
-use secret_toolkit::{snip20::token_info_query, storage::{AppendStore, AppendStoreMut}, utils::{HandleCallback, InitCallback, Query}};
+use secret_toolkit::{snip20::token_info_query, storage::{AppendStore, AppendStoreMut}, utils::{HandleCallback, InitCallback, Query, pad_handle_result, pad_query_result}};
 
 /// prefix for viewing keys
 pub const PREFIX_VIEW_KEY: &[u8] = b"viewingkey";
@@ -25,10 +25,16 @@ pub const FACTORY_KEY: &[u8] = b"factorykey";
 pub const PREFIX_SECRET_ORDER_BOOKS: &[u8] = b"secretorderbooks";
 /// storage key for the secret order books
 pub const PREFIX_SECRET_ORDER_BOOK: &[u8] = b"secretorderbook";
+/// storage key for the secret order books being replaced, until the new one calls back
+pub const PREFIX_PENDING_MIGRATIONS: &[u8] = b"pendingmigrations";
 /// storage key for the amm factory address
 pub const AMM_FACTORY_ADDRESS: &[u8] = b"ammfactoryaddress";
 /// storage key for the children contracts 
 pub const AMM_FACTORY_HASH: &[u8] = b"ammfactoryhash";
+/// storage key for the sSCRT token that wraps native SCRT for the order books
+pub const SSCRT_TOKEN_KEY: &[u8] = b"sscrttoken";
+/// only native denom the order books can wrap
+pub const NATIVE_DENOM: &str = "uscrt";
 /// response size
 pub const BLOCK_SIZE: usize = 256;
 
@@ -46,6 +52,9 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     save(&mut deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_HASH, &msg.secret_order_book_code_hash)?;
     save(&mut deps.storage, AMM_FACTORY_ADDRESS, &msg.amm_factory_contract_address)?;
     save(&mut deps.storage, AMM_FACTORY_HASH, &msg.amm_factory_contract_hash)?;
+    if let Some(sscrt_token) = msg.sscrt_token {
+        save(&mut deps.storage, SSCRT_TOKEN_KEY, &sscrt_token)?;
+    }
     Ok(InitResponse::default())
 }
 
@@ -54,15 +63,18 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    match msg {
+    // Answers are padded so their size does not tell which action was taken
+    let response = match msg {
         HandleMsg::CreateViewingKey { entropy } => try_create_key(deps, env, &entropy),
         HandleMsg::ChangeSecretOrderBookContractCodeId { code_id, code_hash } => try_change_secret_order_book_contract_code_id(deps, env, &code_id, &code_hash),
         HandleMsg::NewSecretOrderBookInstanciate {
             amm_pair_address,
             amm_pair_hash,
             token1_fee,
-            token2_fee
-        } => try_secret_order_book_instanciate(deps, env, &amm_pair_address, &amm_pair_hash, &token1_fee, &token2_fee),
+            token2_fee,
+            token1_min_amount,
+            token2_min_amount
+        } => try_secret_order_book_instanciate(deps, env, &amm_pair_address, &amm_pair_hash, &token1_fee, &token2_fee, token1_min_amount, token2_min_amount),
         HandleMsg::InitCallBackFromSecretOrderBookToFactory {
             auth_key, 
             amm_pair_address,
@@ -74,9 +86,40 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
         HandleMsg::ChangeAssetFee {
             amm_pairs_address,
             asset_contract_address,
-            new_asset_fee
-        } => try_change_asset_fee(deps, env, amm_pairs_address, asset_contract_address, new_asset_fee)
-    }
+            new_asset_fee,
+            new_asset_min_amount
+        } => try_change_asset_fee(deps, env, amm_pairs_address, asset_contract_address, new_asset_fee, new_asset_min_amount),
+        HandleMsg::ChangeOrderBookDepthConfig {
+            amm_pairs_address,
+            depth_config
+        } => try_change_order_book_depth_config(deps, env, amm_pairs_address, depth_config),
+        HandleMsg::ChangeOrderBookScanLimit {
+            amm_pairs_address,
+            scan_limit
+        } => try_change_order_book_scan_limit(deps, env, amm_pairs_address, scan_limit),
+        HandleMsg::ChangeOrderBookFeeSchedule {
+            amm_pairs_address,
+            is_bid,
+            fee_schedule
+        } => try_change_order_book_fee_schedule(deps, env, amm_pairs_address, is_bid, fee_schedule),
+        HandleMsg::ChangeOrderBookProtocolFee {
+            amm_pairs_address,
+            basis_points
+        } => try_send_order_book_treasury_msg(deps, env, amm_pairs_address, TreasuryMsg::ChangeProtocolFee { basis_points }),
+        HandleMsg::WithdrawOrderBookTreasury {
+            amm_pairs_address,
+            recipient
+        } => try_send_order_book_treasury_msg(deps, env, amm_pairs_address, TreasuryMsg::WithdrawTreasury { recipient }),
+        HandleMsg::SetOrderBookContractStatus {
+            amm_pairs_address,
+            status
+        } => try_set_order_book_contract_status(deps, env, amm_pairs_address, status),
+        HandleMsg::MigrateOrderBook {
+            amm_pair_address,
+            amm_pair_hash
+        } => try_migrate_order_book(deps, env, amm_pair_address, amm_pair_hash)
+    };
+    pad_handle_result(response, BLOCK_SIZE)
 }
 
 fn try_create_key<S: Storage, A: Api, Q: Querier>(
@@ -116,7 +159,14 @@ fn try_change_secret_order_book_contract_code_id<S: Storage, A: Api, Q: Querier>
     save(&mut deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_ID, &code_id)?;
     save(&mut deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_HASH, &code_hash)?;
     
-    Ok(HandleResponse::default())
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
 }
 
 fn try_secret_order_book_instanciate<S: Storage, A: Api, Q: Querier>(
@@ -125,7 +175,9 @@ fn try_secret_order_book_instanciate<S: Storage, A: Api, Q: Querier>(
     amm_pair_address: &HumanAddr,
     amm_pair_hash: &String,
     token1_fee: &Uint128,
-    token2_fee: &Uint128
+    token2_fee: &Uint128,
+    token1_min_amount: Option<Uint128>,
+    token2_min_amount: Option<Uint128>
 ) -> HandleResult {  
     let secret_order_book_contract_code_id: u64 = load(&deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_ID)?;
     let secret_order_book_contract_code_hash: String = load(&deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_HASH)?;
@@ -141,41 +193,8 @@ fn try_secret_order_book_instanciate<S: Storage, A: Api, Q: Querier>(
     let response: AmmPairResponse =
     AmmQueryMsg::Pair {}.query(&deps.querier, amm_pair_hash.to_string(), amm_pair_address.to_owned())?;
 
-    let mut token1_info: AssetInfo = match response.asset_infos[0].clone() {
-        AmmAssetInfo::NativeToken { .. } => {
-            return Err(StdError::generic_err(
-                "Native token not supported!",
-            ));
-        },
-        AmmAssetInfo::Token { contract_addr, token_code_hash, viewing_key } => AssetInfo {
-            decimal_places: 0,
-            base_amount: Uint128(0),
-            fee_amount: token1_fee.clone(),
-            min_amount: token1_fee.clone().multiply_ratio(Uint128(2),Uint128(1)),
-            token: Some(Token {
-                contract_addr: HumanAddr(contract_addr),
-                token_code_hash
-            })
-        }
-    };
-
-    let mut token2_info: AssetInfo = match response.asset_infos[1].clone() {
-        AmmAssetInfo::NativeToken { .. } => {
-            return Err(StdError::generic_err(
-                "Native token not supported!",
-            ));
-        },
-        crate::msg::AmmAssetInfo::Token { contract_addr, token_code_hash, viewing_key } => AssetInfo {
-            decimal_places: 0,
-            base_amount: Uint128(0),
-            fee_amount: token2_fee.clone(),
-            min_amount: token2_fee.clone().multiply_ratio(Uint128(2),Uint128(1)),
-            token: Some(Token {
-                contract_addr: HumanAddr(contract_addr),
-                token_code_hash
-            })
-        }
-    };
+    let mut token1_info: AssetInfo = amm_asset_to_asset_info(&deps.storage, response.asset_infos[0].clone(), token1_fee, token1_min_amount)?;
+    let mut token2_info: AssetInfo = amm_asset_to_asset_info(&deps.storage, response.asset_infos[1].clone(), token2_fee, token2_min_amount)?;
 
     let token1_symbol:String;
     let token2_symbol:String;
@@ -199,6 +218,7 @@ fn try_secret_order_book_instanciate<S: Storage, A: Api, Q: Querier>(
         token2_info: token2_info.clone(),
         amm_pair_contract_address: amm_pair_address.clone(),
         amm_pair_contract_hash: amm_pair_hash.clone(),
+        migrate_from: None,
     };
 
     impl InitCallback for SecretOrderBookContractInitMsg {
@@ -218,6 +238,46 @@ fn try_secret_order_book_instanciate<S: Storage, A: Api, Q: Querier>(
     })
 }
 
+// Native SCRT is held by the order book as sSCRT, so its token is the sSCRT contract.
+// The native token tells the order book to swap it on the pair as SCRT
+fn amm_asset_to_asset_info<S: ReadonlyStorage>(
+    storage: &S,
+    amm_asset_info: AmmAssetInfo,
+    fee: &Uint128,
+    min_amount: Option<Uint128>
+) -> StdResult<AssetInfo> {
+    let (token, native_token) = match amm_asset_info {
+        AmmAssetInfo::NativeToken { denom } => {
+            if denom != NATIVE_DENOM {
+                return Err(StdError::generic_err(format!(
+                    "Native token {} not supported!",
+                    denom
+                )));
+            }
+            let sscrt_token: Option<Token> = may_load(storage, SSCRT_TOKEN_KEY)?;
+            if sscrt_token == None {
+                return Err(StdError::generic_err(
+                    "Native token needs the sSCRT token set on the factory!",
+                ));
+            }
+            (sscrt_token.unwrap(), Some(NativeToken { denom }))
+        },
+        AmmAssetInfo::Token { contract_addr, token_code_hash, .. } => (Token {
+            contract_addr: HumanAddr(contract_addr),
+            token_code_hash
+        }, None)
+    };
+
+    Ok(AssetInfo {
+        decimal_places: 0,
+        base_amount: Uint128(0),
+        fee_amount: fee.clone(),
+        min_amount: min_amount.unwrap_or(fee.clone().multiply_ratio(Uint128(2),Uint128(1))),
+        token: Some(token),
+        native_token
+    })
+}
+
 pub fn try_secret_order_book_instanciated_callback<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
@@ -247,15 +307,119 @@ pub fn try_secret_order_book_instanciated_callback<S: Storage, A: Api, Q: Querie
         ]
     };
 
-    // Store this contract
-    let mut secret_order_books = PrefixedStorage::new(PREFIX_SECRET_ORDER_BOOKS, &mut deps.storage);
-    let mut secret_order_books = AppendStoreMut::attach_or_create(&mut secret_order_books)?;
-    secret_order_books.push(&secret_order_book_contract)?;
+    let amm_pair_canonical = deps.api.canonical_address(&amm_pair_address)?;
+    let pending_migrations = ReadonlyPrefixedStorage::new(PREFIX_PENDING_MIGRATIONS, &deps.storage);
+    let migrated_secret_order_book: Option<SecretOrderBookContract> = may_load(&pending_migrations, amm_pair_canonical.as_slice())?;
+
+    let mut messages = vec![];
+    match migrated_secret_order_book {
+        // Replace the secret order book of the pair and tell the old one where to send its orders
+        Some(migrated_secret_order_book) => {
+            let mut pending_migrations = PrefixedStorage::new(PREFIX_PENDING_MIGRATIONS, &mut deps.storage);
+            remove(&mut pending_migrations, amm_pair_canonical.as_slice());
+
+            let mut secret_order_books = PrefixedStorage::new(PREFIX_SECRET_ORDER_BOOKS, &mut deps.storage);
+            let mut store = AppendStoreMut::<SecretOrderBookContract, _>::attach_or_create(&mut secret_order_books)?;
+            let mut index_to_modify: Option<usize> = None;
+            let tx_iter: StdResult<Vec<SecretOrderBookContract>> = store.iter().collect();
+            for (index, v) in tx_iter?.iter().enumerate() {
+                if v.amm_pair_contract_addr == amm_pair_address {
+                    index_to_modify = Some(index);
+                    break;
+                }
+            };
+            match index_to_modify {
+                Some(index) => store.set_at(index as u32, &secret_order_book_contract)?,
+                None => store.push(&secret_order_book_contract)?
+            }
+
+            let start_migration_msg = StartMigrationMsg::StartMigration {
+                contract_address: secret_order_book_contract.contract_addr.clone(),
+                contract_hash: secret_order_book_contract.contract_hash.clone()
+            };
+            messages.push(start_migration_msg.to_cosmos_msg(migrated_secret_order_book.contract_hash, migrated_secret_order_book.contract_addr, None)?);
+        },
+        // Store this contract
+        None => {
+            let mut secret_order_books = PrefixedStorage::new(PREFIX_SECRET_ORDER_BOOKS, &mut deps.storage);
+            let mut secret_order_books = AppendStoreMut::attach_or_create(&mut secret_order_books)?;
+            secret_order_books.push(&secret_order_book_contract)?;
+        }
+    }
 
     let mut secret_order_book = PrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &mut deps.storage);
-    save(&mut secret_order_book, &deps.api.canonical_address(&amm_pair_address.clone())?.as_slice(), &secret_order_book_contract)?;
+    save(&mut secret_order_book, amm_pair_canonical.as_slice(), &secret_order_book_contract)?;
 
-    Ok(HandleResponse::default())
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_migrate_order_book<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pair_address: HumanAddr,
+    amm_pair_hash: String
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let amm_pair_canonical = deps.api.canonical_address(&amm_pair_address)?;
+    let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+    let secret_order_book_contract: SecretOrderBookContract = match may_load(&secret_order_book, amm_pair_canonical.as_slice())? {
+        Some(secret_order_book_contract) => secret_order_book_contract,
+        None => return Err(StdError::generic_err(format!(
+            "Error on: {:?}", amm_pair_address
+        )))
+    };
+
+    let mut pending_migrations = PrefixedStorage::new(PREFIX_PENDING_MIGRATIONS, &mut deps.storage);
+    if may_load::<SecretOrderBookContract, _>(&pending_migrations, amm_pair_canonical.as_slice())?.is_some() {
+        return Err(StdError::generic_err(format!(
+            "Migration already pending for {:?}", amm_pair_address
+        )));
+    }
+    save(&mut pending_migrations, amm_pair_canonical.as_slice(), &secret_order_book_contract)?;
+
+    let secret_order_book_contract_code_id: u64 = load(&deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_ID)?;
+    let secret_order_book_contract_code_hash: String = load(&deps.storage, SECRET_ORDER_BOOK_CONTRACT_CODE_HASH)?;
+    let factory_key: String = load(&deps.storage, FACTORY_KEY)?;
+
+    // The new secret order book keeps the asset infos of the old one
+    let initmsg = SecretOrderBookContractInitMsg {
+        factory_hash: env.contract_code_hash,
+        factory_address: env.contract.address,
+        factory_key,
+        token1_info: secret_order_book_contract.asset_infos[0].clone(),
+        token2_info: secret_order_book_contract.asset_infos[1].clone(),
+        amm_pair_contract_address: amm_pair_address.clone(),
+        amm_pair_contract_hash: amm_pair_hash,
+        migrate_from: Some(MigrationContract {
+            address: secret_order_book_contract.contract_addr.clone(),
+            code_hash: secret_order_book_contract.contract_hash.clone()
+        })
+    };
+
+    let cosmosmsg =
+        initmsg.to_cosmos_msg(format!("({}) Secret Order Book - migration of {}",secret_order_book_contract_code_id,secret_order_book_contract.contract_addr).to_string(), secret_order_book_contract_code_id, secret_order_book_contract_code_hash, None)?;
+
+    Ok(HandleResponse {
+        messages: vec![cosmosmsg],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
 }
 
 pub fn try_change_asset_fee<S: Storage, A: Api, Q: Querier>(
@@ -263,7 +427,8 @@ pub fn try_change_asset_fee<S: Storage, A: Api, Q: Querier>(
     env: Env,
     amm_pairs_address: Vec<HumanAddr>,
     asset_contract_address: HumanAddr,
-    new_asset_fee: Uint128
+    new_asset_fee: Uint128,
+    new_asset_min_amount: Option<Uint128>
 ) -> HandleResult {   
     let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
     if env.message.sender != admin {
@@ -273,6 +438,10 @@ pub fn try_change_asset_fee<S: Storage, A: Api, Q: Querier>(
     }
 
     let mut cosmos_msgs = vec![];
+    let mut logs = vec![
+        log("action", "change_asset_fee"),
+        log("asset", asset_contract_address.clone()),
+    ];
 
     for i in 0..amm_pairs_address.len() {
         // 1. Get each secret order book associated with each amm_pair_address indicated 
@@ -295,7 +464,7 @@ pub fn try_change_asset_fee<S: Storage, A: Api, Q: Querier>(
         // 3. Modify the asset_info with the new fee and send to the secret order book this change
         // 3.1 PREFIX_SECRET_ORDER_BOOK
         modified_secret_order_book.asset_infos[token_index].fee_amount = new_asset_fee;
-        modified_secret_order_book.asset_infos[token_index].min_amount = new_asset_fee.clone().multiply_ratio(Uint128(2),Uint128(1));
+        modified_secret_order_book.asset_infos[token_index].min_amount = new_asset_min_amount.unwrap_or(new_asset_fee.clone().multiply_ratio(Uint128(2),Uint128(1)));
         save(&mut secret_order_book, &deps.api.canonical_address(&amm_pairs_address[i])?.as_slice(), &modified_secret_order_book)?;
         
         // 3.2 PREFIX_SECRET_ORDER_BOOKS
@@ -322,7 +491,208 @@ pub fn try_change_asset_fee<S: Storage, A: Api, Q: Querier>(
         };
     
         let cosmos_msg = change_fee_msg.to_cosmos_msg(modified_secret_order_book.contract_hash.clone(), modified_secret_order_book.contract_addr.clone(), None)?;
-        cosmos_msgs.push(cosmos_msg)
+        cosmos_msgs.push(cosmos_msg);
+        logs.push(log("pair", amm_pairs_address[i].clone()));
+    }
+    logs.push(log("fee_amount", new_asset_fee));
+
+    Ok(HandleResponse {
+        messages: cosmos_msgs,
+        log: logs,
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_order_book_depth_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pairs_address: Vec<HumanAddr>,
+    depth_config: Option<DepthConfig>
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let mut cosmos_msgs = vec![];
+
+    let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+    for amm_pair_address in amm_pairs_address {
+        let load_secret_order_book: SecretOrderBookContract = may_load(&secret_order_book, &deps.api.canonical_address(&amm_pair_address)?.as_slice())?
+            .ok_or_else(|| StdError::generic_err(format!(
+                "Error on: {:?}", amm_pair_address
+            )))?;
+
+        let change_depth_config_msg = ChangeDepthConfigMsg::ChangeDepthConfig {
+            depth_config: depth_config.clone()
+        };
+        cosmos_msgs.push(change_depth_config_msg.to_cosmos_msg(load_secret_order_book.contract_hash, load_secret_order_book.contract_addr, None)?);
+    }
+
+    Ok(HandleResponse {
+        messages: cosmos_msgs,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_order_book_scan_limit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pairs_address: Vec<HumanAddr>,
+    scan_limit: u32
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let mut cosmos_msgs = vec![];
+
+    let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+    for amm_pair_address in amm_pairs_address {
+        let load_secret_order_book: SecretOrderBookContract = may_load(&secret_order_book, &deps.api.canonical_address(&amm_pair_address)?.as_slice())?
+            .ok_or_else(|| StdError::generic_err(format!(
+                "Error on: {:?}", amm_pair_address
+            )))?;
+
+        let change_scan_limit_msg = ChangeScanLimitMsg::ChangeScanLimit {
+            scan_limit
+        };
+        cosmos_msgs.push(change_scan_limit_msg.to_cosmos_msg(load_secret_order_book.contract_hash, load_secret_order_book.contract_addr, None)?);
+    }
+
+    Ok(HandleResponse {
+        messages: cosmos_msgs,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_order_book_fee_schedule<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pairs_address: Vec<HumanAddr>,
+    is_bid: bool,
+    fee_schedule: Option<FeeSchedule>
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let mut cosmos_msgs = vec![];
+
+    let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+    for amm_pair_address in amm_pairs_address {
+        let load_secret_order_book: SecretOrderBookContract = may_load(&secret_order_book, &deps.api.canonical_address(&amm_pair_address)?.as_slice())?
+            .ok_or_else(|| StdError::generic_err(format!(
+                "Error on: {:?}", amm_pair_address
+            )))?;
+
+        let change_fee_schedule_msg = ChangeFeeScheduleMsg::ChangeFeeSchedule {
+            is_bid,
+            fee_schedule: fee_schedule.clone()
+        };
+        cosmos_msgs.push(change_fee_schedule_msg.to_cosmos_msg(load_secret_order_book.contract_hash, load_secret_order_book.contract_addr, None)?);
+    }
+
+    Ok(HandleResponse {
+        messages: cosmos_msgs,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_send_order_book_treasury_msg<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pairs_address: Vec<HumanAddr>,
+    treasury_msg: TreasuryMsg
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let mut cosmos_msgs = vec![];
+
+    let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+    for amm_pair_address in amm_pairs_address {
+        let load_secret_order_book: SecretOrderBookContract = may_load(&secret_order_book, &deps.api.canonical_address(&amm_pair_address)?.as_slice())?
+            .ok_or_else(|| StdError::generic_err(format!(
+                "Error on: {:?}", amm_pair_address
+            )))?;
+
+        cosmos_msgs.push(treasury_msg.to_cosmos_msg(load_secret_order_book.contract_hash, load_secret_order_book.contract_addr, None)?);
+    }
+
+    Ok(HandleResponse {
+        messages: cosmos_msgs,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_set_order_book_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amm_pairs_address: Option<Vec<HumanAddr>>,
+    status: ContractStatus
+) -> HandleResult {
+    let admin: HumanAddr = load(&deps.storage, ADMIN_KEY)?;
+    if env.message.sender != admin {
+        return Err(StdError::generic_err(
+            "Permission Denied.",
+        ));
+    }
+
+    let mut load_secret_order_books: Vec<SecretOrderBookContract> = vec![];
+    if let Some(amm_pairs_address) = amm_pairs_address {
+        let secret_order_book = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOK, &deps.storage);
+        for amm_pair_address in amm_pairs_address {
+            load_secret_order_books.push(may_load(&secret_order_book, &deps.api.canonical_address(&amm_pair_address)?.as_slice())?
+                .ok_or_else(|| StdError::generic_err(format!(
+                    "Error on: {:?}", amm_pair_address
+                )))?);
+        }
+    } else {
+        // Every registered secret order book
+        let secret_order_books = ReadonlyPrefixedStorage::new(PREFIX_SECRET_ORDER_BOOKS, &deps.storage);
+        if let Some(store) = AppendStore::<SecretOrderBookContract, _>::attach(&secret_order_books) {
+            load_secret_order_books = store?.iter().collect::<StdResult<Vec<SecretOrderBookContract>>>()?;
+        }
+    }
+
+    let mut cosmos_msgs = vec![];
+    for load_secret_order_book in load_secret_order_books {
+        let contract_status_msg = ContractStatusMsg::SetContractStatus {
+            status: status.clone()
+        };
+        cosmos_msgs.push(contract_status_msg.to_cosmos_msg(load_secret_order_book.contract_hash, load_secret_order_book.contract_addr, None)?);
     }
 
     Ok(HandleResponse {
@@ -339,7 +709,7 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
-    match msg {
+    let response = match msg {
         QueryMsg::IsKeyValid {
             address,
             viewing_key,
@@ -348,7 +718,8 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
         QueryMsg::SecretOrderBookContractCodeId {} => secret_order_book_contract_code_id(deps),
         QueryMsg::SecretOrderBook {amm_pair_contract_addr} => secret_order_book(deps,amm_pair_contract_addr),
         QueryMsg::SecretOrderBooks {page_size, page} => secret_order_books(deps, page_size, page)
-    }
+    };
+    pad_query_result(response, BLOCK_SIZE)
 }
 
 fn try_validate_key<S: Storage, A: Api, Q: Querier>(
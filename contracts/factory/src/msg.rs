@@ -10,7 +10,9 @@ pub struct InitMsg {
     pub secret_order_book_code_id: u64,
     pub secret_order_book_code_hash: String,
     pub amm_factory_contract_address: HumanAddr,
-    pub amm_factory_contract_hash: String
+    pub amm_factory_contract_hash: String,
+    /// sSCRT token used to wrap native SCRT, needed for pairs with a native side
+    pub sscrt_token: Option<Token>
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -21,7 +23,28 @@ pub struct SecretOrderBookContractInitMsg {
     pub token1_info: AssetInfo,
     pub token2_info: AssetInfo,
     pub amm_pair_contract_address: HumanAddr,
-    pub amm_pair_contract_hash: String
+    pub amm_pair_contract_hash: String,
+    pub migrate_from: Option<MigrationContract>
+}
+
+/// Secret order book the orders of a new secret order book are imported from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationContract {
+    pub address: HumanAddr,
+    pub code_hash: String
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StartMigrationMsg {
+    StartMigration {
+        contract_address: HumanAddr,
+        contract_hash: String
+    }
+}
+
+impl HandleCallback for StartMigrationMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -38,6 +61,104 @@ impl HandleCallback for ChangeFeeMsg {
     const BLOCK_SIZE: usize = BLOCK_SIZE;
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeDepthConfigMsg {
+    ChangeDepthConfig {
+        depth_config: Option<DepthConfig>
+    }
+}
+
+impl HandleCallback for ChangeDepthConfigMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeScanLimitMsg {
+    ChangeScanLimit {
+        scan_limit: u32
+    }
+}
+
+impl HandleCallback for ChangeScanLimitMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeFeeScheduleMsg {
+    ChangeFeeSchedule {
+        is_bid: bool,
+        fee_schedule: Option<FeeSchedule>
+    }
+}
+
+impl HandleCallback for ChangeFeeScheduleMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TreasuryMsg {
+    ChangeProtocolFee {
+        basis_points: u16
+    },
+    WithdrawTreasury {
+        recipient: HumanAddr
+    }
+}
+
+impl HandleCallback for TreasuryMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatusMsg {
+    SetContractStatus {
+        status: ContractStatus
+    }
+}
+
+impl HandleCallback for ContractStatusMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
+/// What a secret order book still accepts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Normal,
+    StopTriggering,
+    WithdrawOnly,
+}
+
+/// Price bands of a secret order book OrderBookDepth query
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepthConfig {
+    /// Decimal price string, checked by the secret order book
+    pub band_width: String,
+    pub min_orders_per_band: u32
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSource {
+    Deposit,
+    Proceeds,
+}
+
+/// Keeper fee of one side of a secret order book, checked by the secret order book
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeSchedule {
+    pub flat_amount: Uint128,
+    pub basis_points: u16,
+    pub min_fee: Option<Uint128>,
+    pub max_fee: Option<Uint128>,
+    pub source: FeeSource,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
@@ -47,7 +168,10 @@ pub enum HandleMsg {
         amm_pair_address: HumanAddr,
         amm_pair_hash: String,
         token1_fee: Uint128,
-        token2_fee: Uint128
+        token2_fee: Uint128,
+        /// Smallest order amounts, twice the fee when not given
+        token1_min_amount: Option<Uint128>,
+        token2_min_amount: Option<Uint128>
     },
     InitCallBackFromSecretOrderBookToFactory {
         auth_key: String, 
@@ -60,7 +184,46 @@ pub enum HandleMsg {
     ChangeAssetFee {
         amm_pairs_address: Vec<HumanAddr>,
         asset_contract_address: HumanAddr,
-        new_asset_fee: Uint128
+        new_asset_fee: Uint128,
+        /// Twice the new fee when not given
+        new_asset_min_amount: Option<Uint128>
+    },
+    /// Enables or disables (None) the public depth query of the given secret order books
+    ChangeOrderBookDepthConfig {
+        amm_pairs_address: Vec<HumanAddr>,
+        depth_config: Option<DepthConfig>
+    },
+    /// Sets how many orders the given secret order books simulate per trigger search
+    ChangeOrderBookScanLimit {
+        amm_pairs_address: Vec<HumanAddr>,
+        scan_limit: u32
+    },
+    /// Sets the keeper fee of one side of the given secret order books, None goes back to the flat asset fee
+    ChangeOrderBookFeeSchedule {
+        amm_pairs_address: Vec<HumanAddr>,
+        is_bid: bool,
+        fee_schedule: Option<FeeSchedule>
+    },
+    /// Sets the share of each fill the given secret order books keep in their treasury, in basis points
+    ChangeOrderBookProtocolFee {
+        amm_pairs_address: Vec<HumanAddr>,
+        basis_points: u16
+    },
+    /// Sends the treasury of the given secret order books to recipient
+    WithdrawOrderBookTreasury {
+        amm_pairs_address: Vec<HumanAddr>,
+        recipient: HumanAddr
+    },
+    /// Pauses or resumes the given secret order books, or all of them with None
+    SetOrderBookContractStatus {
+        amm_pairs_address: Option<Vec<HumanAddr>>,
+        status: ContractStatus
+    },
+    /// Instantiates the current secret order book code for the pair and moves the orders of its
+    /// secret order book there once the new one calls back
+    MigrateOrderBook {
+        amm_pair_address: HumanAddr,
+        amm_pair_hash: String
     }
 }
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
@@ -137,7 +300,8 @@ pub struct AssetInfo {
     pub base_amount: Uint128,
     pub fee_amount: Uint128,
     pub min_amount: Uint128,
-    pub token: Option<Token>
+    pub token: Option<Token>,
+    pub native_token: Option<NativeToken>
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
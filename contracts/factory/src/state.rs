@@ -31,4 +31,8 @@ pub fn may_load<T: DeserializeOwned, S: ReadonlyStorage>(
         Some(value) => Bincode2::deserialize(&value).map(Some),
         None => Ok(None),
     }
-}
\ No newline at end of file
+}
+
+pub fn remove<S: Storage>(storage: &mut S, key: &[u8]) {
+    storage.remove(key);
+}
@@ -1,9 +1,9 @@
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{contract::{PREFIX_VIEW_KEY, query}, msg::{AssetInfo, NativeToken, ResponseStatus, SecretOrderBookContract, Token}};
+    use crate::{contract::{PREFIX_VIEW_KEY, query}, msg::{AssetInfo, ContractStatus, NativeToken, ResponseStatus, SecretOrderBookContract, Token}};
     use cosmwasm_std::{Extern, HumanAddr, StdResult, Uint128, testing::*};
-    use cosmwasm_std::{from_binary, BlockInfo, ContractInfo, MessageInfo, QueryResponse, WasmMsg};
+    use cosmwasm_std::{from_binary, log, BlockInfo, ContractInfo, CosmosMsg, MessageInfo, QueryResponse, WasmMsg};
     use schemars::_serde_json::{de, to_string};
     use std::any::Any;
     use crate::state::{save, load, may_load};
@@ -35,6 +35,7 @@ mod tests {
             secret_order_book_code_hash,
             amm_factory_contract_address,
             amm_factory_contract_hash,
+            sscrt_token: None,
         };
     
         (init(&mut deps, env, init_msg), deps)
@@ -237,6 +238,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             token2_info: AssetInfo {
                 decimal_places:6,
@@ -244,6 +246,7 @@ mod tests {
                 fee_amount: Uint128(500000),
                 min_amount: Uint128(1000000),
                 token: None,
+                native_token: Some(NativeToken {denom:"uscrt".to_string()}),
             },
             amm_pair_address: HumanAddr("ammpaircontract1".to_string()),
         };
@@ -265,6 +268,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             token2_info: AssetInfo {
                 decimal_places: 18,
@@ -272,6 +276,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             amm_pair_address: HumanAddr("ammpaircontract2".to_string()),
         };
@@ -305,9 +310,11 @@ mod tests {
                         fee_amount: Uint128(500000000000000000),
                         min_amount: Uint128(1500000000000000),
                         token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                        native_token: None,
                     },
                     AssetInfo {
                         token: None,
+                        native_token: Some(NativeToken {denom:"uscrt".to_string()}),
                         base_amount: Uint128(1000000),
                         fee_amount: Uint128(500000),
                         min_amount: Uint128(1000000),
@@ -323,6 +330,7 @@ mod tests {
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         },
                         AssetInfo {
                             decimal_places: 18,
@@ -330,6 +338,7 @@ mod tests {
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         }
                     ]);
                 assert_eq!(secret_order_books[1].amm_pair_contract_addr, HumanAddr("ammpaircontract2".to_string()));
@@ -358,9 +367,11 @@ mod tests {
                         fee_amount: Uint128(500000000000000000),
                         min_amount: Uint128(1500000000000000),
                         token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                        native_token: None,
                     },
                     AssetInfo {
                         token: None,
+                        native_token: Some(NativeToken {denom:"uscrt".to_string()}),
                         base_amount: Uint128(1000000),
                         fee_amount: Uint128(500000),
                         min_amount: Uint128(1000000),
@@ -392,7 +403,8 @@ mod tests {
                             base_amount: Uint128(1000000000000000000),
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
-                            token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()})
+                            token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None
                         },
                         AssetInfo {
                             decimal_places: 18,
@@ -400,6 +412,7 @@ mod tests {
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         }
                     ])
             },
@@ -450,6 +463,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             token2_info: AssetInfo {
                 decimal_places:6,
@@ -457,6 +471,7 @@ mod tests {
                 fee_amount: Uint128(500000),
                 min_amount: Uint128(1000000),
                 token: None,
+                native_token: Some(NativeToken {denom:"uscrt".to_string()}),
             },
             amm_pair_address: HumanAddr("ammpaircontract1".to_string()),
         };
@@ -478,6 +493,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             token2_info: AssetInfo {
                 decimal_places: 18,
@@ -485,6 +501,7 @@ mod tests {
                 fee_amount: Uint128(500000000000000000),
                 min_amount: Uint128(1500000000000000),
                 token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
             },
             amm_pair_address: HumanAddr("ammpaircontract2".to_string()),
         };
@@ -500,7 +517,8 @@ mod tests {
         let handle_msg = HandleMsg::ChangeAssetFee {
             amm_pairs_address: vec![HumanAddr("ammpaircontract2".to_string())],
             asset_contract_address: HumanAddr("token3".to_string()),
-            new_asset_fee: Uint128(1000000000000000000)
+            new_asset_fee: Uint128(1000000000000000000),
+            new_asset_min_amount: None
         };
     
         let handle_result = handle(&mut deps, mock_env("admin", &[]), handle_msg.clone());
@@ -509,6 +527,12 @@ mod tests {
             "handle() failed: {}",
             handle_result.err().unwrap()
         ); 
+        assert_eq!(handle_result.unwrap().log, vec![
+            log("action", "change_asset_fee"),
+            log("asset", "token3"),
+            log("pair", "ammpaircontract2"),
+            log("fee_amount", "1000000000000000000"),
+        ]);
 
         let query_msg = QueryMsg::SecretOrderBook {
             amm_pair_contract_addr: HumanAddr("ammpaircontract2".to_string()),
@@ -530,7 +554,8 @@ mod tests {
                             base_amount: Uint128(1000000000000000000),
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
-                            token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()})
+                            token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None
                         },
                         AssetInfo {
                             decimal_places: 18,
@@ -538,6 +563,7 @@ mod tests {
                             fee_amount: Uint128(1000000000000000000),
                             min_amount: Uint128(2000000000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         }
                     ])
             },
@@ -566,9 +592,11 @@ mod tests {
                         fee_amount: Uint128(500000000000000000),
                         min_amount: Uint128(1500000000000000),
                         token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                        native_token: None,
                     },
                     AssetInfo {
                         token: None,
+                        native_token: Some(NativeToken {denom:"uscrt".to_string()}),
                         base_amount: Uint128(1000000),
                         fee_amount: Uint128(500000),
                         min_amount: Uint128(1000000),
@@ -584,6 +612,7 @@ mod tests {
                             fee_amount: Uint128(500000000000000000),
                             min_amount: Uint128(1500000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         },
                         AssetInfo {
                             decimal_places: 18,
@@ -591,6 +620,7 @@ mod tests {
                             fee_amount: Uint128(1000000000000000000),
                             min_amount: Uint128(2000000000000000000),
                             token: Some(Token {contract_addr:HumanAddr("token3".to_string()),token_code_hash:"".to_string()}),
+                            native_token: None,
                         }
                     ]);
                 assert_eq!(secret_order_books[1].amm_pair_contract_addr, HumanAddr("ammpaircontract2".to_string()));
@@ -599,5 +629,197 @@ mod tests {
             _ => {}
         }
     }
+
+    #[test]
+    fn test_handle_set_order_book_contract_status() {
+        let (init_result, mut deps) = init_helper(
+            "123124".to_string(),
+            10,
+            "DFADFA123123".to_string(),
+            HumanAddr("ammfactoryaddress".to_string()),
+            "ammfactoryhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        for i in 1..3 {
+            let handle_msg = HandleMsg::InitCallBackFromSecretOrderBookToFactory {
+                auth_key:"TF9fujurR33f73E4II+o5cLzwuXBMVrT9kpapaqT8GM=".to_string(),
+                contract_address: HumanAddr(format!("contract{}", i)),
+                contract_hash: "xfadsf".to_string(),
+                token1_info: AssetInfo {
+                    decimal_places: 18,
+                    base_amount: Uint128(1000000000000000000),
+                    fee_amount: Uint128(500000000000000000),
+                    min_amount: Uint128(1500000000000000),
+                    token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                    native_token: None,
+                },
+                token2_info: AssetInfo {
+                    decimal_places: 18,
+                    base_amount: Uint128(1000000000000000000),
+                    fee_amount: Uint128(500000000000000000),
+                    min_amount: Uint128(1500000000000000),
+                    token: Some(Token {contract_addr:HumanAddr(format!("token{}", i + 1)),token_code_hash:"".to_string()}),
+                    native_token: None,
+                },
+                amm_pair_address: HumanAddr(format!("ammpaircontract{}", i)),
+            };
+            let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        let handle_msg = HandleMsg::SetOrderBookContractStatus {
+            amm_pairs_address: None,
+            status: ContractStatus::WithdrawOnly
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        // All registered books
+        let handle_result = handle(&mut deps, mock_env("admin", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 2);
+
+        // A single book
+        let handle_msg = HandleMsg::SetOrderBookContractStatus {
+            amm_pairs_address: Some(vec![HumanAddr("ammpaircontract2".to_string())]),
+            status: ContractStatus::Normal
+        };
+        let handle_result = handle(&mut deps, mock_env("admin", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("contract2".to_string()));
+            },
+            _ => panic!("Unexpected message")
+        }
+    }
+
+    #[test]
+    fn test_handle_migrate_order_book() {
+        let (init_result, mut deps) = init_helper(
+            "123124".to_string(),
+            10,
+            "DFADFA123123".to_string(),
+            HumanAddr("ammfactoryaddress".to_string()),
+            "ammfactoryhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let callback_msg = |contract_address: &str| HandleMsg::InitCallBackFromSecretOrderBookToFactory {
+            auth_key:"TF9fujurR33f73E4II+o5cLzwuXBMVrT9kpapaqT8GM=".to_string(),
+            contract_address: HumanAddr(contract_address.to_string()),
+            contract_hash: "xfadsf".to_string(),
+            token1_info: AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(Token {contract_addr:HumanAddr("token1".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
+            },
+            token2_info: AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(Token {contract_addr:HumanAddr("token2".to_string()),token_code_hash:"".to_string()}),
+                native_token: None,
+            },
+            amm_pair_address: HumanAddr("ammpaircontract1".to_string()),
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), callback_msg("contract1"));
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = HandleMsg::MigrateOrderBook {
+            amm_pair_address: HumanAddr("ammpaircontract1".to_string()),
+            amm_pair_hash: "ammpairhash".to_string()
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        let handle_result = handle(&mut deps, mock_env("admin", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        match &handle_result.unwrap().messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Instantiate { code_id, .. }) => {
+                assert_eq!(*code_id, 10);
+            },
+            _ => panic!("Unexpected message")
+        }
+
+        // Only one migration at a time per pair
+        let handle_result = handle(&mut deps, mock_env("admin", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        // The new book replaces the old one and the old one is told to start exporting
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), callback_msg("contract2"));
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("contract1".to_string()));
+            },
+            _ => panic!("Unexpected message")
+        }
+
+        let query_result = query(&deps, QueryMsg::SecretOrderBooks {
+            page: None,
+            page_size: None
+        });
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::SecretOrderBooks { secret_order_books } => {
+                assert_eq!(secret_order_books.len(), 1);
+                assert_eq!(secret_order_books[0].contract_addr, HumanAddr("contract2".to_string()));
+            },
+            _ => panic!("Unexpected query answer")
+        }
+
+        let query_result = query(&deps, QueryMsg::SecretOrderBook {
+            amm_pair_contract_addr: HumanAddr("ammpaircontract1".to_string()),
+        });
+        let query_answer: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match query_answer {
+            QueryAnswer::SecretOrderBook { secret_order_book } => {
+                assert_eq!(secret_order_book.unwrap().contract_addr, HumanAddr("contract2".to_string()));
+            },
+            _ => panic!("Unexpected query answer")
+        }
+    }
     
 }
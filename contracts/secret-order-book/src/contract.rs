@@ -1,20 +1,71 @@
-use cosmwasm_std::{Api, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Decimal, Empty, Env, Extern, HandleResponse, HumanAddr, InitResponse, LogAttribute, Querier, QueryResult, StdError, StdResult, Storage, Uint128, WasmMsg, from_binary, to_binary};
+use std::collections::BTreeMap;
+use cosmwasm_std::{Api, BankMsg, Binary, BlockInfo, CanonicalAddr, Coin, CosmosMsg, Decimal, Empty, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier, QueryResult, ReadonlyStorage, StdError, StdResult, Storage, Uint128, WasmMsg, from_binary, log, to_binary};
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
-use secret_toolkit::{snip20, storage::{AppendStore, AppendStoreMut}, utils::{HandleCallback, Query}};
+use serde::Serialize;
+use secret_toolkit::{snip20, storage::{AppendStore, AppendStoreMut}, utils::{HandleCallback, Query, pad_handle_result, pad_query_result, space_pad}};
 use secret_toolkit::snip20::transfer_msg;
-use crate::{msg::{AmmAssetInfo, AmmPairReverseSimulationResponse, AmmPairSimulationResponse, AmmSimulationOfferAsset, AmmSimulationQuery, AssetInfo, FactoryHandleMsg, FactoryQueryMsg, HandleAnswer, HandleMsg, InitMsg, IsKeyValidResponse, LimitOrderState, QueryAnswer, QueryMsg, ResponseStatus, Snip20Msg, UserOrderMap}, state::{load, may_load, remove, save}};
-use crate::order_queues::OrderQueue;
+use crate::{msg::{BASIS_POINTS, AmmAssetInfo, AmmPairHandleMsg, AmmPairReceiveMsg, AmmPairReverseSimulationResponse, AmmPairSimulationResponse, AmmSimulationOfferAsset, AmmSimulationQuery, AssetInfo, ContractStatus, DepthBand, DepthConfig, Expiration, FeeSchedule, FeeSource, FactoryHandleMsg, FactoryQueryMsg, HandleAnswer, HandleMsg, InitMsg, IsKeyValidResponse, LimitOrderAmendment, LimitOrderState, MigratedConfig, MigratedHistory, MigratedKeeperRewards, MigratedLimitOrder, MigrationContract, MigrationHandleMsg, OrderEvent, OrderEventAction, OrderStatus, OrderType, Price, QueryAnswer, QueryMsg, ResponseStatus, Snip20Msg, Token, TriggerCandidate, UserOrderMap}, state::{load, may_load, remove, save}};
+use crate::order_queues::{ASK_ORDER_QUEUE, BID_ORDER_QUEUE, OrderIndex, OrderQueue, PendingSwap, STOP_ASK_ORDER_QUEUE, STOP_BID_ORDER_QUEUE, order_queue_key};
 pub const FACTORY_DATA: &[u8] = b"factory";
 pub const AMM_PAIR_DATA: &[u8] = b"ammpair";
 pub const TOKEN1_DATA: &[u8] = b"token1";
 pub const TOKEN2_DATA: &[u8] = b"token2";
 pub const HISTORY_LIMIT_ORDERS: &[u8] = b"historylimitorders";
 pub const ACTIVE_LIMIT_ORDERS: &[u8] = b"activelimitorders";
-pub const BID_ORDER_QUEUE: &[u8] = b"bidordequeue";
-pub const ASK_ORDER_QUEUE: &[u8] = b"askorderqueue";
-pub const SWAPPED_LIMIT_ORDER: &[u8] = b"swappedlimitorder";
-pub const SWAPPED_TRIGGER_ADDRESS: &[u8] = b"swappedtriggeraddress";
+pub const USER_ORDERS: &[u8] = b"userorders";
+pub const LAST_ORDER_ID: &[u8] = b"lastorderid";
+pub const LAST_SEQUENCE: &[u8] = b"lastsequence";
+pub const PENDING_SWAPS: &[u8] = b"pendingswaps";
+pub const DEPTH_CONFIG: &[u8] = b"depthconfig";
+pub const SCAN_LIMIT: &[u8] = b"scanlimit";
+pub const BID_FEE_SCHEDULE: &[u8] = b"bidfeeschedule";
+pub const ASK_FEE_SCHEDULE: &[u8] = b"askfeeschedule";
+pub const KEEPER_REWARDS: &[u8] = b"keeperrewards";
+pub const KEEPERS: &[u8] = b"keepers";
+pub const PROTOCOL_FEE: &[u8] = b"protocolfee";
+pub const TREASURY: &[u8] = b"treasury";
+pub const CONTRACT_STATUS: &[u8] = b"contractstatus";
+pub const STATUS_AFTER_MIGRATION: &[u8] = b"statusaftermigration";
+pub const MIGRATION_SOURCE: &[u8] = b"migrationsource";
+pub const ORDER_EVENTS: &[u8] = b"orderevents";
+pub const MIGRATION_TARGET: &[u8] = b"migrationtarget";
+pub const ORDERS_EXPORTED: &[u8] = b"ordersexported";
+pub const HISTORY_EXPORTED: &[u8] = b"historyexported";
+pub const EVENTS_EXPORTED: &[u8] = b"eventsexported";
+pub const CONFIG_EXPORTED: &[u8] = b"configexported";
+pub const KEEPERS_EXPORTED: &[u8] = b"keepersexported";
 pub const BLOCK_SIZE: usize = 256;
+// Number of halvings used to search the largest slice of an order that still fills at its price
+pub const PARTIAL_FILL_SEARCH_STEPS: u8 = 8;
+// Order books in the order they are checked for triggers
+pub const ORDER_BOOKS: [(bool, OrderType); 4] = [
+    (true, OrderType::Limit),
+    (false, OrderType::Limit),
+    (true, OrderType::Stop),
+    (false, OrderType::Stop),
+];
+// Most resting orders a new limit order is matched against
+pub const MAX_MATCHED_ORDERS: u32 = 10;
+// Most orders a single TriggerLimitOrders can swap
+pub const MAX_ORDERS_PER_TRIGGER: u32 = 10;
+// Blocks after which a swap that was not paid back by the pair can be unlocked
+pub const SWAP_TIMEOUT_BLOCKS: u64 = 10;
+// Expired orders refunded by a trigger before it looks for an order to swap
+pub const EXPIRED_ORDERS_PER_TRIGGER: u32 = 5;
+// Expired orders refunded by PruneExpiredOrders when no limit is given
+pub const DEFAULT_PRUNE_LIMIT: u32 = 20;
+// Orders get_limit_order_to_trigger simulates per search when no scan limit is set
+pub const DEFAULT_SCAN_LIMIT: u32 = 20;
+// Highest scan limit the factory can set
+pub const MAX_SCAN_LIMIT: u32 = 100;
+// Fewest orders a band of the OrderBookDepth query can be shown with
+pub const MIN_ORDERS_PER_DEPTH_BAND: u32 = 2;
+// Highest protocol fee the factory can set, in basis points of the filled amount
+pub const MAX_PROTOCOL_FEE_BASIS_POINTS: u16 = 1000;
+// Orders or history orders moved per export when no limit is given
+pub const DEFAULT_EXPORT_LIMIT: u32 = 20;
+// Most orders or history orders moved per export
+pub const MAX_EXPORT_LIMIT: u32 = 50;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
@@ -29,23 +80,35 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     save(&mut deps.storage, TOKEN1_DATA, &msg.token1_info)?;
     save(&mut deps.storage, TOKEN2_DATA, &msg.token2_info)?;
 
-    save(&mut deps.storage, BID_ORDER_QUEUE, &OrderQueue::new(true))?;
-    save(&mut deps.storage, ASK_ORDER_QUEUE, &OrderQueue::new(false))?;
+    save(&mut deps.storage, BID_ORDER_QUEUE, &OrderQueue::new(true, OrderType::Limit))?;
+    save(&mut deps.storage, ASK_ORDER_QUEUE, &OrderQueue::new(false, OrderType::Limit))?;
+    save(&mut deps.storage, STOP_BID_ORDER_QUEUE, &OrderQueue::new(true, OrderType::Stop))?;
+    save(&mut deps.storage, STOP_ASK_ORDER_QUEUE, &OrderQueue::new(false, OrderType::Stop))?;
+    save(&mut deps.storage, LAST_ORDER_ID, &0u64)?;
+    save(&mut deps.storage, LAST_SEQUENCE, &0u64)?;
+    match msg.migrate_from.clone() {
+        Some(migrate_from) => {
+            // Nothing can be placed until the orders of the old order book are imported
+            save(&mut deps.storage, MIGRATION_SOURCE, &migrate_from)?;
+            save(&mut deps.storage, CONTRACT_STATUS, &ContractStatus::Migrating)?;
+        },
+        None => save(&mut deps.storage, CONTRACT_STATUS, &ContractStatus::Normal)?
+    }
 
     let mut amm_pair_data = PrefixedStorage::new(AMM_PAIR_DATA, &mut deps.storage);
     save(&mut amm_pair_data, b"address", &msg.amm_pair_contract_address)?;
     save(&mut amm_pair_data, b"hash", &msg.amm_pair_contract_hash)?;
 
     // send register to snip20
-    let snip20_register_msg = to_binary(&Snip20Msg::register_receive(env.clone().contract_code_hash))?;
+    let snip20_register_msg = padded_binary(&Snip20Msg::register_receive(env.clone().contract_code_hash))?;
     
     let mut token1_response: Option<CosmosMsg> = None;
     let mut token2_response: Option<CosmosMsg> = None;
 
-    // NO NATIVE TOKENS FOR NOW
+    // Native tokens are held through their SNIP-20 wrapper, so both sides need a token contract
     if msg.token1_info.clone().token == None || msg.token2_info.clone().token == None {
         return Err(StdError::generic_err(
-            "Native token not supported for now...",
+            "Native token needs its wrapper token contract!",
         ));
     }
 
@@ -93,19 +156,71 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    match msg {
+    check_contract_status(&deps.storage, &msg)?;
+
+    // Answers are padded so their size does not tell which action was taken
+    let response = match msg {
         // Receiver to CreateLimitOrder from SNIP20
         HandleMsg::Receive { sender, from, amount, msg } => try_receive(deps, env, sender, from, amount, msg),
         // Receiver to CreateLimitOrder from SCRT
-        HandleMsg::CancelLimitOrder {} => try_cancel_limit_order(deps, env), 
-        HandleMsg::TriggerLimitOrders {} => try_trigger_limit_orders(deps, env), 
+        HandleMsg::ReceiveNativeToken { msg } => try_receive_native_token(deps, env, msg),
+        HandleMsg::CancelLimitOrder { order_id } => try_cancel_limit_order(deps, env, order_id), 
+        HandleMsg::UpdateLimitOrder { order_id, price, expected_amount } => try_update_limit_order(deps, env, order_id, price, expected_amount), 
+        HandleMsg::TriggerLimitOrders { max_orders } => try_trigger_limit_orders(deps, env, max_orders), 
+        HandleMsg::PruneExpiredOrders { limit } => try_prune_expired_orders(deps, env, limit), 
+        HandleMsg::UnlockTimedOutSwaps {} => try_unlock_timed_out_swaps(deps, env), 
+        HandleMsg::SettleNativeSwap {} => try_settle_native_swap(deps, env),
         HandleMsg::ChangeFee {
             token_index,
             min_amount,
             fee_amount
         } => try_change_fee(deps, env, token_index, min_amount, fee_amount),
+        HandleMsg::ChangeDepthConfig { depth_config } => try_change_depth_config(deps, env, depth_config),
+        HandleMsg::ChangeScanLimit { scan_limit } => try_change_scan_limit(deps, env, scan_limit),
+        HandleMsg::ChangeFeeSchedule { is_bid, fee_schedule } => try_change_fee_schedule(deps, env, is_bid, fee_schedule),
+        HandleMsg::ClaimKeeperRewards {} => try_claim_keeper_rewards(deps, env),
+        HandleMsg::ChangeProtocolFee { basis_points } => try_change_protocol_fee(deps, env, basis_points),
+        HandleMsg::WithdrawTreasury { recipient } => try_withdraw_treasury(deps, env, recipient),
+        HandleMsg::SetContractStatus { status } => try_set_contract_status(deps, env, status),
+        HandleMsg::StartMigration { contract_address, contract_hash } => try_start_migration(deps, env, contract_address, contract_hash),
+        HandleMsg::ExportLimitOrders { limit } => try_export_limit_orders(deps, env, limit),
+        HandleMsg::ExportHistoryLimitOrders { owners, limit } => try_export_history_limit_orders(deps, env, owners, limit),
+        HandleMsg::ExportKeeperRewards { limit } => try_export_keeper_rewards(deps, env, limit),
+        HandleMsg::ImportLimitOrders {
+            limit_orders,
+            last_order_id,
+            last_sequence,
+            treasury,
+            config,
+            finished
+        } => try_import_limit_orders(deps, env, limit_orders, last_order_id, last_sequence, treasury, config, finished),
+        HandleMsg::ImportHistoryLimitOrders { histories } => try_import_history_limit_orders(deps, env, histories),
+        HandleMsg::ImportKeeperRewards { keeper_rewards } => try_import_keeper_rewards(deps, env, keeper_rewards),
         _ => Err(StdError::generic_err("Handler not found!"))
-    } 
+    };
+    pad_handle_result(response, BLOCK_SIZE)
+}
+
+// Canceling, refunds and the settlement of swaps already sent are always allowed
+fn check_contract_status<S: ReadonlyStorage>(storage: &S, msg: &HandleMsg) -> StdResult<()> {
+    let status: ContractStatus = may_load(storage, CONTRACT_STATUS)?.unwrap_or(ContractStatus::Normal);
+    let is_allowed = match status {
+        ContractStatus::Normal => true,
+        ContractStatus::StopTriggering => !matches!(msg, HandleMsg::TriggerLimitOrders { .. }),
+        ContractStatus::WithdrawOnly | ContractStatus::Migrating => !matches!(msg,
+            HandleMsg::TriggerLimitOrders { .. }
+            | HandleMsg::Receive { msg: Some(_), .. }
+            | HandleMsg::ReceiveNativeToken { .. }
+            | HandleMsg::UpdateLimitOrder { .. }
+        ),
+    };
+    if !is_allowed {
+        return Err(StdError::generic_err(format!(
+            "This action is disabled while the order book is in {:?} mode!",
+            status
+        )));
+    }
+    Ok(())
 }
 
 pub fn try_receive<S: Storage, A: Api, Q: Querier>(
@@ -117,32 +232,14 @@ pub fn try_receive<S: Storage, A: Api, Q: Querier>(
     msg: Option<Binary>,
 ) -> StdResult<HandleResponse> {
     if msg != None {
-        let msg: HandleMsg = from_binary(&msg.unwrap())?;
-
-        if matches!(msg, HandleMsg::Receive { .. }) {
-            return Err(StdError::generic_err(
-                "Recursive call to receive() is not allowed",
-            ));
-        }
-    
-        if let HandleMsg::CreateLimitOrder {is_bid, price, expected_amount} = msg.clone() {
-            let (deposit_token_index,balances,deposit_amount) = prepare_create_limit_order(deps,env.clone(),amount);
-            if deposit_token_index == None {
-                return Err(StdError::generic_err(format!(
-                    "Invalid Token or Amount Sent < Minimum Amount"
-                )));
-            }
-            return create_limit_order(deps, env.clone(), balances, deposit_token_index.unwrap(), deposit_amount, expected_amount, from, is_bid, price)
-        } else {
-                return Err(StdError::generic_err(format!(
-                    "Receive handler not found!"
-                )));
-        }
+        let deposit_token_address = env.message.sender.clone();
+        return receive_deposit(deps, env, deposit_token_address, from, amount, msg.unwrap());
     } else {
         let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
         let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address").unwrap();
         if from == amm_pair_address {
-            return swap_callback(deps, env.clone(), amount);
+            let received_token_address = env.message.sender.clone();
+            return swap_callback(deps, env, received_token_address, amount);
         } else {
             return Err(StdError::generic_err(format!(
                 "Receive handler not found!"
@@ -152,9 +249,82 @@ pub fn try_receive<S: Storage, A: Api, Q: Querier>(
     
 }
 
-fn prepare_create_limit_order<S: Storage, A: Api, Q: Querier>(
+pub fn try_receive_native_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: Binary,
+) -> StdResult<HandleResponse> {
+    let native_token_info: AssetInfo = load_native_token_info(&deps.storage)?;
+    let denom: String = native_token_info.native_token.clone().unwrap().denom;
+
+    if env.message.sent_funds.len() != 1 || env.message.sent_funds[0].denom != denom {
+        return Err(StdError::generic_err(format!(
+            "Only {} can be sent!",
+            denom
+        )));
+    }
+    let amount: Uint128 = env.message.sent_funds[0].amount;
+
+    // Wrap the deposit first, the order is then handled as if it was received in the wrapper token
+    let wrapper_token = native_token_info.token.unwrap();
+    let deposit_msg = deposit_native_msg(&wrapper_token, env.message.sent_funds.clone())?;
+
+    let from = env.message.sender.clone();
+    let mut response = receive_deposit(deps, env, wrapper_token.contract_addr, from, amount, msg)?;
+    response.messages.insert(0, deposit_msg);
+    Ok(response)
+}
+
+fn receive_deposit<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    deposit_token_address: HumanAddr,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Binary,
+) -> StdResult<HandleResponse> {
+    let msg: HandleMsg = from_binary(&msg)?;
+
+    if matches!(msg, HandleMsg::Receive { .. } | HandleMsg::ReceiveNativeToken { .. }) {
+        return Err(StdError::generic_err(
+            "Recursive call to receive() is not allowed",
+        ));
+    }
+
+    if let HandleMsg::CreateLimitOrder {is_bid, price, expected_amount, order_type, expires_at} = msg.clone() {
+        let (deposit_token_index,balances,deposit_amount) = prepare_create_limit_order(deps,&deposit_token_address,amount);
+        if deposit_token_index == None {
+            return Err(StdError::generic_err(format!(
+                "Invalid Token or Amount Sent < Minimum Amount"
+            )));
+        }
+        return create_limit_order(deps, env.clone(), balances, deposit_token_index.unwrap(), deposit_amount, expected_amount, from, is_bid, price, order_type.unwrap_or(OrderType::Limit), expires_at)
+    } else if let HandleMsg::CreateOcoOrder {is_bid, limit_price, limit_expected_amount, stop_price, stop_expected_amount, expires_at} = msg.clone() {
+        let (deposit_token_index,balances,deposit_amount) = prepare_create_limit_order(deps,&deposit_token_address,amount);
+        if deposit_token_index == None {
+            return Err(StdError::generic_err(format!(
+                "Invalid Token or Amount Sent < Minimum Amount"
+            )));
+        }
+        return create_oco_order(deps, env.clone(), balances, deposit_token_index.unwrap(), deposit_amount, from, is_bid, limit_price, limit_expected_amount, stop_price, stop_expected_amount, expires_at)
+    } else if let HandleMsg::IncreaseLimitOrder {order_id} = msg.clone() {
+        let (deposit_token_index,_balances,deposit_amount) = prepare_create_limit_order(deps,&deposit_token_address,amount);
+        if deposit_token_index == None {
+            return Err(StdError::generic_err(format!(
+                "Invalid Token or Amount Sent < Minimum Amount"
+            )));
+        }
+        return increase_limit_order(deps, env.clone(), deposit_token_index.unwrap(), deposit_amount, from, order_id)
+    } else {
+        return Err(StdError::generic_err(format!(
+            "Receive handler not found!"
+        )));
+    }
+}
+
+fn prepare_create_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    deposit_token_address: &HumanAddr,
     amount:Uint128
 ) -> (
     Option<i8>,
@@ -168,12 +338,12 @@ fn prepare_create_limit_order<S: Storage, A: Api, Q: Querier>(
     let mut balances = vec![Uint128(0), Uint128(0)];
     let deposit_amount: Uint128 = amount;
 
-    if token1_info.token.unwrap().contract_addr == env.message.sender {
+    if token1_info.token.unwrap().contract_addr == *deposit_token_address {
         balances[0] = amount;
         deposit_token_index = Some(0);
     };
 
-    if token2_info.token.unwrap().contract_addr == env.message.sender {
+    if token2_info.token.unwrap().contract_addr == *deposit_token_address {
         balances[1] = amount;
         deposit_token_index = Some(1);
     }
@@ -190,49 +360,248 @@ pub fn create_limit_order<S: Storage, A: Api, Q: Querier>(
     expected_amount: Uint128,
     from: HumanAddr,
     is_bid: bool,
-    price: Uint128
+    price: Price,
+    order_type: OrderType,
+    expires_at: Option<Expiration>
 ) -> StdResult<HandleResponse> {
-    let token1_info: AssetInfo = load(&deps.storage, TOKEN1_DATA).unwrap();
-    let token2_info: AssetInfo = load(&deps.storage, TOKEN2_DATA).unwrap();
-    
     // Create new user limit order
     let user_address = deps.api.canonical_address(&from)?;
 
-    // check if this user already has a limit order here
-    let active_limit_orders = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &deps.storage);
-    let limit_order_data: Option<LimitOrderState> = may_load(&active_limit_orders, user_address.as_slice())?;
-    if limit_order_data != None {
-        return Err(StdError::generic_err(format!(
-            "User already has a limit order for this pair. To create a new one withdraw the other one!"
-        ))); 
-    }
+    let limit_order = new_limit_order(deps, &env, balances, deposit_token_index, deposit_amount, expected_amount, is_bid, price, order_type, expires_at)?;
+    let sequence = next_sequence(&mut deps.storage)?;
+    record_order_event(&mut deps.storage, &env, &user_address, OrderEventAction::Create, &limit_order, limit_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
 
-    // check if valid price and quantity
-    let fee_amount: Uint128;
-    let mut min_deposit_amount: Uint128 = Uint128(0);
-    let mut min_expected_amount: Uint128 = Uint128(0);
-    if is_bid == true {
-        min_deposit_amount = token2_info.min_amount;
-        min_expected_amount = token1_info.min_amount;
-        fee_amount = token1_info.fee_amount;
+    // Cross the order with the other side of the book first, only the remainder rests on it
+    let (limit_order, messages) = match_limit_order(deps, &env, &from, limit_order)?;
+    if limit_order.balances[limit_order.deposit_token_index as usize] > Uint128(0) {
+        add_limit_order(deps, &from, &user_address, &limit_order, sequence)?;
     } else {
-        min_deposit_amount = token1_info.min_amount;
-        min_expected_amount = token2_info.min_amount;
-        fee_amount = token2_info.fee_amount;
+        retire_limit_order(&mut deps.storage, &user_address, &limit_order)?;
     }
-    if deposit_amount < min_deposit_amount || expected_amount < min_expected_amount || price <= Uint128(0) {
-        return Err(StdError::generic_err(format!(
-            "Bad Amount or Price! 
-            {} < {} || {} < {} || {} <= {}",
-            deposit_amount,
-            min_deposit_amount,
-            expected_amount,
-            min_expected_amount,
-            price,
-            Uint128(0)
-        ))); 
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CreateLimitOrder {
+            status: ResponseStatus::Success,
+            order_id: limit_order.id,
+        })?),
+    })
+}
+
+pub fn create_oco_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    balances: Vec<Uint128>,
+    deposit_token_index: i8,
+    deposit_amount: Uint128,
+    from: HumanAddr,
+    is_bid: bool,
+    limit_price: Price,
+    limit_expected_amount: Uint128,
+    stop_price: Price,
+    stop_expected_amount: Uint128,
+    expires_at: Option<Expiration>
+) -> StdResult<HandleResponse> {
+    let user_address = deps.api.canonical_address(&from)?;
+
+    // Both legs hold the whole deposit, whichever one fills first retires the other.
+    // They are not matched on creation but new orders can still match the limit leg.
+    let mut limit_order = new_limit_order(deps, &env, balances.clone(), deposit_token_index, deposit_amount, limit_expected_amount, is_bid, limit_price, OrderType::Limit, expires_at.clone())?;
+    let mut stop_order = new_limit_order(deps, &env, balances, deposit_token_index, deposit_amount, stop_expected_amount, is_bid, stop_price, OrderType::Stop, expires_at)?;
+    limit_order.linked_order_id = Some(stop_order.id);
+    stop_order.linked_order_id = Some(limit_order.id);
+
+    let limit_sequence = next_sequence(&mut deps.storage)?;
+    let stop_sequence = next_sequence(&mut deps.storage)?;
+    add_limit_order(deps, &from, &user_address, &limit_order, limit_sequence)?;
+    add_limit_order(deps, &from, &user_address, &stop_order, stop_sequence)?;
+    record_order_event(&mut deps.storage, &env, &user_address, OrderEventAction::Create, &limit_order, limit_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
+    record_order_event(&mut deps.storage, &env, &user_address, OrderEventAction::Create, &stop_order, stop_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CreateOcoOrder {
+            status: ResponseStatus::Success,
+            limit_order_id: limit_order.id,
+            stop_order_id: stop_order.id,
+        })?),
+    })
+}
+
+// Settles a new limit order against the resting limit orders it crosses, at their price.
+// Tokens are transferred directly, no swap goes through the pair.
+fn match_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    owner: &HumanAddr,
+    limit_order: LimitOrderState
+) -> StdResult<(LimitOrderState, Vec<CosmosMsg>)> {
+    let mut incoming_order = limit_order;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if incoming_order.order_type != OrderType::Limit {
+        return Ok((incoming_order, messages));
+    }
+
+    let token1_info: AssetInfo = load(&deps.storage, TOKEN1_DATA)?;
+    let token2_info: AssetInfo = load(&deps.storage, TOKEN2_DATA)?;
+    let resting_order_book: OrderQueue = load(&deps.storage, order_queue_key(!incoming_order.is_bid, &OrderType::Limit))?;
+    // Read up front as matching takes filled orders off the queue
+    let resting_order_indexes: Vec<OrderIndex> = resting_order_book.orders(&deps.storage)
+        .take(MAX_MATCHED_ORDERS as usize)
+        .collect::<StdResult<Vec<OrderIndex>>>()?;
+    let incoming_deposit_index = incoming_order.deposit_token_index as usize;
+    let mut incoming_offered_amount = Uint128(0);
+    let mut incoming_received_amount = Uint128(0);
+
+    for resting_order_index in resting_order_indexes {
+        if resting_order_index.expires_at.as_ref().map_or(false, |expires_at| expires_at.is_expired(&env.block)) {
+            continue;
+        }
+        // A bid crosses asks at or below its price, an ask crosses bids at or above it
+        let is_crossing: bool;
+        if incoming_order.is_bid {
+            is_crossing = incoming_order.price >= resting_order_index.price;
+        } else {
+            is_crossing = incoming_order.price <= resting_order_index.price;
+        }
+        if !is_crossing {
+            break;
+        }
+
+        let resting_owner_canonical = deps.api.canonical_address(&resting_order_index.owner)?;
+        let resting_order: LimitOrderState = match load_active_limit_order(&deps.storage, &resting_owner_canonical, resting_order_index.id)? {
+            Some(resting_order) => resting_order,
+            None => continue
+        };
+        if resting_order.status == OrderStatus::Executing
+            || is_linked_order_executing(&deps.storage, &resting_owner_canonical, &resting_order)? {
+            continue;
+        }
+
+        // Trade at the resting order price (token2 per base amount of token1)
+        let (bid_order, ask_order) = if incoming_order.is_bid { (&incoming_order, &resting_order) } else { (&resting_order, &incoming_order) };
+        let resting_price: Uint128 = resting_order.price.to_base_amount(&token1_info, &token2_info)?;
+        let token1_amount = std::cmp::min(
+            ask_order.balances[0],
+            bid_order.balances[1].multiply_ratio(token1_info.base_amount, resting_price)
+        );
+        let token2_amount = token1_amount.multiply_ratio(resting_price, token1_info.base_amount);
+
+        // Both sides must get at least their expected amount for the part of the deposit they give
+        if token1_amount == Uint128(0) || token2_amount == Uint128(0)
+            || token2_amount < ask_order.expected_amount.multiply_ratio(token1_amount, ask_order.deposit_amount)
+            || token1_amount < bid_order.expected_amount.multiply_ratio(token2_amount, bid_order.deposit_amount) {
+            // Rounding can leave a single resting order short, the next ones may still fill
+            continue;
+        }
+
+        let resting_received_token: &AssetInfo;
+        let (incoming_offer_amount, incoming_received) = if incoming_order.is_bid {
+            resting_received_token = &token2_info;
+            (token2_amount, token1_amount)
+        } else {
+            resting_received_token = &token1_info;
+            (token1_amount, token2_amount)
+        };
+        let (resting_offer_amount, resting_received) = (incoming_received, incoming_offer_amount);
+
+        messages.extend(payout_msgs(resting_received_token, &env.contract.address, &resting_order_index.owner, resting_received)?);
+        let mut resting_amounts = vec![Uint128(0),Uint128(0)];
+        resting_amounts[resting_order.deposit_token_index as usize] = resting_offer_amount;
+        resting_amounts[incoming_deposit_index] = resting_received;
+        record_order_event(&mut deps.storage, env, &resting_owner_canonical, OrderEventAction::Fill, &resting_order, resting_amounts, vec![Uint128(0),Uint128(0)])?;
+        fill_limit_order(&mut deps.storage, &resting_owner_canonical, resting_order, resting_offer_amount, resting_received)?;
+
+        // What is left of the resting order may be too small to trade, then it is refunded and the order closed
+        if let Some(mut resting_order) = load_active_limit_order(&deps.storage, &resting_owner_canonical, resting_order_index.id)? {
+            messages.extend(refund_dust_remainder(&deps.storage, &env.contract.address, &resting_order_index.owner, &mut resting_order)?);
+            if resting_order.balances[resting_order.deposit_token_index as usize] == Uint128(0) {
+                set_order_status(&mut resting_order, OrderStatus::Filled)?;
+                retire_limit_order(&mut deps.storage, &resting_owner_canonical, &resting_order)?;
+                // The refunded deposit was shared with the other leg
+                if let Some(linked_order_id) = resting_order.linked_order_id {
+                    if let Some(mut linked_limit_order) = load_active_limit_order(&deps.storage, &resting_owner_canonical, linked_order_id)? {
+                        set_order_status(&mut linked_limit_order, OrderStatus::Canceled)?;
+                        linked_limit_order.balances = resting_order.balances.clone();
+                        linked_limit_order.withdrew_balance = resting_order.withdrew_balance.clone();
+                        retire_limit_order(&mut deps.storage, &resting_owner_canonical, &linked_limit_order)?;
+                    }
+                }
+            }
+        }
+
+        let mut received_balance = vec![Uint128(0),Uint128(0)];
+        received_balance[1 - incoming_deposit_index] = incoming_received;
+        incoming_order.balances[incoming_deposit_index] = (incoming_order.balances[incoming_deposit_index] - incoming_offer_amount)?;
+        incoming_order.withdrew_balance = Some(add_balances(incoming_order.withdrew_balance.clone(), &received_balance));
+        incoming_offered_amount = incoming_offered_amount + incoming_offer_amount;
+        incoming_received_amount = incoming_received_amount + incoming_received;
+
+        if incoming_order.balances[incoming_deposit_index] == Uint128(0) {
+            break;
+        }
+    }
+
+    if incoming_received_amount > Uint128(0) {
+        let incoming_received_token: &AssetInfo = if incoming_order.is_bid { &token1_info } else { &token2_info };
+        messages.extend(payout_msgs(incoming_received_token, &env.contract.address, owner, incoming_received_amount)?);
+        // A remainder too small to rest on the book is refunded with the proceeds
+        messages.extend(refund_dust_remainder(&deps.storage, &env.contract.address, owner, &mut incoming_order)?);
+        if incoming_order.balances[incoming_deposit_index] > Uint128(0) {
+            set_order_status(&mut incoming_order, OrderStatus::PartiallyFilled)?;
+        } else {
+            set_order_status(&mut incoming_order, OrderStatus::Filled)?;
+        }
+        let mut incoming_amounts = vec![Uint128(0),Uint128(0)];
+        incoming_amounts[incoming_deposit_index] = incoming_offered_amount;
+        incoming_amounts[1 - incoming_deposit_index] = incoming_received_amount;
+        let owner_canonical = deps.api.canonical_address(owner)?;
+        record_order_event(&mut deps.storage, env, &owner_canonical, OrderEventAction::Fill, &incoming_order, incoming_amounts, vec![Uint128(0),Uint128(0)])?;
+    }
+
+    Ok((incoming_order, messages))
+}
+
+// Refunds the remaining deposit of an order once it is below the minimum amount of its token.
+// The caller closes the order, its balances are left at zero.
+fn refund_dust_remainder<S: ReadonlyStorage>(
+    storage: &S,
+    contract_address: &HumanAddr,
+    owner: &HumanAddr,
+    limit_order: &mut LimitOrderState
+) -> StdResult<Vec<CosmosMsg>> {
+    let deposit_token_index = limit_order.deposit_token_index as usize;
+    let deposit_token_info: AssetInfo = load(storage, if deposit_token_index == 0 { TOKEN1_DATA } else { TOKEN2_DATA })?;
+    let remainder: Uint128 = limit_order.balances[deposit_token_index];
+    if remainder == Uint128(0) || remainder >= deposit_token_info.min_amount {
+        return Ok(vec![]);
     }
 
+    let messages: Vec<CosmosMsg> = refund_balances(storage, contract_address, owner, &limit_order.balances)?;
+    limit_order.withdrew_balance = Some(add_balances(limit_order.withdrew_balance.clone(), &limit_order.balances));
+    limit_order.balances = vec![Uint128(0),Uint128(0)];
+    Ok(messages)
+}
+
+fn new_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    balances: Vec<Uint128>,
+    deposit_token_index: i8,
+    deposit_amount: Uint128,
+    expected_amount: Uint128,
+    is_bid: bool,
+    price: Price,
+    order_type: OrderType,
+    expires_at: Option<Expiration>
+) -> StdResult<LimitOrderState> {
+    // check if valid price and quantity
+    validate_limit_order_amounts(&deps.storage, is_bid, deposit_amount, expected_amount, price)?;
+    // Later fee changes do not apply to the order
+    let fee_schedule: FeeSchedule = load_fee_schedule(&deps.storage, is_bid)?;
+
     // check if correct flag on is_bid!
     // is_bid = true => sell token 2 for token 1
     // is_bid = false =>  sell token 1 for token 2
@@ -242,151 +611,194 @@ pub fn create_limit_order<S: Storage, A: Api, Q: Querier>(
         ))); 
     }
 
+    if let Some(expires_at) = expires_at.clone() {
+        if expires_at.is_expired(&env.block) {
+            return Err(StdError::generic_err(format!(
+                "Order would already be expired!"
+            )));
+        }
+    }
+
+    let order_id: u64 = next_order_id(&mut deps.storage)?;
+
     //Create Limit order
-    let limit_order = LimitOrderState {
+    Ok(LimitOrderState {
+        id: order_id,
+        order_type,
+        linked_order_id: None,
         is_bid,
-        status: "Active".to_string(),
+        status: OrderStatus::Active,
         price,
         deposit_token_index,
         deposit_amount,
         expected_amount,
-        fee_amount,
+        fee_schedule,
         timestamp: env.block.time,
         balances,
-        withdrew_balance: None
-    };
-    
-    let mut key_store = PrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &mut deps.storage);
-    save(&mut key_store, &user_address.as_slice(), &limit_order)?;
+        withdrew_balance: None,
+        expires_at,
+        amendments: vec![]
+    })
+}
 
-    // Update Order Book
-    if is_bid {
-        let mut bid_order_book:OrderQueue = load(&deps.storage, BID_ORDER_QUEUE).unwrap();
-        bid_order_book.insert(
-            from.clone(),
-            price,
-            env.block.time
-        );
-        save(&mut deps.storage, BID_ORDER_QUEUE, &bid_order_book)?;
+fn validate_limit_order_amounts<S: ReadonlyStorage>(
+    storage: &S,
+    is_bid: bool,
+    deposit_amount: Uint128,
+    expected_amount: Uint128,
+    price: Price
+) -> StdResult<()> {
+    let token1_info: AssetInfo = load(storage, TOKEN1_DATA)?;
+    let token2_info: AssetInfo = load(storage, TOKEN2_DATA)?;
+
+    let min_deposit_amount: Uint128;
+    let min_expected_amount: Uint128;
+    if is_bid == true {
+        min_deposit_amount = token2_info.min_amount;
+        min_expected_amount = token1_info.min_amount;
     } else {
-        let mut ask_order_book:OrderQueue = load(&deps.storage, ASK_ORDER_QUEUE).unwrap();
-        ask_order_book.insert(
-            from.clone(),
+        min_deposit_amount = token1_info.min_amount;
+        min_expected_amount = token2_info.min_amount;
+    }
+    if deposit_amount < min_deposit_amount || expected_amount < min_expected_amount || price.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Bad Amount or Price! 
+            {} < {} || {} < {} || {} <= {}",
+            deposit_amount,
+            min_deposit_amount,
+            expected_amount,
+            min_expected_amount,
             price,
-            env.block.time
-        );
-        save(&mut deps.storage, ASK_ORDER_QUEUE, &ask_order_book)?;
+            0
+        ))); 
     }
+    // The price must be payable in whole units of token2
+    price.to_base_amount(&token1_info, &token2_info)?;
+    Ok(())
+}
 
-    Ok(HandleResponse {
-        messages: vec![],
-        log: vec![],
-        data: Some(to_binary(&HandleAnswer::Status {
-            status: ResponseStatus::Success,
-            message: None,
-        })?),
-    })
+fn add_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    owner: &HumanAddr,
+    owner_canonical: &CanonicalAddr,
+    limit_order: &LimitOrderState,
+    sequence: u64
+) -> StdResult<()> {
+    save_active_limit_order(&mut deps.storage, owner_canonical, limit_order)?;
+
+    // Update Order Book
+    let queue_key = order_queue_key(limit_order.is_bid, &limit_order.order_type);
+    let mut order_book: OrderQueue = load(&deps.storage, queue_key)?;
+    order_book.insert(
+        &mut deps.storage,
+        limit_order.id,
+        owner.clone(),
+        limit_order.price,
+        limit_order.timestamp,
+        sequence,
+        limit_order.linked_order_id,
+        limit_order.expires_at.clone()
+    )?;
+    save(&mut deps.storage, queue_key, &order_book)
 }
 
 pub fn swap_callback<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    received_token_address: HumanAddr,
     amount: Uint128
 ) -> StdResult<HandleResponse>{
-    let trigger_address: HumanAddr = load(&mut deps.storage, SWAPPED_TRIGGER_ADDRESS)?;
-    let order_id: HumanAddr = load(&mut deps.storage, SWAPPED_LIMIT_ORDER)?;
-    let order_id_canonical = deps.api.canonical_address(&order_id)?;
     let token1_info: AssetInfo = load(&deps.storage, TOKEN1_DATA).unwrap();
     let token2_info: AssetInfo = load(&deps.storage, TOKEN2_DATA).unwrap();
 
-    let mut active_limit_orders_data = PrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &mut deps.storage);
-    let limit_order_data: Option<LimitOrderState> = may_load(&active_limit_orders_data, &order_id_canonical.as_slice()).unwrap();
+    // Swaps are settled in the order they were sent, so take the first one still in flight paying out in the received token
+    let mut pending_swaps: Vec<PendingSwap> = may_load(&deps.storage, PENDING_SWAPS)?.unwrap_or(vec![]);
+    let pending_swap_position = pending_swaps.iter().position(|pending_swap| {
+        let received_token_info = if pending_swap.order.is_bid { &token1_info } else { &token2_info };
+        received_token_info.token.as_ref().unwrap().contract_addr == received_token_address
+            && env.block.height <= pending_swap.block_height + SWAP_TIMEOUT_BLOCKS
+    });
+    if pending_swap_position == None {
+        return Err(StdError::generic_err(format!(
+            "No pending swap found."
+        )));
+    }
+    let pending_swap: PendingSwap = pending_swaps.remove(pending_swap_position.unwrap());
+    if amount < pending_swap.expected_return {
+        return Err(StdError::generic_err(format!(
+            "Swap returned {} but the order expects at least {}",
+            amount,
+            pending_swap.expected_return
+        )));
+    }
+    if pending_swaps.is_empty() {
+        remove(&mut deps.storage, PENDING_SWAPS);
+    } else {
+        save(&mut deps.storage, PENDING_SWAPS, &pending_swaps)?;
+    }
+
+    let trigger_address_canonical = deps.api.canonical_address(&pending_swap.trigger_address)?;
+    let swapped_order: OrderIndex = pending_swap.order;
+    let order_owner: HumanAddr = swapped_order.owner.clone();
+    let order_owner_canonical = deps.api.canonical_address(&order_owner)?;
+
+    let limit_order_data: Option<LimitOrderState> = load_active_limit_order(&deps.storage, &order_owner_canonical, swapped_order.id)?;
     if limit_order_data == None {
         return Err(StdError::generic_err(format!(
             "No limit order found."
         ))); 
     }
 
+    // Amount of the deposit that was offered to the pair on this swap
+    let offer_amount: Uint128 = pending_swap.offer_amount;
+    let deposit_amount: Uint128 = limit_order_data.clone().unwrap().deposit_amount;
+
     // Calculate Fees and separate the amount the user needs to receive from the fees
-    let order_fees: Uint128 = limit_order_data.clone().unwrap().fee_amount;
-    let user_amount: Uint128 = (amount - order_fees).unwrap();
+    // Fees taken from the deposit were kept out of the swap when it was sent
+    let fee_schedule: FeeSchedule = limit_order_data.clone().unwrap().fee_schedule;
+    let order_fees: Uint128 = match fee_schedule.source {
+        FeeSource::Deposit => Uint128(0),
+        FeeSource::Proceeds => fee_schedule.fee(offer_amount, deposit_amount, amount),
+    };
+    // The protocol fee is kept in the treasury of the contract
+    let protocol_fee_basis_points: u16 = may_load(&deps.storage, PROTOCOL_FEE)?.unwrap_or(0);
+    let protocol_fee: Uint128 = std::cmp::min(
+        amount.multiply_ratio(protocol_fee_basis_points as u128, BASIS_POINTS as u128),
+        (amount - order_fees)?
+    );
+    let user_amount: Uint128 = ((amount - order_fees)? - protocol_fee)?;
     
     // Transfer the amount received to the user
-    let token_contract_address: HumanAddr;
-    let token_contract_hash: String;
-
-    if limit_order_data.clone().unwrap().is_bid {
-        token_contract_address = token1_info.token.clone().unwrap().contract_addr;
-        token_contract_hash = token1_info.token.clone().unwrap().token_code_hash;
-    } else {
-        token_contract_address = token2_info.token.clone().unwrap().contract_addr;
-        token_contract_hash = token2_info.token.clone().unwrap().token_code_hash;
-    }
-
-    let transfer_result1: CosmosMsg = transfer_msg(
-        order_id.clone(),
-        user_amount,
-        None,
-        BLOCK_SIZE,
-        token_contract_hash.clone(),
-        token_contract_address.clone()
-    ).unwrap();
-
-    // Transfer the fee to the triggerer
-    let transfer_result2: CosmosMsg = transfer_msg(
-        trigger_address.clone(),
-        order_fees,
-        None,
-        BLOCK_SIZE,
-        token_contract_hash.clone(),
-        token_contract_address.clone()
-    ).unwrap();
-
-    // Get limit order from active and modify
-    let mut modify_limit_order = limit_order_data.clone().unwrap();
-    modify_limit_order.status = "Filled".to_string();
-    modify_limit_order.balances = vec![Uint128(0),Uint128(0)];
-    if modify_limit_order.is_bid == true {
-        modify_limit_order.withdrew_balance = Some(vec![
-            amount,
-            Uint128(0)
-        ]);
-    } else {
-        modify_limit_order.withdrew_balance = Some(vec![
-            Uint128(0),
-            amount
-        ]);
-    }
-
-    // Remove from active limit order and queue
-    remove(&mut active_limit_orders_data,&order_id_canonical.as_slice());
-    remove(&mut deps.storage,SWAPPED_TRIGGER_ADDRESS);
-    remove(&mut deps.storage,SWAPPED_LIMIT_ORDER);
-    
-    if modify_limit_order.is_bid == true {
-        let mut bid_order_book:OrderQueue = load(&mut deps.storage, BID_ORDER_QUEUE).unwrap();
-        bid_order_book.remove(
-            order_id.clone()
-        );
-        save(&mut deps.storage, BID_ORDER_QUEUE, &bid_order_book)?;
-    } else {
-        let mut ask_order_book:OrderQueue = load(&mut deps.storage, ASK_ORDER_QUEUE).unwrap();
-        ask_order_book.remove(
-            order_id.clone()
-        );
-        save(&mut deps.storage, ASK_ORDER_QUEUE, &ask_order_book)?;
+    let received_token_info: &AssetInfo = if limit_order_data.clone().unwrap().is_bid { &token1_info } else { &token2_info };
+    let messages: Vec<CosmosMsg> = payout_msgs(received_token_info, &env.contract.address, &order_owner, user_amount)?;
+
+    // Book the fee to the triggerer, who claims it later with the fees of other fills
+    let mut keeper_reward = vec![Uint128(0), Uint128(0)];
+    let received_token_index: usize = if limit_order_data.clone().unwrap().is_bid { 0 } else { 1 };
+    match fee_schedule.source {
+        FeeSource::Deposit => keeper_reward[1 - received_token_index] = pending_swap.deposit_fee,
+        FeeSource::Proceeds => keeper_reward[received_token_index] = order_fees,
     }
-
-    // Add to History Limit Orders
-    let mut history_limit_orders = PrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, &order_id_canonical.as_slice()], &mut deps.storage);
-    let mut user_history_orders = AppendStoreMut::attach_or_create(&mut history_limit_orders)?;
-    user_history_orders.push(&modify_limit_order)?;
+    credit_keeper_reward(&mut deps.storage, &trigger_address_canonical, &keeper_reward)?;
+
+    let mut treasury_fee = vec![Uint128(0), Uint128(0)];
+    treasury_fee[received_token_index] = protocol_fee;
+    let treasury_balances: Option<Vec<Uint128>> = may_load(&deps.storage, TREASURY)?;
+    save(&mut deps.storage, TREASURY, &add_balances(treasury_balances, &treasury_fee))?;
+
+    let mut amounts = vec![Uint128(0), Uint128(0)];
+    amounts[1 - received_token_index] = offer_amount;
+    amounts[received_token_index] = user_amount;
+    let mut fees = vec![Uint128(0), Uint128(0)];
+    fees[1 - received_token_index] = pending_swap.deposit_fee;
+    fees[received_token_index] = order_fees + protocol_fee;
+    record_order_event(&mut deps.storage, &env, &order_owner_canonical, OrderEventAction::Fill, &limit_order_data.clone().unwrap(), amounts, fees)?;
+
+    // Update the order and its linked leg with what was swapped
+    fill_limit_order(&mut deps.storage, &order_owner_canonical, limit_order_data.unwrap(), offer_amount, amount)?;
         
     Ok(HandleResponse {
-        messages: vec![
-            transfer_result1,
-            transfer_result2
-        ],
+        messages,
         log: vec![],
         data: Some(to_binary(&HandleAnswer::Status {
             status: ResponseStatus::Success,
@@ -395,171 +807,1276 @@ pub fn swap_callback<S: Storage, A: Api, Q: Querier>(
     })
 }
 
-pub fn try_cancel_limit_order<S: Storage, A: Api, Q: Querier>(
+// The pair pays native coins with a bank send instead of the Receive hook, so the trigger sends this
+// right after the swap and the coins the order book holds are the proceeds of that swap
+pub fn try_settle_native_swap<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env
 ) -> StdResult<HandleResponse>{
-    // load limit order state of the user
-    let user_address = &deps.api.canonical_address(&env.message.sender)?;
-
-    let limit_orders_data = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &deps.storage);
-    let limit_order_data: Option<LimitOrderState> = may_load(&limit_orders_data, &user_address.as_slice())?;
-    if limit_order_data == None {
+    if env.message.sender != env.contract.address {
         return Err(StdError::generic_err(format!(
-            "No limit order found."
-        ))); 
+            "Only the order book can settle its native swaps!"
+        )));
+    }
+    let native_token_info: AssetInfo = load_native_token_info(&deps.storage)?;
+    let denom: String = native_token_info.native_token.clone().unwrap().denom;
+    let received_coin: Coin = deps.querier.query_balance(env.contract.address.clone(), &denom)?;
+    if received_coin.amount == Uint128(0) {
+        return Err(StdError::generic_err(format!(
+            "The pair did not pay any {}.",
+            denom
+        )));
     }
 
-    let mut transfer_result: CosmosMsg = CosmosMsg::Custom(Empty {});
+    // The proceeds are wrapped again so the fees are held like the other balances, the payout redeems the owner's share
+    let wrapper_token = native_token_info.token.unwrap();
+    let deposit_msg = deposit_native_msg(&wrapper_token, vec![received_coin.clone()])?;
+    let mut response = swap_callback(deps, env, wrapper_token.contract_addr, received_coin.amount)?;
+    response.messages.insert(0, deposit_msg);
+    Ok(response)
+}
 
-    // send transfer from this contract to the token contract
-    if limit_order_data.clone().unwrap().balances[0] > Uint128(0) {
-        let token1_info: AssetInfo = load(&deps.storage, TOKEN1_DATA).unwrap();
-        
-        transfer_result = transfer_msg(
-            env.message.sender.clone(),
-            limit_order_data.clone().unwrap().balances[0],
-            None,
-            BLOCK_SIZE,
-            token1_info.token.clone().unwrap().token_code_hash,
-            token1_info.token.clone().unwrap().contract_addr
-        ).unwrap();       
+// Takes offer_amount out of the order deposit and books received_amount of the other token to it.
+// What is left stays on the book, a filled order is moved to history with its linked leg canceled.
+fn fill_limit_order<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    limit_order: LimitOrderState,
+    offer_amount: Uint128,
+    received_amount: Uint128
+) -> StdResult<()> {
+    let mut modify_limit_order = limit_order;
+    let deposit_token_index = modify_limit_order.deposit_token_index as usize;
+    modify_limit_order.balances[deposit_token_index] = (modify_limit_order.balances[deposit_token_index] - offer_amount)?;
+
+    let mut received_balance = vec![Uint128(0),Uint128(0)];
+    if modify_limit_order.is_bid == true {
+        received_balance[0] = received_amount;
+    } else {
+        received_balance[1] = received_amount;
     }
+    modify_limit_order.withdrew_balance = Some(add_balances(modify_limit_order.withdrew_balance.clone(), &received_balance));
 
-    if limit_order_data.clone().unwrap().balances[1] > Uint128(0) {
-        let token2_info: AssetInfo = load(&deps.storage, TOKEN2_DATA).unwrap();
-        
-        transfer_result = transfer_msg(
-            env.message.sender.clone(),
-            limit_order_data.clone().unwrap().balances[1],
-            None,
-            BLOCK_SIZE,
-            token2_info.token.clone().unwrap().token_code_hash,
-            token2_info.token.clone().unwrap().contract_addr
-        ).unwrap();
+    // The linked leg shares the deposit so it follows the balances of this one
+    let mut linked_limit_order: Option<LimitOrderState> = None;
+    if let Some(linked_order_id) = modify_limit_order.linked_order_id {
+        linked_limit_order = load_active_limit_order(storage, owner, linked_order_id)?;
+    }
+    if let Some(linked_limit_order) = linked_limit_order.as_mut() {
+        linked_limit_order.balances = modify_limit_order.balances.clone();
+        linked_limit_order.withdrew_balance = modify_limit_order.withdrew_balance.clone();
     }
 
-    // Add modified limit order to this user history and remove it from active
-    let mut updated_limit_order: LimitOrderState = limit_order_data.clone().unwrap();
-    updated_limit_order.status = "Canceled".to_string();
-    updated_limit_order.withdrew_balance = Some(updated_limit_order.balances);
-    updated_limit_order.balances = vec![Uint128(0),Uint128(0)];
+    // Part of the deposit is still waiting for a better price, so the order stays on the book
+    if modify_limit_order.balances[deposit_token_index] > Uint128(0) {
+        set_order_status(&mut modify_limit_order, OrderStatus::PartiallyFilled)?;
+        save_active_limit_order(storage, owner, &modify_limit_order)?;
+        if let Some(linked_limit_order) = linked_limit_order {
+            save_active_limit_order(storage, owner, &linked_limit_order)?;
+        }
+        return Ok(());
+    }
 
-    // Remove limit order from active
-    let mut active_limit_orders_data = PrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &mut deps.storage);
-    remove(&mut active_limit_orders_data,user_address.as_slice());
+    set_order_status(&mut modify_limit_order, OrderStatus::Filled)?;
+    retire_limit_order(storage, owner, &modify_limit_order)?;
 
-    // Remove from queue
-    if updated_limit_order.is_bid == true {
-        let mut bid_order_book:OrderQueue = load(&mut deps.storage, BID_ORDER_QUEUE).unwrap();
-        bid_order_book.remove(
-            env.message.sender
-        );
-        save(&mut deps.storage, BID_ORDER_QUEUE, &bid_order_book)?;
-    } else {
-        let mut ask_order_book:OrderQueue = load(&mut deps.storage, ASK_ORDER_QUEUE).unwrap();
-        ask_order_book.remove(
-            env.message.sender
-        );
-        save(&mut deps.storage, ASK_ORDER_QUEUE, &ask_order_book)?;
+    // The deposit is gone so the other leg is canceled without a refund
+    if let Some(mut linked_limit_order) = linked_limit_order {
+        set_order_status(&mut linked_limit_order, OrderStatus::Canceled)?;
+        retire_limit_order(storage, owner, &linked_limit_order)?;
     }
-
-    // Add Order to history
-    let mut history_limit_orders = PrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address.as_slice()], &mut deps.storage);
-    let mut user_history_orders = AppendStoreMut::attach_or_create(&mut history_limit_orders)?;
-    user_history_orders.push(&updated_limit_order)?;
-        
-    // Response
-    Ok(HandleResponse {
-        messages: vec![
-            transfer_result,
-            //factory_response
-        ],
-        log: vec![],
-        data: Some(to_binary(&HandleAnswer::Status {
-            status: ResponseStatus::Success,
-            message: None,
-        })?),
-    })
+    Ok(())
 }
 
-pub fn try_trigger_limit_orders<S: Storage, A: Api, Q: Querier>(
+pub fn try_update_limit_order<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: Env
+    env: Env,
+    order_id: u64,
+    price: Price,
+    expected_amount: Uint128
 ) -> StdResult<HandleResponse>{
-    // 1. Check Swappable Limit Orders Order Books
-    let (order_id, limit_order_state) = get_limit_order_to_trigger(deps, true);
-    if order_id != None {
-        let token2_data:AssetInfo = load(&deps.storage, TOKEN2_DATA).unwrap();
-        let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
-        let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address").unwrap();
-    
-        // Set the swapped limit order
-        save(&mut deps.storage, SWAPPED_LIMIT_ORDER, &order_id.unwrap())?;
-        save(&mut deps.storage, SWAPPED_TRIGGER_ADDRESS, &env.message.sender)?;
+    let user_address = &deps.api.canonical_address(&env.message.sender)?;
+
+    let limit_order_data: Option<LimitOrderState> = load_active_limit_order(&deps.storage, user_address, order_id)?;
+    if limit_order_data == None {
+        return Err(StdError::generic_err(format!(
+            "No limit order found."
+        ))); 
+    }
+    let mut updated_limit_order: LimitOrderState = limit_order_data.unwrap();
+    check_not_executing(&updated_limit_order)?;
+
+    // Only what is left of the deposit still trades, at the rate the expected amount sets for the whole deposit
+    let remaining_amount: Uint128 = updated_limit_order.balances[updated_limit_order.deposit_token_index as usize];
+    let remaining_expected_amount: Uint128 = expected_amount.multiply_ratio(remaining_amount, updated_limit_order.deposit_amount);
+    validate_limit_order_amounts(&deps.storage, updated_limit_order.is_bid, remaining_amount, remaining_expected_amount, price)?;
+
+    let previous_price: Price = updated_limit_order.price;
+    updated_limit_order.price = price;
+    updated_limit_order.expected_amount = expected_amount;
+    updated_limit_order.amendments.push(LimitOrderAmendment {
+        price,
+        expected_amount,
+        deposit_amount: updated_limit_order.deposit_amount,
+        timestamp: env.block.time
+    });
+    save_active_limit_order(&mut deps.storage, user_address, &updated_limit_order)?;
+
+    // Re-position the order on its queue, it keeps its priority only if the price did not change
+    let queue_key = order_queue_key(updated_limit_order.is_bid, &updated_limit_order.order_type);
+    let mut order_book: OrderQueue = load(&deps.storage, queue_key)?;
+    if previous_price != price && order_book.remove(&mut deps.storage, order_id)?.is_some() {
+        let sequence = next_sequence(&mut deps.storage)?;
+        order_book.insert(
+            &mut deps.storage,
+            order_id,
+            env.message.sender.clone(),
+            price,
+            env.block.time,
+            sequence,
+            updated_limit_order.linked_order_id,
+            updated_limit_order.expires_at.clone()
+        )?;
+    }
+    save(&mut deps.storage, queue_key, &order_book)?;
+    record_order_event(&mut deps.storage, &env, user_address, OrderEventAction::Update, &updated_limit_order, updated_limit_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn increase_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    deposit_token_index: i8,
+    amount: Uint128,
+    from: HumanAddr,
+    order_id: u64
+) -> StdResult<HandleResponse>{
+    let user_address = &deps.api.canonical_address(&from)?;
+
+    let limit_order_data: Option<LimitOrderState> = load_active_limit_order(&deps.storage, user_address, order_id)?;
+    if limit_order_data == None {
+        return Err(StdError::generic_err(format!(
+            "No limit order found."
+        ))); 
+    }
+    let limit_order: LimitOrderState = limit_order_data.unwrap();
+    check_not_executing(&limit_order)?;
+    if limit_order.deposit_token_index != deposit_token_index {
+        return Err(StdError::generic_err(format!(
+            "Top up must be sent in the deposit token of the order!"
+        )));
+    }
+
+    // The top up is added at the order price, the other leg of an OCO order shares the deposit
+    let mut increased_orders: Vec<LimitOrderState> = vec![limit_order.clone()];
+    if let Some(linked_order_id) = limit_order.linked_order_id {
+        if let Some(linked_limit_order) = load_active_limit_order(&deps.storage, user_address, linked_order_id)? {
+            increased_orders.push(linked_limit_order);
+        }
+    }
+    for mut increased_order in increased_orders {
+        let index = deposit_token_index as usize;
+        increased_order.expected_amount = increased_order.expected_amount + increased_order.expected_amount.multiply_ratio(amount, increased_order.deposit_amount);
+        increased_order.deposit_amount = increased_order.deposit_amount + amount;
+        increased_order.balances[index] = increased_order.balances[index] + amount;
+        increased_order.amendments.push(LimitOrderAmendment {
+            price: increased_order.price,
+            expected_amount: increased_order.expected_amount,
+            deposit_amount: increased_order.deposit_amount,
+            timestamp: env.block.time
+        });
+        save_active_limit_order(&mut deps.storage, user_address, &increased_order)?;
+    }
+    let mut top_up: Vec<Uint128> = vec![Uint128(0),Uint128(0)];
+    top_up[deposit_token_index as usize] = amount;
+    record_order_event(&mut deps.storage, &env, user_address, OrderEventAction::Increase, &limit_order, top_up, vec![Uint128(0),Uint128(0)])?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_cancel_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    order_id: u64
+) -> StdResult<HandleResponse>{
+    // load limit order state of the user
+    let user_address = &deps.api.canonical_address(&env.message.sender)?;
+
+    let limit_order_data: Option<LimitOrderState> = load_active_limit_order(&deps.storage, user_address, order_id)?;
+    if limit_order_data == None {
+        return Err(StdError::generic_err(format!(
+            "No limit order found."
+        ))); 
+    }
+    check_not_executing(&limit_order_data.clone().unwrap())?;
+    let mut updated_limit_order: LimitOrderState = limit_order_data.clone().unwrap();
+    set_order_status(&mut updated_limit_order, OrderStatus::Canceled)?;
+
+    // send transfer from this contract to the token contract, native tokens are redeemed first
+    let messages: Vec<CosmosMsg> = refund_balances(&deps.storage, &env.contract.address, &env.message.sender, &limit_order_data.clone().unwrap().balances)?;
+    record_order_event(&mut deps.storage, &env, user_address, OrderEventAction::Cancel, &updated_limit_order, updated_limit_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
+
+    // Add modified limit order to this user history and remove it from active
+    updated_limit_order.withdrew_balance = Some(add_balances(updated_limit_order.withdrew_balance.clone(), &updated_limit_order.balances));
+    updated_limit_order.balances = vec![Uint128(0),Uint128(0)];
+
+    // Remove limit order from active and queue, add it to history
+    retire_limit_order(&mut deps.storage, user_address, &updated_limit_order)?;
+
+    // Canceling one leg of an OCO order cancels the other one, the deposit is refunded once
+    if let Some(linked_order_id) = updated_limit_order.linked_order_id {
+        if let Some(mut linked_limit_order) = load_active_limit_order(&deps.storage, user_address, linked_order_id)? {
+            set_order_status(&mut linked_limit_order, OrderStatus::Canceled)?;
+            linked_limit_order.withdrew_balance = updated_limit_order.withdrew_balance.clone();
+            linked_limit_order.balances = vec![Uint128(0),Uint128(0)];
+            retire_limit_order(&mut deps.storage, user_address, &linked_limit_order)?;
+        }
+    }
         
-        let swap_response = snip20::send_msg(
-            amm_pair_address, 
-            limit_order_state.clone().unwrap().balances[1], 
-            Some(Binary::from(r#"{ "swap": { } }"#.as_bytes())), 
-            None, 
-            256, 
-            token2_data.clone().token.unwrap().token_code_hash, 
-            token2_data.clone().token.unwrap().contract_addr
-        ); 
-        return Ok(HandleResponse {
-            messages: vec![
-                swap_response.unwrap()
-            ],
-            log: vec![],
-            data: Some(to_binary(&HandleAnswer::Status {
-                status: ResponseStatus::Success,
-                message: None,
-            })?),
-        })       
-    }
-    let (order_id, limit_order_state) = get_limit_order_to_trigger(deps, false);
-    if order_id != None {
-        let token1_data:AssetInfo = load(&deps.storage, TOKEN1_DATA).unwrap();
-        let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
-        let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address").unwrap();
+    // Response
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_claim_keeper_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env
+) -> StdResult<HandleResponse>{
+    let keeper_address = &deps.api.canonical_address(&env.message.sender)?;
+
+    let mut keeper_rewards = PrefixedStorage::new(KEEPER_REWARDS, &mut deps.storage);
+    let rewards: Vec<Uint128> = may_load(&keeper_rewards, keeper_address.as_slice())?.unwrap_or(vec![Uint128(0),Uint128(0)]);
+    if rewards.iter().all(|reward| *reward == Uint128(0)) {
+        return Err(StdError::generic_err(format!(
+            "No keeper rewards to claim."
+        )));
+    }
+    save(&mut keeper_rewards, keeper_address.as_slice(), &vec![Uint128(0),Uint128(0)])?;
+
+    // send transfer from this contract to the token contract, native tokens are redeemed first
+    let messages: Vec<CosmosMsg> = refund_balances(&deps.storage, &env.contract.address, &env.message.sender, &rewards)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_trigger_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    max_orders: Option<u32>
+) -> StdResult<HandleResponse>{
+    // 0. Refund expired orders so they stop taking up the order books
+    let mut messages: Vec<CosmosMsg> = expire_limit_orders(deps, &env, EXPIRED_ORDERS_PER_TRIGGER)?;
+
+    let token1_data: AssetInfo = load(&deps.storage, TOKEN1_DATA)?;
+    let token2_data: AssetInfo = load(&deps.storage, TOKEN2_DATA)?;
+    let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
+    let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address")?;
+    let amm_pair_hash: String = load(&amm_pair_data, b"hash")?;
+
+    let max_orders = max_orders.unwrap_or(1).min(MAX_ORDERS_PER_TRIGGER);
+    let mut pending_swaps: Vec<PendingSwap> = may_load(&deps.storage, PENDING_SWAPS)?.unwrap_or(vec![]);
+    // Swaps still in flight from earlier calls do not count against this batch
+    let mut batch_swaps: u32 = 0;
+    // Orders already swapped by this batch, with the other leg of OCO orders
+    let mut triggered_orders: Vec<u64> = vec![];
+    // Amounts of token1 and token2 this batch already offers to the pair
+    let mut batch_offer_amounts: Vec<Uint128> = vec![Uint128(0), Uint128(0)];
+
+    // 1. Check Swappable Limit Orders Order Books until the batch is full
+    while batch_swaps < max_orders {
+        let mut order_triggered = false;
+        for (is_bid, order_type) in ORDER_BOOKS.iter() {
+            // bids sell token 2, asks sell token 1
+            let offer_token_index: usize = if *is_bid { 1 } else { 0 };
+            let (order_index, limit_order_state, offer_amount) = get_limit_order_to_trigger(
+                deps,
+                *is_bid,
+                order_type,
+                Some(&env.block),
+                &triggered_orders,
+                batch_offer_amounts[offer_token_index]
+            )?;
+            if let (Some(order_index), Some(limit_order_state), Some(offer_amount)) = (order_index, limit_order_state, offer_amount) {
+                // The pair reverts instead of paying less than the order expects for this part of its deposit
+                let expected_return = limit_order_state.expected_amount.multiply_ratio(offer_amount, limit_order_state.deposit_amount);
+                let deposit_fee = deposit_fee(&limit_order_state, offer_amount);
+                let swap_amount = (offer_amount - deposit_fee)?;
+                let owner_canonical = deps.api.canonical_address(&order_index.owner)?;
+                let offer_token_data: &AssetInfo = if *is_bid { &token2_data } else { &token1_data };
+                let offer_token = offer_token_data.clone().token.unwrap();
+                match &offer_token_data.native_token {
+                    // The pair holds the native coin, so the wrapped deposit is redeemed and the coins are sent along
+                    Some(native_token) => {
+                        let swap_msg = AmmPairHandleMsg::Swap {
+                            offer_asset: AmmSimulationOfferAsset {
+                                info: amm_asset_info(offer_token_data),
+                                amount: swap_amount
+                            },
+                            expected_return: Some(expected_return),
+                            to: None
+                        };
+                        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                            contract_addr: offer_token.contract_addr,
+                            callback_code_hash: offer_token.token_code_hash,
+                            msg: padded_binary(&Snip20Msg::redeem(swap_amount))?,
+                            send: vec![],
+                        }));
+                        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                            contract_addr: amm_pair_address.clone(),
+                            callback_code_hash: amm_pair_hash.clone(),
+                            msg: padded_binary(&swap_msg)?,
+                            send: vec![Coin {
+                                denom: native_token.denom.clone(),
+                                amount: swap_amount,
+                            }],
+                        }));
+                    },
+                    None => {
+                        let swap_msg = AmmPairReceiveMsg::Swap {
+                            expected_return: Some(expected_return),
+                            to: None
+                        };
+                        messages.push(snip20::send_msg(
+                            amm_pair_address.clone(), 
+                            swap_amount, 
+                            Some(padded_binary(&swap_msg)?), 
+                            None, 
+                            BLOCK_SIZE, 
+                            offer_token.token_code_hash, 
+                            offer_token.contract_addr
+                        )?);
+                    }
+                }
+                // Native proceeds come back without the Receive hook, so they are settled right after the swap
+                let received_token_data: &AssetInfo = if *is_bid { &token1_data } else { &token2_data };
+                if received_token_data.native_token != None {
+                    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr: env.contract.address.clone(),
+                        callback_code_hash: env.contract_code_hash.clone(),
+                        msg: padded_binary(&HandleMsg::SettleNativeSwap {})?,
+                        send: vec![],
+                    }));
+                }
+
+                triggered_orders.push(order_index.id);
+                if let Some(linked_order_id) = order_index.linked_order_id {
+                    triggered_orders.push(linked_order_id);
+                }
+                batch_offer_amounts[offer_token_index] = batch_offer_amounts[offer_token_index] + swap_amount;
+                pending_swaps.push(PendingSwap {
+                    order: order_index,
+                    trigger_address: env.message.sender.clone(),
+                    offer_amount,
+                    deposit_fee,
+                    expected_return,
+                    block_height: env.block.height
+                });
+                batch_swaps += 1;
+
+                // The order can not be triggered, changed or canceled until the pair pays back
+                let mut executing_order: LimitOrderState = limit_order_state;
+                set_order_status(&mut executing_order, OrderStatus::Executing)?;
+                save_active_limit_order(&mut deps.storage, &owner_canonical, &executing_order)?;
+                order_triggered = true;
+                break;
+            }
+        }
+        if !order_triggered {
+            break;
+        }
+    }
+
+    // Set the swapped limit orders
+    if !triggered_orders.is_empty() {
+        save(&mut deps.storage, PENDING_SWAPS, &pending_swaps)?;
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_unlock_timed_out_swaps<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env
+) -> StdResult<HandleResponse>{
+    let pending_swaps: Vec<PendingSwap> = may_load(&deps.storage, PENDING_SWAPS)?.unwrap_or(vec![]);
+    let (timed_out_swaps, pending_swaps): (Vec<PendingSwap>, Vec<PendingSwap>) = pending_swaps
+        .into_iter()
+        .partition(|pending_swap| env.block.height > pending_swap.block_height + SWAP_TIMEOUT_BLOCKS);
+
+    for timed_out_swap in timed_out_swaps.iter() {
+        let owner_canonical = deps.api.canonical_address(&timed_out_swap.order.owner)?;
+        if let Some(mut limit_order) = load_active_limit_order(&deps.storage, &owner_canonical, timed_out_swap.order.id)? {
+            if limit_order.status == OrderStatus::Executing {
+                // The offered amount left the deposit when the swap was sent, the deposit fee kept out of it
+                // goes to the keeper like on settlement, so only the rest of the deposit is unlocked
+                let deposit_token_index = limit_order.deposit_token_index as usize;
+                limit_order.balances[deposit_token_index] = (limit_order.balances[deposit_token_index] - timed_out_swap.offer_amount)?;
+                let mut keeper_reward = vec![Uint128(0), Uint128(0)];
+                keeper_reward[deposit_token_index] = timed_out_swap.deposit_fee;
+                let trigger_address_canonical = deps.api.canonical_address(&timed_out_swap.trigger_address)?;
+                credit_keeper_reward(&mut deps.storage, &trigger_address_canonical, &keeper_reward)?;
+
+                // Orders that got part of their deposit swapped before stay partially filled
+                if limit_order.withdrew_balance == None {
+                    set_order_status(&mut limit_order, OrderStatus::Active)?;
+                } else {
+                    set_order_status(&mut limit_order, OrderStatus::PartiallyFilled)?;
+                }
+
+                // The linked leg shares the deposit so it follows the balances of this one
+                let mut linked_limit_order: Option<LimitOrderState> = None;
+                if let Some(linked_order_id) = limit_order.linked_order_id {
+                    linked_limit_order = load_active_limit_order(&deps.storage, &owner_canonical, linked_order_id)?;
+                }
+                if let Some(linked_limit_order) = linked_limit_order.as_mut() {
+                    linked_limit_order.balances = limit_order.balances.clone();
+                }
+
+                if limit_order.balances[deposit_token_index] > Uint128(0) {
+                    save_active_limit_order(&mut deps.storage, &owner_canonical, &limit_order)?;
+                    if let Some(linked_limit_order) = linked_limit_order {
+                        save_active_limit_order(&mut deps.storage, &owner_canonical, &linked_limit_order)?;
+                    }
+                } else {
+                    // Nothing is left to trade
+                    set_order_status(&mut limit_order, OrderStatus::Canceled)?;
+                    retire_limit_order(&mut deps.storage, &owner_canonical, &limit_order)?;
+                    if let Some(mut linked_limit_order) = linked_limit_order {
+                        set_order_status(&mut linked_limit_order, OrderStatus::Canceled)?;
+                        retire_limit_order(&mut deps.storage, &owner_canonical, &linked_limit_order)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if pending_swaps.is_empty() {
+        remove(&mut deps.storage, PENDING_SWAPS);
+    } else {
+        save(&mut deps.storage, PENDING_SWAPS, &pending_swaps)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: Some(format!("{} swaps unlocked", timed_out_swaps.len())),
+        })?),
+    })
+}
+
+pub fn try_prune_expired_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>
+) -> StdResult<HandleResponse>{
+    let messages: Vec<CosmosMsg> = expire_limit_orders(deps, &env, limit.unwrap_or(DEFAULT_PRUNE_LIMIT))?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+// Refunds and archives up to limit expired orders, returns the refund transfers
+fn expire_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    limit: u32
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut expired_orders: u32 = 0;
+
+    for (is_bid, order_type) in ORDER_BOOKS.iter() {
+        if expired_orders >= limit {
+            break;
+        }
+        let order_book: OrderQueue = load(&deps.storage, order_queue_key(*is_bid, order_type))?;
+        for order_index in order_book.expired(&deps.storage, &env.block, limit - expired_orders)? {
+            let owner_canonical = deps.api.canonical_address(&order_index.owner)?;
+            // The other leg of an expired OCO order may have been archived already
+            let mut limit_order = match load_active_limit_order(&deps.storage, &owner_canonical, order_index.id)? {
+                Some(limit_order) => limit_order,
+                None => continue
+            };
+            // Both legs of an OCO order share the deposit and the expiration
+            let mut linked_limit_order: Option<LimitOrderState> = None;
+            if let Some(linked_order_id) = limit_order.linked_order_id {
+                linked_limit_order = load_active_limit_order(&deps.storage, &owner_canonical, linked_order_id)?;
+            }
+            // Its swap settles or gets unlocked first
+            if limit_order.status == OrderStatus::Executing
+                || linked_limit_order.as_ref().map_or(false, |linked_limit_order| linked_limit_order.status == OrderStatus::Executing) {
+                continue;
+            }
+
+            messages.extend(refund_balances(&deps.storage, &env.contract.address, &order_index.owner, &limit_order.balances)?);
+            set_order_status(&mut limit_order, OrderStatus::Expired)?;
+            record_order_event(&mut deps.storage, env, &owner_canonical, OrderEventAction::Expire, &limit_order, limit_order.balances.clone(), vec![Uint128(0),Uint128(0)])?;
+            limit_order.withdrew_balance = Some(add_balances(limit_order.withdrew_balance.clone(), &limit_order.balances));
+            limit_order.balances = vec![Uint128(0),Uint128(0)];
+            retire_limit_order(&mut deps.storage, &owner_canonical, &limit_order)?;
+
+            if let Some(mut linked_limit_order) = linked_limit_order {
+                set_order_status(&mut linked_limit_order, OrderStatus::Expired)?;
+                linked_limit_order.withdrew_balance = limit_order.withdrew_balance.clone();
+                linked_limit_order.balances = vec![Uint128(0),Uint128(0)];
+                retire_limit_order(&mut deps.storage, &owner_canonical, &linked_limit_order)?;
+            }
+            expired_orders += 1;
+        }
+    }
+
+    Ok(messages)
+}
+
+pub fn try_change_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    token_index: i8,
+    min_amount: Uint128,
+    fee_amount: Uint128
+) -> StdResult<HandleResponse>{ 
+    // 1. Check if it came from factory!
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+    // 2. change token state on the storage dependong on the token index 
+    let mut token_data: AssetInfo;
+
+    if token_index == 0 {
+        token_data = load(&deps.storage,TOKEN1_DATA).unwrap();
+    } else {
+        token_data = load(&deps.storage,TOKEN2_DATA).unwrap();
+    }
+
+    token_data.min_amount = min_amount;
+    token_data.fee_amount = fee_amount;
+
+    if token_index == 0 {
+        save(&mut deps.storage, TOKEN1_DATA, &token_data)?;
+    } else {
+        save(&mut deps.storage, TOKEN2_DATA, &token_data)?;
+    }
+
+    // Fees are public settings, they can be logged in full
+    let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
+    let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address")?;
     
-        // Set the swapped limit order
-        save(&mut deps.storage, SWAPPED_LIMIT_ORDER, &order_id.unwrap())?;
-        save(&mut deps.storage, SWAPPED_TRIGGER_ADDRESS, &env.message.sender)?;
-
-        let swap_response = snip20::send_msg(
-            amm_pair_address, 
-            limit_order_state.clone().unwrap().balances[0], 
-            Some(Binary::from(r#"{ "swap": { } }"#.as_bytes())), 
-            None, 
-            256, 
-            token1_data.clone().token.unwrap().token_code_hash, 
-            token1_data.clone().token.unwrap().contract_addr
-        ); 
-        return Ok(HandleResponse {
-            messages: vec![
-                swap_response.unwrap()
-            ],
-            log: vec![],
-            data: Some(to_binary(&HandleAnswer::Status {
-                status: ResponseStatus::Success,
-                message: None,
-            })?),
-        })   
-    }
-
-    return Ok(HandleResponse::default())
+    return Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "change_fee"),
+            log("pair", amm_pair_address),
+            log("token", token_data.token.unwrap().contract_addr),
+            log("fee_amount", token_data.fee_amount),
+            log("min_amount", token_data.min_amount),
+        ],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_depth_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    depth_config: Option<DepthConfig>
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    match depth_config {
+        Some(depth_config) => {
+            if depth_config.band_width.is_zero() {
+                return Err(StdError::generic_err(format!(
+                    "Depth band width must be greater than zero!"
+                )));
+            }
+            if depth_config.min_orders_per_band < MIN_ORDERS_PER_DEPTH_BAND {
+                return Err(StdError::generic_err(format!(
+                    "Depth bands need at least {} orders!",
+                    MIN_ORDERS_PER_DEPTH_BAND
+                )));
+            }
+            save(&mut deps.storage, DEPTH_CONFIG, &depth_config)?;
+        },
+        None => remove(&mut deps.storage, DEPTH_CONFIG)
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_scan_limit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    scan_limit: u32
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    if scan_limit == 0 || scan_limit > MAX_SCAN_LIMIT {
+        return Err(StdError::generic_err(format!(
+            "Scan limit must be between 1 and {}!",
+            MAX_SCAN_LIMIT
+        )));
+    }
+    save(&mut deps.storage, SCAN_LIMIT, &scan_limit)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_set_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    status: ContractStatus
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    // Migrating is only entered and left through the migration itself
+    if status == ContractStatus::Migrating {
+        return Err(StdError::generic_err(format!(
+            "The order book can only be set to migrating by a migration!"
+        )));
+    }
+
+    // A migrating order book takes the status once its orders are imported,
+    // so the factory can still set the status of all its order books at once
+    let current_status: ContractStatus = may_load(&deps.storage, CONTRACT_STATUS)?.unwrap_or(ContractStatus::Normal);
+    if current_status == ContractStatus::Migrating {
+        save(&mut deps.storage, STATUS_AFTER_MIGRATION, &status)?;
+    } else {
+        save(&mut deps.storage, CONTRACT_STATUS, &status)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_start_migration<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contract_address: HumanAddr,
+    contract_hash: String
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    if may_load::<MigrationContract, _>(&deps.storage, MIGRATION_TARGET)? != None {
+        return Err(StdError::generic_err(format!(
+            "The order book is already migrating!"
+        )));
+    }
+
+    save(&mut deps.storage, MIGRATION_TARGET, &MigrationContract {
+        address: contract_address,
+        code_hash: contract_hash
+    })?;
+    save(&mut deps.storage, CONTRACT_STATUS, &ContractStatus::Migrating)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_export_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    limit: Option<u32>
+) -> StdResult<HandleResponse>{ 
+    let migration_target: MigrationContract = may_load(&deps.storage, MIGRATION_TARGET)?.ok_or_else(|| StdError::generic_err(format!(
+        "The order book is not migrating!"
+    )))?;
+    if may_load::<bool, _>(&deps.storage, ORDERS_EXPORTED)?.unwrap_or(false) {
+        return Err(StdError::generic_err(format!(
+            "All limit orders were already exported!"
+        )));
+    }
+    // Executing orders only get their final balances once their swap settles
+    let pending_swaps: Vec<PendingSwap> = may_load(&deps.storage, PENDING_SWAPS)?.unwrap_or(vec![]);
+    if !pending_swaps.is_empty() {
+        return Err(StdError::generic_err(format!(
+            "Swaps sent to the pair have to settle or be unlocked first!"
+        )));
+    }
+    let limit = limit.unwrap_or(DEFAULT_EXPORT_LIMIT).min(MAX_EXPORT_LIMIT) as usize;
+
+    let mut export_batch: Vec<OrderIndex> = vec![];
+    for (is_bid, order_type) in ORDER_BOOKS.iter() {
+        let order_book: OrderQueue = load(&deps.storage, order_queue_key(*is_bid, order_type))?;
+        for order_index in order_book.orders(&deps.storage).take(limit - export_batch.len()) {
+            export_batch.push(order_index?);
+        }
+    }
+
+    let mut limit_orders: Vec<MigratedLimitOrder> = vec![];
+    let mut exported_balances: Vec<Uint128> = vec![Uint128(0),Uint128(0)];
+    for order_index in export_batch {
+        // The other leg of an OCO order is exported with the first one
+        if limit_orders.iter().any(|migrated_limit_order| migrated_limit_order.limit_order.id == order_index.id) {
+            continue;
+        }
+        let owner_canonical = deps.api.canonical_address(&order_index.owner)?;
+        let limit_order: LimitOrderState = load_active_limit_order(&deps.storage, &owner_canonical, order_index.id)?.ok_or_else(|| StdError::generic_err(format!(
+            "No limit order found."
+        )))?;
+        // Both legs share the deposit
+        exported_balances = add_balances(Some(exported_balances), &limit_order.balances);
+
+        let linked_order_id = limit_order.linked_order_id;
+        limit_orders.push(export_limit_order(&mut deps.storage, &order_index.owner, &owner_canonical, limit_order)?);
+        if let Some(linked_order_id) = linked_order_id {
+            if let Some(linked_limit_order) = load_active_limit_order(&deps.storage, &owner_canonical, linked_order_id)? {
+                limit_orders.push(export_limit_order(&mut deps.storage, &order_index.owner, &owner_canonical, linked_limit_order)?);
+            }
+        }
+    }
+
+    let mut finished = true;
+    for (is_bid, order_type) in ORDER_BOOKS.iter() {
+        let order_book: OrderQueue = load(&deps.storage, order_queue_key(*is_bid, order_type))?;
+        finished = finished && order_book.is_empty();
+    }
+    // The treasury leaves with the last orders, nothing adds to it once they are gone
+    let mut treasury_balances: Vec<Uint128> = vec![Uint128(0),Uint128(0)];
+    if finished {
+        save(&mut deps.storage, ORDERS_EXPORTED, &true)?;
+        treasury_balances = may_load(&deps.storage, TREASURY)?.unwrap_or(treasury_balances);
+        remove(&mut deps.storage, TREASURY);
+    }
+
+    // The settings go with the first orders, the new order book applies them before it opens
+    let mut config: Option<MigratedConfig> = None;
+    if !may_load::<bool, _>(&deps.storage, CONFIG_EXPORTED)?.unwrap_or(false) {
+        save(&mut deps.storage, CONFIG_EXPORTED, &true)?;
+        let token1_info: AssetInfo = load(&deps.storage, TOKEN1_DATA)?;
+        let token2_info: AssetInfo = load(&deps.storage, TOKEN2_DATA)?;
+        config = Some(MigratedConfig {
+            fee_amounts: vec![token1_info.fee_amount, token2_info.fee_amount],
+            min_amounts: vec![token1_info.min_amount, token2_info.min_amount],
+            protocol_fee: may_load(&deps.storage, PROTOCOL_FEE)?.unwrap_or(0),
+            bid_fee_schedule: may_load(&deps.storage, BID_FEE_SCHEDULE)?,
+            ask_fee_schedule: may_load(&deps.storage, ASK_FEE_SCHEDULE)?,
+            scan_limit: may_load(&deps.storage, SCAN_LIMIT)?,
+            depth_config: may_load(&deps.storage, DEPTH_CONFIG)?
+        });
+    }
+
+    // The deposits arrive before the orders
+    let mut messages: Vec<CosmosMsg> = migration_transfer_msgs(&deps.storage, &migration_target, &add_balances(Some(exported_balances), &treasury_balances))?;
+    let import_limit_orders_msg = MigrationHandleMsg::ImportLimitOrders {
+        limit_orders,
+        last_order_id: load(&deps.storage, LAST_ORDER_ID)?,
+        last_sequence: load(&deps.storage, LAST_SEQUENCE)?,
+        treasury: treasury_balances,
+        config,
+        finished
+    };
+    messages.push(import_limit_orders_msg.to_cosmos_msg(migration_target.code_hash, migration_target.address, None)?);
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+// Sends balances to the new order book, native tokens stay wrapped
+fn migration_transfer_msgs<S: ReadonlyStorage>(
+    storage: &S,
+    migration_target: &MigrationContract,
+    balances: &Vec<Uint128>
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (token_key, balance) in [TOKEN1_DATA, TOKEN2_DATA].iter().zip(balances.iter()) {
+        if *balance > Uint128(0) {
+            let token = load::<AssetInfo, _>(storage, token_key)?.token.unwrap();
+            messages.push(transfer_msg(
+                migration_target.address.clone(),
+                *balance,
+                None,
+                BLOCK_SIZE,
+                token.token_code_hash,
+                token.contract_addr
+            )?);
+        }
+    }
+    Ok(messages)
+}
+
+// Takes an order out of active and its queue, keeping its sequence for the new order book
+fn export_limit_order<S: Storage>(
+    storage: &mut S,
+    owner: &HumanAddr,
+    owner_canonical: &CanonicalAddr,
+    limit_order: LimitOrderState
+) -> StdResult<MigratedLimitOrder> {
+    remove_active_limit_order(storage, owner_canonical, limit_order.id)?;
+
+    let queue_key = order_queue_key(limit_order.is_bid, &limit_order.order_type);
+    let mut order_book: OrderQueue = load(storage, queue_key)?;
+    let order_index: OrderIndex = order_book.remove(storage, limit_order.id)?.ok_or_else(|| StdError::generic_err(format!(
+        "Limit order {} is not on the order book.",
+        limit_order.id
+    )))?;
+    save(storage, queue_key, &order_book)?;
+
+    Ok(MigratedLimitOrder {
+        owner: owner.clone(),
+        sequence: order_index.sequence,
+        limit_order
+    })
+}
+
+pub fn try_export_history_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    owners: Vec<HumanAddr>,
+    limit: Option<u32>
+) -> StdResult<HandleResponse>{ 
+    let migration_target: MigrationContract = may_load(&deps.storage, MIGRATION_TARGET)?.ok_or_else(|| StdError::generic_err(format!(
+        "The order book is not migrating!"
+    )))?;
+    let limit = limit.unwrap_or(DEFAULT_EXPORT_LIMIT).min(MAX_EXPORT_LIMIT) as usize;
+
+    let mut histories: Vec<MigratedHistory> = vec![];
+    let mut exported_entries: usize = 0;
+    for owner in owners {
+        if exported_entries >= limit {
+            break;
+        }
+        let owner_canonical = deps.api.canonical_address(&owner)?;
+        // Orders retired and events recorded after an earlier export are sent with the next one
+        let history_exported = ReadonlyPrefixedStorage::new(HISTORY_EXPORTED, &deps.storage);
+        let exported_count: u32 = may_load(&history_exported, owner_canonical.as_slice())?.unwrap_or(0);
+        let events_exported = ReadonlyPrefixedStorage::new(EVENTS_EXPORTED, &deps.storage);
+        let exported_events_count: u32 = may_load(&events_exported, owner_canonical.as_slice())?.unwrap_or(0);
+
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, owner_canonical.as_slice()], &deps.storage);
+        let limit_orders: Vec<LimitOrderState> = match AppendStore::<LimitOrderState, _>::attach(&history_limit_orders) {
+            Some(store) => store?.iter().skip(exported_count as usize).take(limit - exported_entries).collect::<StdResult<Vec<LimitOrderState>>>()?,
+            None => vec![]
+        };
+        exported_entries += limit_orders.len();
+        let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, owner_canonical.as_slice()], &deps.storage);
+        let events: Vec<OrderEvent> = match AppendStore::<OrderEvent, _>::attach(&order_events) {
+            Some(store) => store?.iter().skip(exported_events_count as usize).take(limit - exported_entries).collect::<StdResult<Vec<OrderEvent>>>()?,
+            None => vec![]
+        };
+        exported_entries += events.len();
+
+        if limit_orders.is_empty() && events.is_empty() {
+            continue;
+        }
+
+        let mut history_exported = PrefixedStorage::new(HISTORY_EXPORTED, &mut deps.storage);
+        save(&mut history_exported, owner_canonical.as_slice(), &(exported_count + limit_orders.len() as u32))?;
+        let mut events_exported = PrefixedStorage::new(EVENTS_EXPORTED, &mut deps.storage);
+        save(&mut events_exported, owner_canonical.as_slice(), &(exported_events_count + events.len() as u32))?;
+        histories.push(MigratedHistory {
+            owner,
+            limit_orders,
+            order_events: events
+        });
+    }
+    if histories.is_empty() {
+        return Err(StdError::generic_err(format!(
+            "No history left to export for these owners."
+        )));
+    }
+
+    let import_history_limit_orders_msg = MigrationHandleMsg::ImportHistoryLimitOrders {
+        histories
+    };
+
+    Ok(HandleResponse {
+        messages: vec![import_history_limit_orders_msg.to_cosmos_msg(migration_target.code_hash, migration_target.address, None)?],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+// Keepers are walked in the order they first earned a fee, including the ones that never placed an order
+pub fn try_export_keeper_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    limit: Option<u32>
+) -> StdResult<HandleResponse>{ 
+    let migration_target: MigrationContract = may_load(&deps.storage, MIGRATION_TARGET)?.ok_or_else(|| StdError::generic_err(format!(
+        "The order book is not migrating!"
+    )))?;
+    // Settling or unlocking a swap still books fees to its keeper
+    let pending_swaps: Vec<PendingSwap> = may_load(&deps.storage, PENDING_SWAPS)?.unwrap_or(vec![]);
+    if !pending_swaps.is_empty() {
+        return Err(StdError::generic_err(format!(
+            "Swaps sent to the pair have to settle or be unlocked first!"
+        )));
+    }
+    let limit = limit.unwrap_or(DEFAULT_EXPORT_LIMIT).min(MAX_EXPORT_LIMIT) as usize;
+
+    let exported_count: u32 = may_load(&deps.storage, KEEPERS_EXPORTED)?.unwrap_or(0);
+    let keepers_storage = ReadonlyPrefixedStorage::new(KEEPERS, &deps.storage);
+    let keepers: Vec<CanonicalAddr> = match AppendStore::<CanonicalAddr, _>::attach(&keepers_storage) {
+        Some(store) => store?.iter().skip(exported_count as usize).take(limit).collect::<StdResult<Vec<CanonicalAddr>>>()?,
+        None => vec![]
+    };
+    if keepers.is_empty() {
+        return Err(StdError::generic_err(format!(
+            "No keeper rewards left to export."
+        )));
+    }
+    save(&mut deps.storage, KEEPERS_EXPORTED, &(exported_count + keepers.len() as u32))?;
+
+    // Rewards are moved whole, a claim on the old order book finds nothing left
+    let mut keeper_rewards: Vec<MigratedKeeperRewards> = vec![];
+    let mut exported_rewards: Vec<Uint128> = vec![Uint128(0),Uint128(0)];
+    for keeper in keepers {
+        let mut keeper_rewards_storage = PrefixedStorage::new(KEEPER_REWARDS, &mut deps.storage);
+        let rewards: Vec<Uint128> = may_load(&keeper_rewards_storage, keeper.as_slice())?.unwrap_or(vec![Uint128(0),Uint128(0)]);
+        if rewards.iter().all(|reward| *reward == Uint128(0)) {
+            continue;
+        }
+        save(&mut keeper_rewards_storage, keeper.as_slice(), &vec![Uint128(0),Uint128(0)])?;
+        exported_rewards = add_balances(Some(exported_rewards), &rewards);
+        keeper_rewards.push(MigratedKeeperRewards {
+            keeper: deps.api.human_address(&keeper)?,
+            rewards
+        });
+    }
+
+    // The keeper rewards arrive before they are booked
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !keeper_rewards.is_empty() {
+        messages = migration_transfer_msgs(&deps.storage, &migration_target, &exported_rewards)?;
+        let import_keeper_rewards_msg = MigrationHandleMsg::ImportKeeperRewards {
+            keeper_rewards
+        };
+        messages.push(import_keeper_rewards_msg.to_cosmos_msg(migration_target.code_hash, migration_target.address, None)?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+fn check_migration_source<S: ReadonlyStorage>(storage: &S, sender: &HumanAddr) -> StdResult<()> {
+    let migration_source: Option<MigrationContract> = may_load(storage, MIGRATION_SOURCE)?;
+    if migration_source.map_or(true, |migration_source| migration_source.address != *sender) {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from the migrated order book!"
+        )));
+    }
+    Ok(())
+}
+
+pub fn try_import_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit_orders: Vec<MigratedLimitOrder>,
+    last_order_id: u64,
+    last_sequence: u64,
+    treasury: Vec<Uint128>,
+    config: Option<MigratedConfig>,
+    finished: bool
+) -> StdResult<HandleResponse>{ 
+    check_migration_source(&deps.storage, &env.message.sender)?;
+
+    if let Some(config) = config {
+        import_config(&mut deps.storage, config)?;
+    }
+
+    // Orders keep their ids and their place in the queues
+    for migrated_limit_order in limit_orders {
+        let owner_canonical = deps.api.canonical_address(&migrated_limit_order.owner)?;
+        add_limit_order(deps, &migrated_limit_order.owner, &owner_canonical, &migrated_limit_order.limit_order, migrated_limit_order.sequence)?;
+    }
+
+    // New orders are numbered after the ones of the old order book
+    let order_id: u64 = load(&deps.storage, LAST_ORDER_ID)?;
+    save(&mut deps.storage, LAST_ORDER_ID, &order_id.max(last_order_id))?;
+    let sequence: u64 = load(&deps.storage, LAST_SEQUENCE)?;
+    save(&mut deps.storage, LAST_SEQUENCE, &sequence.max(last_sequence))?;
+
+    if treasury.iter().any(|balance| *balance > Uint128(0)) {
+        let treasury_balances: Option<Vec<Uint128>> = may_load(&deps.storage, TREASURY)?;
+        save(&mut deps.storage, TREASURY, &add_balances(treasury_balances, &treasury))?;
+    }
+
+    let status: ContractStatus = may_load(&deps.storage, CONTRACT_STATUS)?.unwrap_or(ContractStatus::Normal);
+    if finished && status == ContractStatus::Migrating {
+        let status_after_migration: ContractStatus = may_load(&deps.storage, STATUS_AFTER_MIGRATION)?.unwrap_or(ContractStatus::Normal);
+        save(&mut deps.storage, CONTRACT_STATUS, &status_after_migration)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+// Takes over the settings of the old order book, unset ones keep their defaults
+fn import_config<S: Storage>(storage: &mut S, config: MigratedConfig) -> StdResult<()> {
+    for (token_index, token_key) in [TOKEN1_DATA, TOKEN2_DATA].iter().enumerate() {
+        let mut token_info: AssetInfo = load(storage, token_key)?;
+        token_info.fee_amount = config.fee_amounts[token_index];
+        token_info.min_amount = config.min_amounts[token_index];
+        save(storage, token_key, &token_info)?;
+    }
+    save(storage, PROTOCOL_FEE, &config.protocol_fee)?;
+    if let Some(bid_fee_schedule) = config.bid_fee_schedule {
+        save(storage, BID_FEE_SCHEDULE, &bid_fee_schedule)?;
+    }
+    if let Some(ask_fee_schedule) = config.ask_fee_schedule {
+        save(storage, ASK_FEE_SCHEDULE, &ask_fee_schedule)?;
+    }
+    if let Some(scan_limit) = config.scan_limit {
+        save(storage, SCAN_LIMIT, &scan_limit)?;
+    }
+    if let Some(depth_config) = config.depth_config {
+        save(storage, DEPTH_CONFIG, &depth_config)?;
+    }
+    Ok(())
+}
+
+pub fn try_import_history_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    histories: Vec<MigratedHistory>
+) -> StdResult<HandleResponse>{ 
+    check_migration_source(&deps.storage, &env.message.sender)?;
+
+    for history in histories {
+        let owner_canonical = deps.api.canonical_address(&history.owner)?;
+        let mut history_limit_orders = PrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, owner_canonical.as_slice()], &mut deps.storage);
+        let mut user_history_orders = AppendStoreMut::attach_or_create(&mut history_limit_orders)?;
+        for limit_order in history.limit_orders {
+            user_history_orders.push(&limit_order)?;
+        }
+
+        let mut order_events = PrefixedStorage::multilevel(&[ORDER_EVENTS, owner_canonical.as_slice()], &mut deps.storage);
+        let mut user_order_events = AppendStoreMut::attach_or_create(&mut order_events)?;
+        for order_event in history.order_events {
+            user_order_events.push(&order_event)?;
+        }
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_import_keeper_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    keeper_rewards: Vec<MigratedKeeperRewards>
+) -> StdResult<HandleResponse>{ 
+    check_migration_source(&deps.storage, &env.message.sender)?;
+
+    for migrated_keeper_rewards in keeper_rewards {
+        let keeper_canonical = deps.api.canonical_address(&migrated_keeper_rewards.keeper)?;
+        credit_keeper_reward(&mut deps.storage, &keeper_canonical, &migrated_keeper_rewards.rewards)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_change_protocol_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    basis_points: u16
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    if basis_points > MAX_PROTOCOL_FEE_BASIS_POINTS {
+        return Err(StdError::generic_err(format!(
+            "Protocol fee can not be more than {} basis points!",
+            MAX_PROTOCOL_FEE_BASIS_POINTS
+        )));
+    }
+
+    save(&mut deps.storage, PROTOCOL_FEE, &basis_points)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
+}
+
+pub fn try_withdraw_treasury<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    recipient: HumanAddr
+) -> StdResult<HandleResponse>{ 
+    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
+    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
+
+    if factory_contract_address != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Message did not came from factory!"
+        ))); 
+    }
+
+    let treasury_balances: Vec<Uint128> = may_load(&deps.storage, TREASURY)?.unwrap_or(vec![Uint128(0),Uint128(0)]);
+    if treasury_balances.iter().all(|balance| *balance == Uint128(0)) {
+        return Err(StdError::generic_err(format!(
+            "Treasury is empty."
+        )));
+    }
+    remove(&mut deps.storage, TREASURY);
+
+    // send transfer from this contract to the token contract, native tokens are redeemed first
+    let messages: Vec<CosmosMsg> = refund_balances(&deps.storage, &env.contract.address, &recipient, &treasury_balances)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
 }
 
-pub fn try_change_fee<S: Storage, A: Api, Q: Querier>(
+pub fn try_change_fee_schedule<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    token_index: i8,
-    min_amount: Uint128,
-    fee_amount: Uint128
+    is_bid: bool,
+    fee_schedule: Option<FeeSchedule>
 ) -> StdResult<HandleResponse>{ 
-    // 1. Check if it came from factory!
     let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
     let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
 
@@ -568,25 +2085,24 @@ pub fn try_change_fee<S: Storage, A: Api, Q: Querier>(
             "Message did not came from factory!"
         ))); 
     }
-    // 2. change token state on the storage dependong on the token index 
-    let mut token_data: AssetInfo;
 
-    if token_index == 0 {
-        token_data = load(&deps.storage,TOKEN1_DATA).unwrap();
-    } else {
-        token_data = load(&deps.storage,TOKEN2_DATA).unwrap();
+    let fee_schedule_key = if is_bid { BID_FEE_SCHEDULE } else { ASK_FEE_SCHEDULE };
+    match fee_schedule {
+        Some(fee_schedule) => {
+            fee_schedule.validate()?;
+            save(&mut deps.storage, fee_schedule_key, &fee_schedule)?;
+        },
+        None => remove(&mut deps.storage, fee_schedule_key)
     }
 
-    token_data.min_amount = min_amount;
-    token_data.fee_amount = fee_amount;
-
-    if token_index == 0 {
-        save(&mut deps.storage, TOKEN1_DATA, &token_data)?;
-    } else {
-        save(&mut deps.storage, TOKEN2_DATA, &token_data)?;
-    }
-    
-    return Ok(HandleResponse::default())
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: ResponseStatus::Success,
+            message: None,
+        })?),
+    })
 }
 
 
@@ -594,13 +2110,25 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
-    match msg {
+    let response = match msg {
         QueryMsg::OrderBookPairInfo {} => get_order_book_pair_info(deps),
-        QueryMsg::GetActiveLimitOrder {user_address, user_viewkey} => get_active_limit_order(deps, user_address, user_viewkey),
+        QueryMsg::GetActiveLimitOrder {user_address, user_viewkey, order_id} => get_active_limit_order(deps, user_address, user_viewkey, order_id),
+        QueryMsg::GetActiveLimitOrders {user_address, user_viewkey} => get_active_limit_orders(deps, user_address, user_viewkey),
         QueryMsg::GetHistoryLimitOrders {user_address, user_viewkey, page_size, page} => get_history_limit_orders(deps, user_address, user_viewkey, page_size, page),
+        QueryMsg::GetOrderEvents {user_address, user_viewkey, page_size, page} => get_order_events(deps, user_address, user_viewkey, page_size, page),
         QueryMsg::CheckOrderBookTrigger {} => to_binary(&check_order_book_trigger(deps)?),
+        QueryMsg::OrderBookDepth {} => get_order_book_depth(deps),
+        QueryMsg::KeeperRewards {user_address, user_viewkey} => get_keeper_rewards(deps, user_address, user_viewkey),
+        QueryMsg::TreasuryBalances {} => get_treasury_balances(deps),
+        QueryMsg::ContractStatus {} => to_binary(&QueryAnswer::ContractStatus {
+            status: may_load(&deps.storage, CONTRACT_STATUS)?.unwrap_or(ContractStatus::Normal)
+        }),
+        QueryMsg::TriggerPreview { max_orders } => to_binary(&QueryAnswer::TriggerPreview {
+            candidates: get_trigger_preview(deps, max_orders)?
+        }),
         _ => Err(StdError::generic_err("Handler not found!"))
-    }
+    };
+    pad_query_result(response, BLOCK_SIZE)
 }
 
 
@@ -618,11 +2146,56 @@ fn get_order_book_pair_info<S: Storage, A: Api, Q: Querier>(
     })
 }
 
-fn get_active_limit_order<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    user_address: HumanAddr,
-    user_viewkey: String
+fn get_order_book_depth<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>
 ) -> QueryResult {
+    let depth_config: DepthConfig = may_load(&deps.storage, DEPTH_CONFIG)?.ok_or_else(|| StdError::generic_err(format!(
+        "Order book depth is not enabled!"
+    )))?;
+
+    to_binary(&QueryAnswer::OrderBookDepth {
+        band_width: depth_config.band_width,
+        bids: order_queue_depth(deps, BID_ORDER_QUEUE, true, &depth_config)?,
+        asks: order_queue_depth(deps, ASK_ORDER_QUEUE, false, &depth_config)?
+    })
+}
+
+// Remaining deposits of the limit orders in a queue summed per price band, best price first
+fn order_queue_depth<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    queue_key: &[u8],
+    is_bid: bool,
+    depth_config: &DepthConfig
+) -> StdResult<Vec<DepthBand>> {
+    let order_queue: OrderQueue = load(&deps.storage, queue_key)?;
+    // Band price => (volume, orders)
+    let mut bands: BTreeMap<Price, (Uint128, u32)> = BTreeMap::new();
+
+    for order_index in order_queue.orders(&deps.storage) {
+        let order_index = order_index?;
+        let owner = deps.api.canonical_address(&order_index.owner)?;
+        if let Some(limit_order) = load_active_limit_order(&deps.storage, &owner, order_index.id)? {
+            let volume = limit_order.balances[limit_order.deposit_token_index as usize];
+            let band = bands.entry(order_index.price.band_floor(depth_config.band_width)).or_insert((Uint128(0), 0));
+            *band = (band.0 + volume, band.1 + 1);
+        }
+    }
+
+    let mut depth_bands: Vec<DepthBand> = bands.into_iter()
+        .filter(|(_, (_, orders))| *orders >= depth_config.min_orders_per_band)
+        .map(|(price, (volume, _))| DepthBand { price, volume })
+        .collect();
+    if is_bid {
+        depth_bands.reverse();
+    }
+    Ok(depth_bands)
+}
+
+fn is_viewing_key_valid<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    user_address: &HumanAddr,
+    user_viewkey: &String
+) -> StdResult<bool> {
     let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
     let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
     let factory_contract_hash: String = load(&factory_data, b"hash")?;
@@ -635,11 +2208,18 @@ fn get_active_limit_order<S: Storage, A: Api, Q: Querier>(
         address: user_address.clone()
     }.query(&deps.querier, factory_contract_hash, factory_contract_address)?;
 
-    if response.is_key_valid.is_valid {
-        let user_address_canonical = &deps.api.canonical_address(&user_address)?;
+    Ok(response.is_key_valid.is_valid)
+}
 
-        let limit_orders_data = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &deps.storage);
-        let limit_order_data: Option<LimitOrderState> = may_load(&limit_orders_data, &user_address_canonical.as_slice())?;
+fn get_active_limit_order<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    user_address: HumanAddr,
+    user_viewkey: String,
+    order_id: u64
+) -> QueryResult {
+    if is_viewing_key_valid(deps, &user_address, &user_viewkey)? {
+        let user_address_canonical = &deps.api.canonical_address(&user_address)?;
+        let limit_order_data: Option<LimitOrderState> = load_active_limit_order(&deps.storage, user_address_canonical, order_id)?;
         
         return to_binary(&QueryAnswer::ActiveLimitOrder {
             active_limit_order: limit_order_data 
@@ -651,6 +2231,67 @@ fn get_active_limit_order<S: Storage, A: Api, Q: Querier>(
     }
 }
 
+fn get_treasury_balances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>
+) -> QueryResult {
+    let protocol_fee_basis_points: u16 = may_load(&deps.storage, PROTOCOL_FEE)?.unwrap_or(0);
+    let balances: Vec<Uint128> = may_load(&deps.storage, TREASURY)?.unwrap_or(vec![Uint128(0),Uint128(0)]);
+
+    to_binary(&QueryAnswer::TreasuryBalances {
+        protocol_fee_basis_points,
+        balances
+    })
+}
+
+fn get_keeper_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    user_address: HumanAddr,
+    user_viewkey: String
+) -> QueryResult {
+    if is_viewing_key_valid(deps, &user_address, &user_viewkey)? {
+        let user_address_canonical = &deps.api.canonical_address(&user_address)?;
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = may_load(&keeper_rewards, user_address_canonical.as_slice())?.unwrap_or(vec![Uint128(0),Uint128(0)]);
+
+        return to_binary(&QueryAnswer::KeeperRewards {
+            rewards
+        });
+    } else {
+        return Err(StdError::generic_err(format!(
+            "Invalid address - viewkey pair!"
+        ))); 
+    }
+}
+
+fn get_active_limit_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    user_address: HumanAddr,
+    user_viewkey: String
+) -> QueryResult {
+    if is_viewing_key_valid(deps, &user_address, &user_viewkey)? {
+        let user_address_canonical = &deps.api.canonical_address(&user_address)?;
+        let user_orders = ReadonlyPrefixedStorage::new(USER_ORDERS, &deps.storage);
+        let user_order_map: Option<UserOrderMap> = may_load(&user_orders, user_address_canonical.as_slice())?;
+
+        let mut active_limit_orders: Vec<LimitOrderState> = vec![];
+        if let Some(user_order_map) = user_order_map {
+            for order_id in user_order_map.active_orders {
+                if let Some(limit_order) = load_active_limit_order(&deps.storage, user_address_canonical, order_id)? {
+                    active_limit_orders.push(limit_order);
+                }
+            }
+        }
+
+        return to_binary(&QueryAnswer::ActiveLimitOrders {
+            active_limit_orders
+        });
+    } else {
+        return Err(StdError::generic_err(format!(
+            "Invalid address - viewkey pair!"
+        ))); 
+    }
+}
+
 fn get_history_limit_orders<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     user_address: HumanAddr,
@@ -658,19 +2299,7 @@ fn get_history_limit_orders<S: Storage, A: Api, Q: Querier>(
     page_size: Option<u32>,
     page: Option<u32>
 ) -> QueryResult {
-    let factory_data = ReadonlyPrefixedStorage::new(FACTORY_DATA, &deps.storage);
-    let factory_contract_address: HumanAddr = load(&factory_data, b"address")?;
-    let factory_contract_hash: String = load(&factory_data, b"hash")?;
-    let factory_key: String = load(&factory_data, b"key")?;
-
-    let response: IsKeyValidResponse =
-    FactoryQueryMsg::IsKeyValid {
-        factory_key,
-        viewing_key: user_viewkey.clone(),
-        address: user_address.clone()
-    }.query(&deps.querier, factory_contract_hash, factory_contract_address)?;
-
-    if response.is_key_valid.is_valid {
+    if is_viewing_key_valid(deps, &user_address, &user_viewkey)? {
         let user_address = &deps.api.canonical_address(&user_address)?;
         let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address.as_slice()], &deps.storage);
         
@@ -708,50 +2337,148 @@ fn get_history_limit_orders<S: Storage, A: Api, Q: Querier>(
     }
 }
 
+fn get_order_events<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    user_address: HumanAddr,
+    user_viewkey: String,
+    page_size: Option<u32>,
+    page: Option<u32>
+) -> QueryResult {
+    if !is_viewing_key_valid(deps, &user_address, &user_viewkey)? {
+        return Err(StdError::generic_err(format!(
+            "Invalid address - viewkey pair!"
+        )));
+    }
+    let user_address = &deps.api.canonical_address(&user_address)?;
+    let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, user_address.as_slice()], &deps.storage);
+    let store = match AppendStore::<OrderEvent, _>::attach(&order_events) {
+        Some(store) => store?,
+        None => return to_binary(&QueryAnswer::OrderEvents {
+            order_events: vec![]
+        })
+    };
+
+    let order_events: StdResult<Vec<OrderEvent>> = match (page_size, page) {
+        (Some(page_size), Some(page)) => store.iter().skip((page * page_size) as _).take(page_size as _).collect(),
+        _ => store.iter().collect()
+    };
+
+    to_binary(&QueryAnswer::OrderEvents {
+        order_events: order_events?
+    })
+}
+
 fn check_order_book_trigger<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>
 ) -> StdResult<bool> {
-        let (order_id, limit_order_state) = get_limit_order_to_trigger(deps, true);
-        if order_id != None {
-            return Ok(true)
+        let status: ContractStatus = may_load(&deps.storage, CONTRACT_STATUS)?.unwrap_or(ContractStatus::Normal);
+        if status != ContractStatus::Normal {
+            return Ok(false)
         }
-        let (order_id, limit_order_state) = get_limit_order_to_trigger(deps, false);
-        if order_id != None {
-            return Ok(true)
+        for (is_bid, order_type) in ORDER_BOOKS.iter() {
+            // Queries have no block info so expired orders still count until they are pruned
+            let (order_index, _limit_order_state, _offer_amount) = get_limit_order_to_trigger(deps, *is_bid, order_type, None, &vec![], Uint128(0))?;
+            if order_index != None {
+                return Ok(true)
+            }
         }
         return Ok(false)
 }
 
+// Follows try_trigger_limit_orders without changing state, so keepers can weigh the fees against gas
+fn get_trigger_preview<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    max_orders: Option<u32>
+) -> StdResult<Vec<TriggerCandidate>> {
+    let token1_data: AssetInfo = load(&deps.storage, TOKEN1_DATA)?;
+    let token2_data: AssetInfo = load(&deps.storage, TOKEN2_DATA)?;
+    let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
+    let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address")?;
+    let amm_pair_hash: String = load(&amm_pair_data, b"hash")?;
+
+    let max_orders = max_orders.unwrap_or(1).min(MAX_ORDERS_PER_TRIGGER);
+    let mut candidates: Vec<TriggerCandidate> = vec![];
+    let mut triggered_orders: Vec<u64> = vec![];
+    let mut batch_offer_amounts: Vec<Uint128> = vec![Uint128(0), Uint128(0)];
+
+    while (candidates.len() as u32) < max_orders {
+        let mut order_triggered = false;
+        for (is_bid, order_type) in ORDER_BOOKS.iter() {
+            let offer_token_index: usize = if *is_bid { 1 } else { 0 };
+            // Queries have no block info so expired orders still count until they are pruned
+            let (order_index, limit_order_state, offer_amount) = get_limit_order_to_trigger(
+                deps,
+                *is_bid,
+                order_type,
+                None,
+                &triggered_orders,
+                batch_offer_amounts[offer_token_index]
+            )?;
+            if let (Some(order_index), Some(limit_order_state), Some(offer_amount)) = (order_index, limit_order_state, offer_amount) {
+                let offer_token_data: &AssetInfo = if *is_bid { &token2_data } else { &token1_data };
+                let offer_asset = amm_asset_info(offer_token_data);
+                let batch_offer_amount = batch_offer_amounts[offer_token_index];
+                let mut batch_return_amount = Uint128(0);
+                if batch_offer_amount > Uint128(0) {
+                    batch_return_amount = simulate_swap(deps, &offer_asset, batch_offer_amount, &amm_pair_hash, &amm_pair_address)?;
+                }
+                let deposit_fee = deposit_fee(&limit_order_state, offer_amount);
+                let swap_amount = (offer_amount - deposit_fee)?;
+                let simulated_return = (simulate_swap(deps, &offer_asset, batch_offer_amount + swap_amount, &amm_pair_hash, &amm_pair_address)? - batch_return_amount).unwrap_or(Uint128(0));
+                let fee_schedule = limit_order_state.fee_schedule.clone();
+                let fee_amount = match fee_schedule.source {
+                    FeeSource::Deposit => deposit_fee,
+                    FeeSource::Proceeds => fee_schedule.fee(offer_amount, limit_order_state.deposit_amount, simulated_return),
+                };
+
+                candidates.push(TriggerCandidate {
+                    is_bid: *is_bid,
+                    order_type: order_type.clone(),
+                    deposit_amount: limit_order_state.balances[limit_order_state.deposit_token_index as usize],
+                    offer_amount,
+                    simulated_return,
+                    expected_return: limit_order_state.expected_amount.multiply_ratio(offer_amount, limit_order_state.deposit_amount),
+                    fee_amount,
+                    fee_source: fee_schedule.source
+                });
+
+                triggered_orders.push(order_index.id);
+                if let Some(linked_order_id) = order_index.linked_order_id {
+                    triggered_orders.push(linked_order_id);
+                }
+                batch_offer_amounts[offer_token_index] = batch_offer_amount + swap_amount;
+                order_triggered = true;
+                break;
+            }
+        }
+        if !order_triggered {
+            break;
+        }
+    }
+
+    Ok(candidates)
+}
+
 
 pub fn get_limit_order_to_trigger<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    is_bid: bool
-) -> (Option<HumanAddr>, Option<LimitOrderState>) {
-    let mut order_book: OrderQueue;
+    is_bid: bool,
+    order_type: &OrderType,
+    block: Option<&BlockInfo>,
+    excluded_orders: &Vec<u64>,
+    batch_offer_amount: Uint128
+) -> StdResult<(Option<OrderIndex>, Option<LimitOrderState>, Option<Uint128>)> {
+    let order_book: OrderQueue;
     let amm_pair_data = ReadonlyPrefixedStorage::new(AMM_PAIR_DATA, &deps.storage);
-    let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address").unwrap();
-    let amm_pair_hash: String = load(&amm_pair_data, b"hash").unwrap();
-    let limit_orders_data = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS, &deps.storage);
-    let token1_data:AssetInfo = load(&deps.storage, TOKEN1_DATA).unwrap();
-    let token2_data:AssetInfo = load(&deps.storage, TOKEN2_DATA).unwrap();
+    let amm_pair_address: HumanAddr = load(&amm_pair_data, b"address")?;
+    let amm_pair_hash: String = load(&amm_pair_data, b"hash")?;
+    let token1_data:AssetInfo = load(&deps.storage, TOKEN1_DATA)?;
+    let token2_data:AssetInfo = load(&deps.storage, TOKEN2_DATA)?;
 
-    if is_bid {
-        order_book = load(&deps.storage, BID_ORDER_QUEUE).unwrap();
-    } else {
-        order_book = load(&deps.storage, ASK_ORDER_QUEUE).unwrap();
-    }
+    order_book = load(&deps.storage, order_queue_key(is_bid, order_type))?;
     
-    let asset1:AmmAssetInfo = AmmAssetInfo::Token {
-        contract_addr: token1_data.clone().token.unwrap().contract_addr,
-        token_code_hash: token1_data.clone().token.unwrap().token_code_hash,
-        viewing_key: "".to_string()
-    };
-
-    let asset2:AmmAssetInfo = AmmAssetInfo::Token {
-        contract_addr: token2_data.clone().token.unwrap().contract_addr,
-        token_code_hash: token2_data.clone().token.unwrap().token_code_hash,
-        viewing_key: "".to_string()
-    };
+    let asset1:AmmAssetInfo = amm_asset_info(&token1_data);
+    let asset2:AmmAssetInfo = amm_asset_info(&token2_data);
 
     // Simulate offering Token 1 with base unit of 1
     // Getting => X Token 2 per Token1 Price
@@ -761,58 +2488,459 @@ pub fn get_limit_order_to_trigger<S: Storage, A: Api, Q: Querier>(
             info: asset1.clone(),
             amount: token1_data.base_amount,
         }
-    }.query(&deps.querier, amm_pair_hash.clone(), amm_pair_address.clone()).unwrap();
+    }.query(&deps.querier, amm_pair_hash.clone(), amm_pair_address.clone())?;
+    let amm_price: Price = Price::from_base_amount(response_amm_base_simulation.return_amount, &token1_data, &token2_data)?;
+
+    // Simulations this search may run, cheap skips do not count
+    let scan_limit: u32 = may_load(&deps.storage, SCAN_LIMIT)?.unwrap_or(DEFAULT_SCAN_LIMIT);
+    let mut scanned_orders: u32 = 0;
+    // Smallest offers that did not fill, with the expected and deposit amounts giving their rate
+    let mut unfillable_offers: Vec<(Uint128, Uint128, Uint128)> = vec![];
+
+    for order_book_peek in order_book.orders(&deps.storage) {
+        let order_book_peek: OrderIndex = order_book_peek?;
+        // Skip expired orders, they get refunded instead of swapped
+        if let (Some(block), Some(expires_at)) = (block, order_book_peek.expires_at.as_ref()) {
+            if expires_at.is_expired(block) {
+                continue;
+            }
+        }
+        // Skip orders already swapped by the current batch
+        if excluded_orders.contains(&order_book_peek.id) {
+            continue;
+        }
+
+        // Limit orders wait for a better price, stop orders for the price to move through them
+        let would_trigger_base_price: bool = match (order_type, is_bid) {
+            (OrderType::Limit, true) => order_book_peek.price >= amm_price,
+            (OrderType::Limit, false) => order_book_peek.price <= amm_price,
+            (OrderType::Stop, true) => order_book_peek.price <= amm_price,
+            (OrderType::Stop, false) => order_book_peek.price >= amm_price,
+        };
+        if !would_trigger_base_price {
+            // Breaks the cycle because the orderbook is ordered and if the base price would not trigger this order, the others would not trigger too
+            break;
+        }
+
+        let order_book_owner_canonical = &deps.api.canonical_address(&order_book_peek.owner)?;
+        let limit_order_data: LimitOrderState = match load_active_limit_order(&deps.storage, order_book_owner_canonical, order_book_peek.id)? {
+            Some(limit_order_data) => limit_order_data,
+            None => return Err(StdError::generic_err(format!(
+                "Queued order {} has no active limit order!",
+                order_book_peek.id
+            )))
+        };
+        if limit_order_data.status == OrderStatus::Executing
+            || is_linked_order_executing(&deps.storage, order_book_owner_canonical, &limit_order_data)? {
+            continue;
+        }
+        let asset: AmmAssetInfo;
+        let min_offer_amount: Uint128;
+
+        if is_bid {
+            asset = asset2.clone();
+            min_offer_amount = token2_data.min_amount;
+        } else {
+            asset = asset1.clone();
+            min_offer_amount = token1_data.min_amount;
+        }
 
-    for _ in 1..10 { // Max limit of 10 limit orders to check
-        // Peek order, compare price of the limit order with the simulated one
-        if let Some(order_book_peek) = order_book.peek() {
-            let would_trigger_base_price: bool;
-            if is_bid {
-                would_trigger_base_price = order_book_peek.price >= response_amm_base_simulation.return_amount;
-            } else {
-                would_trigger_base_price = order_book_peek.price <= response_amm_base_simulation.return_amount;
+        // The pair pays less per unit the more is offered, so an order that needs at least the rate of an
+        // unfillable offer for at least as much can not fill either and is skipped without simulating it
+        let remaining_amount: Uint128 = limit_order_data.balances[limit_order_data.deposit_token_index as usize];
+        if remaining_amount == Uint128(0) {
+            continue;
+        }
+        let smallest_offer_amount: Uint128 = if (remaining_amount - min_offer_amount).unwrap_or(Uint128(0)) >= min_offer_amount { min_offer_amount } else { remaining_amount };
+        // Fees taken from the deposit change what is swapped, so those orders are always simulated
+        let is_proceeds_fee = limit_order_data.fee_schedule.source == FeeSource::Proceeds;
+        let is_dominated = is_proceeds_fee && unfillable_offers.iter().any(|(offer_amount, expected_amount, deposit_amount)| {
+            smallest_offer_amount >= *offer_amount
+                && limit_order_data.expected_amount >= expected_amount.multiply_ratio(limit_order_data.deposit_amount, *deposit_amount)
+        });
+        if is_dominated {
+            continue;
+        }
+
+        if scanned_orders >= scan_limit {
+            break;
+        }
+        scanned_orders += 1;
+
+        // Here we have the final simulation for this with slippage
+        // Find the largest part of the remaining deposit that still returns at least the order's price
+        let offer_amount = get_fillable_amount(
+            deps,
+            &limit_order_data,
+            &asset,
+            min_offer_amount,
+            batch_offer_amount,
+            &amm_pair_hash,
+            &amm_pair_address
+        )?;
+
+        if let Some(offer_amount) = offer_amount {
+            //This order is elligible for a (partial) trigger so return it
+            return Ok((Some(order_book_peek), Some(limit_order_data), Some(offer_amount)))
+        }
+        // Not even the smallest offer filled, lower amount limit orders can still be on the order book
+        if is_proceeds_fee {
+            unfillable_offers.push((smallest_offer_amount, limit_order_data.expected_amount, limit_order_data.deposit_amount));
+        }
+    }
+
+    Ok((None, None, None))
+} 
+
+// Returns how much of the order's remaining deposit can be swapped on the pair right now
+// while still receiving at least the order's expected amount pro-rata.
+// The whole remainder is tried first, then a bounded binary search looks for the largest slice.
+fn get_fillable_amount<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    limit_order: &LimitOrderState,
+    offer_asset: &AmmAssetInfo,
+    min_offer_amount: Uint128,
+    batch_offer_amount: Uint128,
+    amm_pair_hash: &String,
+    amm_pair_address: &HumanAddr
+) -> StdResult<Option<Uint128>> {
+    let remaining_amount: Uint128 = limit_order.balances[limit_order.deposit_token_index as usize];
+    let simulate = |offer_amount: Uint128| -> StdResult<Uint128> {
+        simulate_swap(deps, offer_asset, offer_amount, amm_pair_hash, amm_pair_address)
+    };
+    // Swaps sent earlier in the same batch run first, so this one only gets what the pair pays on top of them
+    let mut batch_return_amount = Uint128(0);
+    if batch_offer_amount > Uint128(0) {
+        batch_return_amount = simulate(batch_offer_amount)?;
+    }
+    let would_fill = |offer_amount: Uint128| -> StdResult<bool> {
+        let swap_amount = (offer_amount - deposit_fee(limit_order, offer_amount)).unwrap_or(Uint128(0));
+        let return_amount = (simulate(batch_offer_amount + swap_amount)? - batch_return_amount).unwrap_or(Uint128(0));
+        let expected_amount = limit_order.expected_amount.multiply_ratio(offer_amount, limit_order.deposit_amount);
+        Ok(expected_amount <= return_amount)
+    };
+
+    if remaining_amount == Uint128(0) {
+        return Ok(None);
+    }
+    if would_fill(remaining_amount)? {
+        return Ok(Some(remaining_amount));
+    }
+
+    // A partial fill must leave a remainder that can still be filled or canceled later
+    let max_partial_amount = (remaining_amount - min_offer_amount).unwrap_or(Uint128(0));
+    if max_partial_amount < min_offer_amount || !would_fill(min_offer_amount)? {
+        return Ok(None);
+    }
+
+    let mut fillable_amount = min_offer_amount;
+    let mut unfillable_amount = max_partial_amount;
+    if would_fill(max_partial_amount)? {
+        return Ok(Some(max_partial_amount));
+    }
+    for _ in 0..PARTIAL_FILL_SEARCH_STEPS {
+        let middle_amount = Uint128((fillable_amount.u128() + unfillable_amount.u128()) / 2);
+        if middle_amount == fillable_amount {
+            break;
+        }
+        if would_fill(middle_amount)? {
+            fillable_amount = middle_amount;
+        } else {
+            unfillable_amount = middle_amount;
+        }
+    }
+
+    Ok(Some(fillable_amount))
+}
+
+// What the pair returns for offer_amount of offer_asset
+fn simulate_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offer_asset: &AmmAssetInfo,
+    offer_amount: Uint128,
+    amm_pair_hash: &String,
+    amm_pair_address: &HumanAddr
+) -> StdResult<Uint128> {
+    let response_amm_order_simulation: AmmPairSimulationResponse =
+        AmmSimulationQuery::simulation {
+            offer_asset: AmmSimulationOfferAsset{
+                info: offer_asset.clone(),
+                amount: offer_amount
             }
-            
-            if would_trigger_base_price {
-                // Now we know that this order is a candidate to trigger but need to simulate again with his amount 
-                // Simulate offering N amount of Token1
-                // Getting => X Token2 per N Token1
-                let order_book_id_canonical = &deps.api.canonical_address(&order_book_peek.id).unwrap();
-                let limit_order_data: Option<LimitOrderState> = may_load(&limit_orders_data, order_book_id_canonical.as_slice()).unwrap();
-                //let amount: Uint128 = limit_order_data.clone().unwrap().deposit_amount;
-                let simulated: Uint128;
-                let asset: AmmAssetInfo;
-
-                if is_bid { asset = asset2.clone()} 
-                else { asset = asset1.clone()} 
-
-                // Here we have the final simulation for this with slippage
-                // Check if deposited amount is <= simulated amount that comes from the swap
-                let response_amm_order_simulation: AmmPairSimulationResponse =
-                    AmmSimulationQuery::simulation {
-                        offer_asset: AmmSimulationOfferAsset{
-                            info: asset.clone(),
-                            amount: limit_order_data.clone().unwrap().deposit_amount
-                        }
-                    }.query(&deps.querier, amm_pair_hash.clone(), amm_pair_address.clone()).unwrap();
-
-                simulated = response_amm_order_simulation.return_amount;
-                let would_trigger_total_amount = limit_order_data.clone().unwrap().expected_amount <= simulated;
-               
-                if would_trigger_total_amount {
-                    //This order is elligible for a trigger so return it
-                    return (Some(order_book_peek.clone().id), Some(limit_order_data.clone().unwrap()))
-                } else {
-                    // pop current order from the orderbook as it's not elligible for triggering
-                    order_book.pop();
-                    // continues the loop as lower amount limit orders can be on the order book
-                }
-            } else {
-                // Breaks the cycle because the orderbook is ordered and if the base price would not trigger this order, the others would not trigger too
-                break;
+        }.query(&deps.querier, amm_pair_hash.clone(), amm_pair_address.clone())?;
+    Ok(response_amm_order_simulation.return_amount)
+}
+
+// Asset as the pair knows it, a wrapped native token is held by the pair as the native coin
+fn amm_asset_info(token_info: &AssetInfo) -> AmmAssetInfo {
+    match &token_info.native_token {
+        Some(native_token) => AmmAssetInfo::NativeToken {
+            denom: native_token.denom.clone()
+        },
+        None => {
+            let token = token_info.token.clone().unwrap();
+            AmmAssetInfo::Token {
+                contract_addr: token.contract_addr,
+                token_code_hash: token.token_code_hash,
+                viewing_key: "".to_string()
             }
         }
     }
+}
+
+// Keeper fee of the side, the flat fee of the bought token unless the factory set a schedule
+fn load_fee_schedule<S: ReadonlyStorage>(storage: &S, is_bid: bool) -> StdResult<FeeSchedule> {
+    let fee_schedule_key = if is_bid { BID_FEE_SCHEDULE } else { ASK_FEE_SCHEDULE };
+    if let Some(fee_schedule) = may_load(storage, fee_schedule_key)? {
+        return Ok(fee_schedule);
+    }
+    let bought_token_info: AssetInfo = load(storage, if is_bid { TOKEN1_DATA } else { TOKEN2_DATA })?;
+    Ok(FeeSchedule::flat(bought_token_info.fee_amount))
+}
+
+// Part of offer_amount kept out of the swap to pay the keeper
+fn deposit_fee(limit_order: &LimitOrderState, offer_amount: Uint128) -> Uint128 {
+    match limit_order.fee_schedule.source {
+        FeeSource::Deposit => limit_order.fee_schedule.fee(offer_amount, limit_order.deposit_amount, offer_amount),
+        FeeSource::Proceeds => Uint128(0),
+    }
+}
+
+fn check_not_executing(limit_order: &LimitOrderState) -> StdResult<()> {
+    if limit_order.status == OrderStatus::Executing {
+        return Err(StdError::generic_err(format!(
+            "Limit order is waiting for its swap to settle."
+        )));
+    }
+    Ok(())
+}
+
+// Both legs of an OCO order share the deposit, so one leg can not fill while the other is swapping
+fn is_linked_order_executing<S: ReadonlyStorage>(storage: &S, owner: &CanonicalAddr, limit_order: &LimitOrderState) -> StdResult<bool> {
+    match limit_order.linked_order_id {
+        Some(linked_order_id) => Ok(load_active_limit_order(storage, owner, linked_order_id)?
+            .map_or(false, |linked_limit_order| linked_limit_order.status == OrderStatus::Executing)),
+        None => Ok(false)
+    }
+}
+
+fn set_order_status(limit_order: &mut LimitOrderState, status: OrderStatus) -> StdResult<()> {
+    if !limit_order.status.can_transition_to(&status) {
+        return Err(StdError::generic_err(format!(
+            "Limit order {} can not go from {:?} to {:?}.",
+            limit_order.id,
+            limit_order.status,
+            status
+        )));
+    }
+    limit_order.status = status;
+    Ok(())
+}
+
+// Adds the fees of a fill to what the keeper can claim, a keeper's first fee also lists them for the migration
+pub fn credit_keeper_reward<S: Storage>(storage: &mut S, keeper: &CanonicalAddr, amounts: &Vec<Uint128>) -> StdResult<()> {
+    let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, storage);
+    let rewards: Option<Vec<Uint128>> = may_load(&keeper_rewards, keeper.as_slice())?;
+    if rewards == None {
+        let mut keepers_storage = PrefixedStorage::new(KEEPERS, storage);
+        let mut keepers = AppendStoreMut::attach_or_create(&mut keepers_storage)?;
+        keepers.push(keeper)?;
+    }
+    let mut keeper_rewards = PrefixedStorage::new(KEEPER_REWARDS, storage);
+    save(&mut keeper_rewards, keeper.as_slice(), &add_balances(rewards, amounts))
+}
+
+fn add_balances(balances: Option<Vec<Uint128>>, amounts: &Vec<Uint128>) -> Vec<Uint128> {
+    let balances = balances.unwrap_or(vec![Uint128(0),Uint128(0)]);
+    balances.iter().zip(amounts.iter()).map(|(balance, amount)| *balance + *amount).collect()
+}
+
+fn refund_balances<S: ReadonlyStorage>(
+    storage: &S,
+    contract_address: &HumanAddr,
+    recipient: &HumanAddr,
+    balances: &Vec<Uint128>
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (token_key, balance) in [TOKEN1_DATA, TOKEN2_DATA].iter().zip(balances.iter()) {
+        if *balance > Uint128(0) {
+            let token_info: AssetInfo = load(storage, token_key)?;
+            messages.extend(payout_msgs(&token_info, contract_address, recipient, *balance)?);
+        }
+    }
+    Ok(messages)
+}
+
+fn load_native_token_info<S: ReadonlyStorage>(storage: &S) -> StdResult<AssetInfo> {
+    let token1_info: AssetInfo = load(storage, TOKEN1_DATA)?;
+    let token2_info: AssetInfo = load(storage, TOKEN2_DATA)?;
+    match (&token1_info.native_token, &token2_info.native_token) {
+        (Some(_), _) => Ok(token1_info),
+        (_, Some(_)) => Ok(token2_info),
+        _ => Err(StdError::generic_err(format!(
+            "This order book has no native token!"
+        )))
+    }
+}
+
+// Wraps native coins held by the order book into its wrapper token
+fn deposit_native_msg(wrapper_token: &Token, coins: Vec<Coin>) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: wrapper_token.contract_addr.clone(),
+        callback_code_hash: wrapper_token.token_code_hash.clone(),
+        msg: padded_binary(&Snip20Msg::deposit())?,
+        send: coins,
+    }))
+}
+
+// Pays amount of a token to recipient, a wrapped native token is redeemed and sent as the native coin
+fn payout_msgs(
+    token_info: &AssetInfo,
+    contract_address: &HumanAddr,
+    recipient: &HumanAddr,
+    amount: Uint128
+) -> StdResult<Vec<CosmosMsg>> {
+    let token = token_info.token.clone().unwrap();
+    let native_token = match &token_info.native_token {
+        Some(native_token) => native_token,
+        None => {
+            return Ok(vec![transfer_msg(
+                recipient.clone(),
+                amount,
+                None,
+                BLOCK_SIZE,
+                token.token_code_hash,
+                token.contract_addr
+            )?]);
+        }
+    };
+    // Bank sends of zero coins fail
+    if amount == Uint128(0) {
+        return Ok(vec![]);
+    }
+
+    // The wrapper sends the coins to this contract before they are forwarded
+    Ok(vec![
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.contract_addr,
+            callback_code_hash: token.token_code_hash,
+            msg: padded_binary(&Snip20Msg::redeem(amount))?,
+            send: vec![],
+        }),
+        CosmosMsg::Bank(BankMsg::Send {
+            from_address: contract_address.clone(),
+            to_address: recipient.clone(),
+            amount: vec![Coin {
+                denom: native_token.denom.clone(),
+                amount,
+            }],
+        }),
+    ])
+}
+
+// Messages built here instead of through HandleCallback are space padded the same way,
+// so their size does not tell which action sent them
+pub fn padded_binary<T: Serialize>(msg: &T) -> StdResult<Binary> {
+    let mut binary = to_binary(msg)?;
+    space_pad(&mut binary.0, BLOCK_SIZE);
+    Ok(binary)
+}
+
+// Adds the event to the private log of the owner, order events are not logged publicly
+fn record_order_event<S: Storage>(
+    storage: &mut S,
+    env: &Env,
+    owner: &CanonicalAddr,
+    action: OrderEventAction,
+    limit_order: &LimitOrderState,
+    amounts: Vec<Uint128>,
+    fees: Vec<Uint128>
+) -> StdResult<()> {
+    let mut order_events = PrefixedStorage::multilevel(&[ORDER_EVENTS, owner.as_slice()], storage);
+    let mut user_order_events = AppendStoreMut::attach_or_create(&mut order_events)?;
+    user_order_events.push(&OrderEvent {
+        action,
+        order_id: limit_order.id,
+        is_bid: limit_order.is_bid,
+        amounts,
+        fees,
+        block_height: env.block.height,
+        timestamp: env.block.time
+    })
+}
+
+// Assigns the next sequential order id
+fn next_order_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let order_id: u64 = load::<u64, _>(storage, LAST_ORDER_ID)? + 1;
+    save(storage, LAST_ORDER_ID, &order_id)?;
+    Ok(order_id)
+}
+
+// Sequence numbers give orders at the same price a strict first in, first out priority
+fn next_sequence<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let sequence: u64 = load::<u64, _>(storage, LAST_SEQUENCE)? + 1;
+    save(storage, LAST_SEQUENCE, &sequence)?;
+    Ok(sequence)
+}
+
+fn load_active_limit_order<S: ReadonlyStorage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+    order_id: u64
+) -> StdResult<Option<LimitOrderState>> {
+    let active_limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, owner.as_slice()], storage);
+    may_load(&active_limit_orders, &order_id.to_be_bytes())
+}
+
+fn save_active_limit_order<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    limit_order: &LimitOrderState
+) -> StdResult<()> {
+    let mut user_orders = PrefixedStorage::new(USER_ORDERS, storage);
+    let mut user_order_map: UserOrderMap = may_load(&user_orders, owner.as_slice())?.unwrap_or(UserOrderMap {
+        active_orders: vec![]
+    });
+    if !user_order_map.active_orders.contains(&limit_order.id) {
+        user_order_map.active_orders.push(limit_order.id);
+        save(&mut user_orders, owner.as_slice(), &user_order_map)?;
+    }
+
+    let mut active_limit_orders = PrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, owner.as_slice()], storage);
+    save(&mut active_limit_orders, &limit_order.id.to_be_bytes(), limit_order)
+}
+
+// Removes a limit order from active and from its queue and adds it to the owner's history
+fn retire_limit_order<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    limit_order: &LimitOrderState
+) -> StdResult<()> {
+    remove_active_limit_order(storage, owner, limit_order.id)?;
+
+    let queue_key = order_queue_key(limit_order.is_bid, &limit_order.order_type);
+    let mut order_book: OrderQueue = load(storage, queue_key)?;
+    order_book.remove(storage, limit_order.id)?;
+    save(storage, queue_key, &order_book)?;
+
+    let mut history_limit_orders = PrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, owner.as_slice()], storage);
+    let mut user_history_orders = AppendStoreMut::attach_or_create(&mut history_limit_orders)?;
+    user_history_orders.push(limit_order)?;
+    Ok(())
+}
+
+fn remove_active_limit_order<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    order_id: u64
+) -> StdResult<()> {
+    let mut user_orders = PrefixedStorage::new(USER_ORDERS, storage);
+    if let Some(mut user_order_map) = may_load::<UserOrderMap, _>(&user_orders, owner.as_slice())? {
+        user_order_map.active_orders.retain(|active_order_id| *active_order_id != order_id);
+        save(&mut user_orders, owner.as_slice(), &user_order_map)?;
+    }
 
-    return (None, None);
-} 
\ No newline at end of file
+    let mut active_limit_orders = PrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, owner.as_slice()], storage);
+    remove(&mut active_limit_orders, &order_id.to_be_bytes());
+    Ok(())
+}
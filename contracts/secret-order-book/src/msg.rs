@@ -1,7 +1,9 @@
-use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr, Uint128};
+use std::{fmt, str::FromStr};
+
+use cosmwasm_std::{Binary, BlockInfo, CanonicalAddr, HumanAddr, StdError, StdResult, Uint128};
 use schemars::JsonSchema;
 use secret_toolkit::utils::{HandleCallback, Query};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{contract::BLOCK_SIZE};
 
@@ -13,7 +15,9 @@ pub struct InitMsg {
     pub token1_info: AssetInfo,
     pub token2_info: AssetInfo,
     pub amm_pair_contract_address: HumanAddr,
-    pub amm_pair_contract_hash: String
+    pub amm_pair_contract_hash: String,
+    /// Order book whose orders are imported into this one, which stays Migrating until they all arrived
+    pub migrate_from: Option<MigrationContract>
 }
 
 // Messages sent to SNIP-20 contracts
@@ -28,20 +32,29 @@ pub enum Snip20Msg {
         amount: Uint128,
         padding: Option<String>,
     },
+    Deposit {
+        padding: Option<String>,
+    },
 }
 
 impl Snip20Msg {
     pub fn register_receive(code_hash: String) -> Self {
         Snip20Msg::RegisterReceive {
             code_hash,
-            padding: None, // TODO add padding calculation
+            padding: None, // the serialized message is space padded by padded_binary
         }
     }
 
     pub fn redeem(amount: Uint128) -> Self {
         Snip20Msg::Redeem {
             amount,
-            padding: None, // TODO add padding calculation
+            padding: None, // the serialized message is space padded by padded_binary
+        }
+    }
+
+    pub fn deposit() -> Self {
+        Snip20Msg::Deposit {
+            padding: None, // the serialized message is space padded by padded_binary
         }
     }
 }
@@ -64,21 +77,151 @@ impl HandleCallback for FactoryHandleMsg {
     const BLOCK_SIZE: usize = BLOCK_SIZE;
 }
 
+/// Handle messages an order book that is migrating sends to its new order book
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationHandleMsg {
+    ImportLimitOrders {
+        limit_orders: Vec<MigratedLimitOrder>,
+        last_order_id: u64,
+        last_sequence: u64,
+        /// Protocol fees of the old order book, sent with the last orders
+        treasury: Vec<Uint128>,
+        /// Settings of the old order book, sent with the first orders
+        config: Option<MigratedConfig>,
+        finished: bool
+    },
+    ImportHistoryLimitOrders {
+        histories: Vec<MigratedHistory>
+    },
+    ImportKeeperRewards {
+        keeper_rewards: Vec<MigratedKeeperRewards>
+    }
+}
+
+impl HandleCallback for MigrationHandleMsg {
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
     Receive{ sender: HumanAddr, from: HumanAddr, amount: Uint128, msg: Option<Binary> },
+    /// Same messages as Receive but paid in the native coin of the pair, which is wrapped before use
+    ReceiveNativeToken { msg: Binary },
     CreateLimitOrder {
         is_bid: bool,
-        price: Uint128,
+        price: Price,
+        expected_amount: Uint128,
+        order_type: Option<OrderType>,
+        expires_at: Option<Expiration>
+    },
+    /// Take profit limit and stop loss legs backed by the same deposit,
+    /// the first leg to fill cancels the other one
+    CreateOcoOrder {
+        is_bid: bool,
+        limit_price: Price,
+        limit_expected_amount: Uint128,
+        stop_price: Price,
+        stop_expected_amount: Uint128,
+        expires_at: Option<Expiration>
+    },
+    /// Tops up the deposit of an active order, sent through Receive in its deposit token
+    IncreaseLimitOrder {
+        order_id: u64
+    },
+    UpdateLimitOrder {
+        order_id: u64,
+        price: Price,
         expected_amount: Uint128
     },
-    CancelLimitOrder {},
-    TriggerLimitOrders {},
+    CancelLimitOrder {
+        order_id: u64
+    },
+    TriggerLimitOrders {
+        max_orders: Option<u32>
+    },
+    /// Puts orders whose swap timed out back on the book, anyone can call it
+    UnlockTimedOutSwaps {},
+    /// Books the native coins the pair paid for a swap to its order, only sent by the order book itself after the swap
+    SettleNativeSwap {},
+    /// Refunds up to limit expired orders to their owners, anyone can call it
+    PruneExpiredOrders {
+        limit: Option<u32>
+    },
     ChangeFee {
         token_index: i8,
         min_amount: Uint128,
         fee_amount: Uint128
+    },
+    /// Enables the OrderBookDepth query with the given bands, or disables it with None. Only the factory can call it
+    ChangeDepthConfig {
+        depth_config: Option<DepthConfig>
+    },
+    /// Sets how many orders a trigger search simulates before giving up. Only the factory can call it
+    ChangeScanLimit {
+        scan_limit: u32
+    },
+    /// Sets the keeper fee of new bids or asks, None goes back to the flat fee of the bought token.
+    /// Only the factory can call it
+    ChangeFeeSchedule {
+        is_bid: bool,
+        fee_schedule: Option<FeeSchedule>
+    },
+    /// Pays the sender the triggerer fees booked to them over all their fills
+    ClaimKeeperRewards {},
+    /// Sets the share of each fill kept in the treasury, in basis points. Only the factory can call it
+    ChangeProtocolFee {
+        basis_points: u16
+    },
+    /// Sends the whole treasury to recipient. Only the factory can call it
+    WithdrawTreasury {
+        recipient: HumanAddr
+    },
+    /// Pauses triggering or everything but withdrawals. Only the factory can call it,
+    /// a migrating order book takes the status once its orders are imported
+    SetContractStatus {
+        status: ContractStatus
+    },
+    /// Drains the order book into the order book that replaces it. Only the factory can call it
+    StartMigration {
+        contract_address: HumanAddr,
+        contract_hash: String
+    },
+    /// Moves up to limit active orders, best first, and their deposits to the new order book, the treasury goes with the last ones.
+    /// Swaps sent to the pair have to settle first. Anyone can call it while migrating
+    ExportLimitOrders {
+        limit: Option<u32>
+    },
+    /// Copies up to limit history orders and order events of the owners not exported yet to the new
+    /// order book. Anyone can call it while migrating
+    ExportHistoryLimitOrders {
+        owners: Vec<HumanAddr>,
+        limit: Option<u32>
+    },
+    /// Moves the unclaimed rewards of up to limit keepers not exported yet to the new order book.
+    /// Swaps sent to the pair have to settle first. Anyone can call it while migrating
+    ExportKeeperRewards {
+        limit: Option<u32>
+    },
+    /// Sent by the order book being migrated, after the deposits of the orders
+    ImportLimitOrders {
+        limit_orders: Vec<MigratedLimitOrder>,
+        last_order_id: u64,
+        last_sequence: u64,
+        /// Protocol fees of the old order book, sent with the last orders
+        treasury: Vec<Uint128>,
+        /// Settings of the old order book, sent with the first orders
+        config: Option<MigratedConfig>,
+        finished: bool
+    },
+    /// Sent by the order book being migrated
+    ImportHistoryLimitOrders {
+        histories: Vec<MigratedHistory>
+    },
+    /// Sent by the order book being migrated, after the keeper rewards
+    ImportKeeperRewards {
+        keeper_rewards: Vec<MigratedKeeperRewards>
     }
 }
 
@@ -93,6 +236,22 @@ pub enum HandleAnswer {
         #[serde(skip_serializing_if = "Option::is_none")]
         message: Option<String>,
     },
+    /// response from creating a limit order
+    CreateLimitOrder {
+        /// success or failure
+        status: ResponseStatus,
+        /// id assigned to the new limit order
+        order_id: u64,
+    },
+    /// response from creating a one-cancels-other order
+    CreateOcoOrder {
+        /// success or failure
+        status: ResponseStatus,
+        /// id assigned to the take profit leg
+        limit_order_id: u64,
+        /// id assigned to the stop loss leg
+        stop_order_id: u64,
+    },
 }
 /// success or failure response
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
@@ -106,6 +265,11 @@ pub enum ResponseStatus {
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     GetActiveLimitOrder {
+        user_address: HumanAddr,
+        user_viewkey: String,
+        order_id: u64
+    },
+    GetActiveLimitOrders {
         user_address: HumanAddr,
         user_viewkey: String
     },
@@ -115,8 +279,29 @@ pub enum QueryMsg {
         page_size: Option<u32>,
         page: Option<u32>
     },
+    /// Private event log of the user: their orders created, filled, updated, topped up, canceled and expired, oldest first
+    GetOrderEvents {
+        user_address: HumanAddr,
+        user_viewkey: String,
+        page_size: Option<u32>,
+        page: Option<u32>
+    },
     CheckOrderBookTrigger {},
-    OrderBookPairInfo {}
+    OrderBookPairInfo {},
+    /// Bid and ask volume grouped in price bands, only when enabled with ChangeDepthConfig
+    OrderBookDepth {},
+    /// Triggerer fees a keeper can claim, in token1 and token2
+    KeeperRewards {
+        user_address: HumanAddr,
+        user_viewkey: String
+    },
+    /// Protocol fees collected so far, in token1 and token2
+    TreasuryBalances {},
+    ContractStatus {},
+    /// Orders a TriggerLimitOrders with the same max_orders would swap right now
+    TriggerPreview {
+        max_orders: Option<u32>
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
@@ -125,15 +310,94 @@ pub enum QueryAnswer {
     ActiveLimitOrder {
         active_limit_order: Option<LimitOrderState>
     },
+    ActiveLimitOrders {
+        active_limit_orders: Vec<LimitOrderState>
+    },
     HistoryLimitOrders {
         history_limit_orders: Vec<LimitOrderState>
     },
+    OrderEvents {
+        order_events: Vec<OrderEvent>
+    },
     OrderBookPair {
         amm_pair_address: HumanAddr,
         assets_info: [AssetInfo;2]
+    },
+    OrderBookDepth {
+        band_width: Price,
+        bids: Vec<DepthBand>,
+        asks: Vec<DepthBand>
+    },
+    TriggerPreview {
+        candidates: Vec<TriggerCandidate>
+    },
+    KeeperRewards {
+        rewards: Vec<Uint128>
+    },
+    TreasuryBalances {
+        protocol_fee_basis_points: u16,
+        balances: Vec<Uint128>
+    },
+    ContractStatus {
+        status: ContractStatus
     }
 }
 
+/// Swap a trigger would send for one order, in batch order.
+/// Bids offer token2 and are paid in token1, asks the other way around.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TriggerCandidate {
+    pub is_bid: bool,
+    pub order_type: OrderType,
+    /// Deposit left on the order
+    pub deposit_amount: Uint128,
+    /// Part of the deposit that is used, fee taken from the deposit included
+    pub offer_amount: Uint128,
+    /// Pair return for what is swapped after the candidates before it
+    pub simulated_return: Uint128,
+    /// Least return the swap accepts
+    pub expected_return: Uint128,
+    /// Booked to the triggerer, in the deposited or the bought token depending on fee_source
+    pub fee_amount: Uint128,
+    pub fee_source: FeeSource
+}
+
+/// Entry of the event log of an order owner, which only they can read with their viewing key.
+/// Public logs only carry the action, the side and the pair.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderEvent {
+    pub action: OrderEventAction,
+    pub order_id: u64,
+    pub is_bid: bool,
+    /// In token1 and token2: the deposit on create, the amount given and the amount received
+    /// after fees on fill, the balances left on update, the top up on increase, the refund on
+    /// cancel and expiry
+    pub amounts: Vec<Uint128>,
+    /// In token1 and token2, the triggerer and protocol fees kept from a fill
+    pub fees: Vec<Uint128>,
+    pub block_height: u64,
+    pub timestamp: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventAction {
+    Create,
+    Fill,
+    Cancel,
+    Update,
+    Increase,
+    Expire,
+}
+
+/// Volume left on the orders priced from price up to price + band_width.
+/// Bid volume is in token2 and ask volume in token1.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepthBand {
+    pub price: Price,
+    pub volume: Uint128
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct IsKeyValidResponse {
     pub is_key_valid: IsKeyValid  
@@ -207,6 +471,27 @@ impl Query for AmmSimulationQuery {
     const BLOCK_SIZE: usize = BLOCK_SIZE;
 }
 
+/// Hook sent to the pair with the offered tokens, the pair reverts if it would return less than expected_return
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmmPairReceiveMsg {
+    Swap {
+        expected_return: Option<Uint128>,
+        to: Option<HumanAddr>
+    }
+}
+
+/// Swap of native coins sent to the pair with the coins attached, the pair reverts if it would return less than expected_return
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmmPairHandleMsg {
+    Swap {
+        offer_asset: AmmSimulationOfferAsset,
+        expected_return: Option<Uint128>,
+        to: Option<HumanAddr>
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AmmPairSimulationResponse {
     pub return_amount: Uint128,
@@ -227,26 +512,388 @@ pub struct ActiveLimitOrderQueryResponse {
 }
 
 // State
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepthConfig {
+    pub band_width: Price,
+    // Bands with fewer orders than this are left out so no single order can be told apart
+    pub min_orders_per_band: u32
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct UserOrderMap {
-    pub active_order: Option<Uint128>,
-    pub history_orders: Vec<Uint128>
+    pub active_orders: Vec<u64>
+}
+
+/// What an order book still accepts, set by the factory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Normal,
+    /// No order is swapped, orders can still be placed, changed and canceled
+    StopTriggering,
+    /// Orders can only be canceled or refunded, swaps already sent still settle
+    WithdrawOnly,
+    /// Same as WithdrawOnly while orders move to a new order book, the factory can not change it
+    Migrating,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationContract {
+    pub address: HumanAddr,
+    pub code_hash: String
+}
+
+/// Active order moved to a new order book, with its place in the queue
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigratedLimitOrder {
+    pub owner: HumanAddr,
+    pub sequence: u64,
+    pub limit_order: LimitOrderState
+}
+
+/// Settings the factory made on an order book, so its new order book runs with the same ones
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigratedConfig {
+    /// Triggerer fees and minimum order amounts of token1 and token2
+    pub fee_amounts: Vec<Uint128>,
+    pub min_amounts: Vec<Uint128>,
+    pub protocol_fee: u16,
+    pub bid_fee_schedule: Option<FeeSchedule>,
+    pub ask_fee_schedule: Option<FeeSchedule>,
+    pub scan_limit: Option<u32>,
+    pub depth_config: Option<DepthConfig>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigratedHistory {
+    pub owner: HumanAddr,
+    pub limit_orders: Vec<LimitOrderState>,
+    pub order_events: Vec<OrderEvent>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigratedKeeperRewards {
+    pub keeper: HumanAddr,
+    /// Triggerer fees the keeper had not claimed yet, in token1 and token2
+    pub rewards: Vec<Uint128>
+}
+
+/// Limit orders trigger when the AMM price gets better than their price,
+/// stop orders when it moves through their price against them
+/// (their expected_amount is then the minimum return accepted once triggered)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    Limit,
+    Stop,
+}
+
+/// Lifecycle of a limit order, see can_transition_to for the allowed moves
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Active,
+    Executing,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Expired,
+}
+
+impl OrderStatus {
+    pub fn can_transition_to(&self, next: &OrderStatus) -> bool {
+        match (self, next) {
+            // Matched on the book, swapped, canceled or expired
+            (OrderStatus::Active, OrderStatus::Executing)
+            | (OrderStatus::Active, OrderStatus::PartiallyFilled)
+            | (OrderStatus::Active, OrderStatus::Filled)
+            | (OrderStatus::Active, OrderStatus::Canceled)
+            | (OrderStatus::Active, OrderStatus::Expired) => true,
+            // Swap settled or unlocked after a timeout
+            (OrderStatus::Executing, OrderStatus::Active)
+            | (OrderStatus::Executing, OrderStatus::PartiallyFilled)
+            | (OrderStatus::Executing, OrderStatus::Filled) => true,
+            // A partially filled order is still on the book
+            (OrderStatus::PartiallyFilled, OrderStatus::Executing)
+            | (OrderStatus::PartiallyFilled, OrderStatus::PartiallyFilled)
+            | (OrderStatus::PartiallyFilled, OrderStatus::Filled)
+            | (OrderStatus::PartiallyFilled, OrderStatus::Canceled)
+            | (OrderStatus::PartiallyFilled, OrderStatus::Expired) => true,
+            // Filled, Canceled and Expired orders are final
+            _ => false,
+        }
+    }
+}
+
+// Basis points in a whole
+pub const BASIS_POINTS: u16 = 10000;
+
+/// Where the keeper fee of an order is taken from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSource {
+    /// Out of the deposit before it is swapped, in the deposited token
+    Deposit,
+    /// Out of what the swap returns, in the bought token
+    Proceeds,
+}
+
+/// Keeper fee of one side of the book, copied into each order when it is placed.
+/// Amounts are in the token the fee is taken from and apply to each swap of the order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeSchedule {
+    /// Fee for the whole deposit, swaps of part of it pay their share
+    pub flat_amount: Uint128,
+    /// Share of the swapped amount: the return for Proceeds, the offer for Deposit
+    pub basis_points: u16,
+    pub min_fee: Option<Uint128>,
+    pub max_fee: Option<Uint128>,
+    pub source: FeeSource,
+}
+
+impl FeeSchedule {
+    /// Fixed fee paid out of the proceeds, the only kind of fee before schedules
+    pub fn flat(fee_amount: Uint128) -> Self {
+        FeeSchedule {
+            flat_amount: fee_amount,
+            basis_points: 0,
+            min_fee: None,
+            max_fee: None,
+            source: FeeSource::Proceeds,
+        }
+    }
+
+    pub fn validate(&self) -> StdResult<()> {
+        if self.basis_points > BASIS_POINTS {
+            return Err(StdError::generic_err(format!(
+                "Fee can not be more than {} basis points!",
+                BASIS_POINTS
+            )));
+        }
+        if let (Some(min_fee), Some(max_fee)) = (self.min_fee, self.max_fee) {
+            if min_fee > max_fee {
+                return Err(StdError::generic_err(format!(
+                    "Minimum fee {} is above the maximum fee {}!",
+                    min_fee,
+                    max_fee
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fee for swapping offer_amount of a deposit_amount deposit, where amount is the return or the offer
+    /// depending on the source. It never takes more than amount.
+    pub fn fee(&self, offer_amount: Uint128, deposit_amount: Uint128, amount: Uint128) -> Uint128 {
+        let mut fee = self.flat_amount.multiply_ratio(offer_amount, deposit_amount)
+            + amount.multiply_ratio(self.basis_points as u128, BASIS_POINTS as u128);
+        if let Some(min_fee) = self.min_fee {
+            fee = std::cmp::max(fee, min_fee);
+        }
+        if let Some(max_fee) = self.max_fee {
+            fee = std::cmp::min(fee, max_fee);
+        }
+        std::cmp::min(fee, amount)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct LimitOrderState {
+    pub id: u64,
+    pub order_type: OrderType,
+    pub linked_order_id: Option<u64>, // other leg of an OCO order sharing this deposit
     pub is_bid: bool,
-    pub status: String, //Active, PartiallyFilled, Filled
-    pub price: Uint128,
+    pub status: OrderStatus,
+    pub price: Price,
     pub deposit_token_index: i8,
     pub deposit_amount: Uint128,
     pub expected_amount: Uint128,
-    pub fee_amount: Uint128,
+    pub fee_schedule: FeeSchedule,
     pub balances: Vec<Uint128>,
     pub withdrew_balance: Option<Vec<Uint128>>,
+    pub timestamp: u64,
+    pub expires_at: Option<Expiration>,
+    pub amendments: Vec<LimitOrderAmendment>
+}
+
+/// Price, expected amount and deposit of an order after it was updated or topped up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrderAmendment {
+    pub price: Price,
+    pub expected_amount: Uint128,
+    pub deposit_amount: Uint128,
     pub timestamp: u64
 }
 
+/// Good-til-time for an order, expired orders get refunded instead of triggered
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    AtHeight(u64),
+    AtTime(u64),
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiration::AtHeight(height) => block.height >= *height,
+            Expiration::AtTime(time) => block.time >= *time,
+        }
+    }
+}
+
+// Fractional digits kept by Price
+pub const PRICE_DECIMALS: u8 = 18;
+
+/// Price of one whole token1 in whole token2, as a fixed point number with PRICE_DECIMALS digits.
+/// It is sent and returned as a decimal string like "1.25".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
+pub struct Price(#[schemars(with = "String")] u128);
+
+impl Price {
+    pub fn from_atomics(atomics: u128) -> Self {
+        Price(atomics)
+    }
+
+    pub fn atomics(&self) -> u128 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Storage key part that sorts like the price
+    pub fn to_be_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Price paid when amount of token2 is returned for the base amount of token1
+    pub fn from_base_amount(amount: Uint128, token1_info: &AssetInfo, token2_info: &AssetInfo) -> StdResult<Self> {
+        let (price_factor, amount_factor) = price_scale(token1_info, token2_info)?;
+        amount.u128().checked_mul(price_factor).map(|atomics| Price(atomics / amount_factor)).ok_or_else(|| StdError::generic_err(format!(
+            "Price overflow for amount {}",
+            amount
+        )))
+    }
+
+    /// Amount of token2 worth the base amount of token1 at this price.
+    /// Prices that token2 can not pay in whole units for the base amount are rejected instead of being rounded.
+    pub fn to_base_amount(&self, token1_info: &AssetInfo, token2_info: &AssetInfo) -> StdResult<Uint128> {
+        let (price_factor, amount_factor) = price_scale(token1_info, token2_info)?;
+        let scaled_price = self.0.checked_mul(amount_factor).ok_or_else(|| StdError::generic_err(format!(
+            "Price overflow for price {}",
+            self
+        )))?;
+        if scaled_price % price_factor != 0 {
+            return Err(StdError::generic_err(format!(
+                "Price {} is finer than {} decimals of token2 for {} of token1!",
+                self,
+                token2_info.decimal_places,
+                token1_info.base_amount
+            )));
+        }
+        Ok(Uint128(scaled_price / price_factor))
+    }
+
+    /// Lowest price of the band of band_width this price falls in
+    pub fn band_floor(&self, band_width: Price) -> Price {
+        Price(self.0 / band_width.0 * band_width.0)
+    }
+}
+
+// Atomics of a price per unit of token2
+// The base amount of token1 is worth price * base_amount * 10^token2_decimals / 10^token1_decimals
+// of token2, so a price in atomics is amount of token2 * price_factor / amount_factor.
+// The fraction is reduced, pairs quoting one whole token1 scale by a plain power of ten.
+fn price_scale(token1_info: &AssetInfo, token2_info: &AssetInfo) -> StdResult<(u128, u128)> {
+    if token1_info.decimal_places > PRICE_DECIMALS || token2_info.decimal_places > PRICE_DECIMALS {
+        return Err(StdError::generic_err(format!(
+            "Tokens with more than {} decimals are not supported!",
+            PRICE_DECIMALS
+        )));
+    }
+    if token1_info.base_amount == Uint128(0) {
+        return Err(StdError::generic_err(format!(
+            "Base amount of token1 can not be zero!"
+        )));
+    }
+    let price_factor = 10u128.pow((PRICE_DECIMALS + token1_info.decimal_places - token2_info.decimal_places) as u32);
+    let amount_factor = token1_info.base_amount.u128();
+    let divisor = greatest_common_divisor(price_factor, amount_factor);
+    Ok((price_factor / divisor, amount_factor / divisor))
+}
+
+fn greatest_common_divisor(a: u128, b: u128) -> u128 {
+    if b == 0 { a } else { greatest_common_divisor(b, a % b) }
+}
+
+impl FromStr for Price {
+    type Err = StdError;
+
+    fn from_str(input: &str) -> StdResult<Self> {
+        let invalid = || StdError::generic_err(format!("Invalid price: {}", input));
+        let mut parts = input.split('.');
+        let whole_part = parts.next().unwrap();
+        let fractional_part = parts.next().unwrap_or("");
+        if parts.next().is_some()
+            || (whole_part.is_empty() && fractional_part.is_empty())
+            || fractional_part.len() > PRICE_DECIMALS as usize
+            || !whole_part.chars().chain(fractional_part.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let whole: u128 = if whole_part.is_empty() { 0 } else { whole_part.parse().map_err(|_| invalid())? };
+        let fractional: u128 = if fractional_part.is_empty() { 0 } else { fractional_part.parse().map_err(|_| invalid())? };
+        let fractional = fractional * 10u128.pow((PRICE_DECIMALS as usize - fractional_part.len()) as u32);
+        whole
+            .checked_mul(10u128.pow(PRICE_DECIMALS as u32))
+            .and_then(|whole| whole.checked_add(fractional))
+            .map(Price)
+            .ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = 10u128.pow(PRICE_DECIMALS as u32);
+        let whole = self.0 / unit;
+        let fractional = self.0 % unit;
+        if fractional == 0 {
+            write!(f, "{}", whole)
+        } else {
+            let fractional = format!("{:0>width$}", fractional, width = PRICE_DECIMALS as usize);
+            write!(f, "{}.{}", whole, fractional.trim_end_matches('0'))
+        }
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(PriceVisitor)
+    }
+}
+
+struct PriceVisitor;
+
+impl<'de> de::Visitor<'de> for PriceVisitor {
+    type Value = Price;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("string-encoded decimal price")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Price::from_str(v).map_err(|e| E::custom(format!("{}", e)))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct AssetInfo {
@@ -254,7 +901,9 @@ pub struct AssetInfo {
     pub base_amount: Uint128,
     pub fee_amount: Uint128,
     pub min_amount: Uint128,
-    pub token: Option<Token>
+    pub token: Option<Token>,
+    // Set when the pair side is a native coin, token is then the SNIP-20 wrapper (sSCRT) holding it
+    pub native_token: Option<NativeToken>
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
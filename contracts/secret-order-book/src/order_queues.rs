@@ -1,36 +1,55 @@
-use cosmwasm_std::{CanonicalAddr, HumanAddr, Uint128};
+use cosmwasm_std::{BlockInfo, HumanAddr, ReadonlyStorage, StdError, StdResult, Storage, Uint128};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering};
+use std::cmp::Ordering;
 use schemars::JsonSchema;
-use std::collections::{BinaryHeap};
+use crate::msg::{Expiration, OrderType, Price};
+use crate::skip_list::{SkipList, SkipListIter};
+use crate::state::{load, may_load, remove, save};
+
+pub const BID_ORDER_QUEUE: &[u8] = b"bidordequeue";
+pub const ASK_ORDER_QUEUE: &[u8] = b"askorderqueue";
+pub const STOP_BID_ORDER_QUEUE: &[u8] = b"stopbidorderqueue";
+pub const STOP_ASK_ORDER_QUEUE: &[u8] = b"stopaskorderqueue";
+
+// Prefixes under the queue key
+const PRICE_LEVELS: &[u8] = b"levels";
+const QUEUED_ORDERS: &[u8] = b"orders";
+const ORDER_POSITIONS: &[u8] = b"positions";
+const HEIGHT_EXPIRATIONS: &[u8] = b"heightexpirations";
+const TIME_EXPIRATIONS: &[u8] = b"timeexpirations";
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct OrderIndex {
-    pub id: HumanAddr,
-    pub price: Uint128,
+    pub id: u64,
+    pub owner: HumanAddr,
+    pub price: Price,
     pub timestamp: u64,
+    // Unique and increasing in the order orders were placed, breaks ties between equal prices
+    pub sequence: u64,
     pub is_bid: bool,
+    pub order_type: OrderType,
+    pub linked_order_id: Option<u64>,
+    pub expires_at: Option<Expiration>,
+}
+
+impl OrderIndex {
+    // Limit bids and stop asks are served from the highest price down,
+    // limit asks and stop bids from the lowest price up
+    fn is_descending(&self) -> bool {
+        self.is_bid == (self.order_type == OrderType::Limit)
+    }
 }
 
-// Arrange at first by price and after that by timestamp
+// Price-time priority, the order served first is the greatest
 impl Ord for OrderIndex {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.price < other.price {
-            match self.is_bid {
-                true => Ordering::Less,
-                false => Ordering::Greater,
-            }
-            //Ordering::Less
-        } else if self.price > other.price {
-            match self.is_bid {
-                true => Ordering::Greater,
-                false => Ordering::Less,
-            }
-            //Ordering::Greater
-        } else {
-            // FIFO
-            other.timestamp.cmp(&self.timestamp)
-        }
+        let price_ordering = match self.is_descending() {
+            true => self.price.cmp(&other.price),
+            false => other.price.cmp(&self.price),
+        };
+        // FIFO
+        price_ordering.then_with(|| other.sequence.cmp(&self.sequence))
     }
 }
 
@@ -42,52 +61,312 @@ impl PartialOrd for OrderIndex {
 
 impl PartialEq for OrderIndex {
     fn eq(&self, other: &Self) -> bool {
-        if self.price > other.price || self.price < other.price {
-            false
-        } else {
-            self.timestamp == other.timestamp
-        }
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for OrderIndex {}
+
+// Swap sent to the pair for an order, settled when the pair pays it back through Receive or, in native coins, SettleNativeSwap
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct PendingSwap {
+    pub order: OrderIndex,
+    pub trigger_address: HumanAddr,
+    pub offer_amount: Uint128,
+    // Part of offer_amount kept out of the swap for the keeper
+    pub deposit_fee: Uint128,
+    pub expected_return: Uint128,
+    pub block_height: u64,
+}
+
+// Where an order sits in its queue
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct OrderPosition {
+    price: Price,
+    sequence: u64,
+}
+
+// Orders at one price, linked from the first placed to the last
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PriceLevel {
+    first_sequence: u64,
+    last_sequence: u64,
+    order_count: u32,
+}
+
+// Order in its price level, linked to the orders placed before and after it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct QueuedOrder {
+    order_index: OrderIndex,
+    previous_sequence: Option<u64>,
+    next_sequence: Option<u64>,
+}
+
+/// Header of an order queue, saved under the queue key. Price levels are nodes of a skip list
+/// and every order has its own entry keyed by big-endian price plus sequence number, linked to
+/// its neighbours in the level, all in prefixed storage under the same key.
+/// Inserting or removing an order only rewrites its own entry, its neighbours and its level,
+/// whatever the number of prices and orders in the queue.
+/// Orders are served in OrderIndex order, by price and after that by sequence.
+/// Orders that expire are also indexed by expiration, soonest first, so expired orders
+/// are found without reading the ones still live.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OrderQueue {
-    idx_queue: Option<BinaryHeap<OrderIndex>>,
-    is_bid: bool
+    // Prices with at least one order, lowest first
+    price_levels: SkipList,
+    // Ids of the orders that expire, keyed by expiration then sequence
+    height_expirations: SkipList,
+    time_expirations: SkipList,
+    is_bid: bool,
+    order_type: OrderType
 }
 
 impl OrderQueue {
-    pub fn new(is_bid: bool) -> Self {
+    pub fn new(is_bid: bool, order_type: OrderType) -> Self {
         OrderQueue {
-            idx_queue: Some(BinaryHeap::new()),
-            is_bid
+            price_levels: SkipList::new(),
+            height_expirations: SkipList::new(),
+            time_expirations: SkipList::new(),
+            is_bid,
+            order_type
+        }
+    }
+
+    // Same direction as OrderIndex::is_descending
+    fn is_descending(&self) -> bool {
+        self.is_bid == (self.order_type == OrderType::Limit)
+    }
+
+    fn key(&self) -> &'static [u8] {
+        order_queue_key(self.is_bid, &self.order_type)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.price_levels.is_empty()
+    }
+
+    /// Adds an order among the others of its price by sequence. The header has to be saved afterwards.
+    pub fn insert<S: Storage>(&mut self, storage: &mut S, id: u64, owner: HumanAddr, price: Price, timestamp:u64, sequence: u64, linked_order_id: Option<u64>, expires_at: Option<Expiration> ) -> StdResult<()> {
+        let key = self.key();
+        let expiration = expires_at.clone();
+        let mut queued_order = QueuedOrder {
+            order_index: OrderIndex {
+                id,
+                owner,
+                price,
+                timestamp,
+                sequence,
+                is_bid: self.is_bid,
+                order_type: self.order_type.clone(),
+                linked_order_id,
+                expires_at
+            },
+            previous_sequence: None,
+            next_sequence: None
+        };
+
+        match self.price_levels.get::<PriceLevel, _>(&*storage, &[key, PRICE_LEVELS], price.atomics())? {
+            Some(mut price_level) => {
+                // Sequences only grow, so a new order almost always goes last
+                let mut queued_orders = PrefixedStorage::multilevel(&[key, QUEUED_ORDERS], storage);
+                let mut previous_sequence = Some(price_level.last_sequence);
+                let mut next_sequence = None;
+                while let Some(previous) = previous_sequence {
+                    if previous < sequence {
+                        break;
+                    }
+                    let previous_order: QueuedOrder = load(&queued_orders, &order_key(price, previous))?;
+                    next_sequence = Some(previous);
+                    previous_sequence = previous_order.previous_sequence;
+                }
+
+                match previous_sequence {
+                    Some(previous) => {
+                        let mut previous_order: QueuedOrder = load(&queued_orders, &order_key(price, previous))?;
+                        previous_order.next_sequence = Some(sequence);
+                        save(&mut queued_orders, &order_key(price, previous), &previous_order)?;
+                    }
+                    None => price_level.first_sequence = sequence
+                }
+                match next_sequence {
+                    Some(next) => {
+                        let mut next_order: QueuedOrder = load(&queued_orders, &order_key(price, next))?;
+                        next_order.previous_sequence = Some(sequence);
+                        save(&mut queued_orders, &order_key(price, next), &next_order)?;
+                    }
+                    None => price_level.last_sequence = sequence
+                }
+                queued_order.previous_sequence = previous_sequence;
+                queued_order.next_sequence = next_sequence;
+                save(&mut queued_orders, &order_key(price, sequence), &queued_order)?;
+
+                price_level.order_count += 1;
+                self.price_levels.set(storage, &[key, PRICE_LEVELS], price.atomics(), price_level)?;
+            }
+            None => {
+                let mut queued_orders = PrefixedStorage::multilevel(&[key, QUEUED_ORDERS], storage);
+                save(&mut queued_orders, &order_key(price, sequence), &queued_order)?;
+
+                let price_level = PriceLevel {
+                    first_sequence: sequence,
+                    last_sequence: sequence,
+                    order_count: 1
+                };
+                self.price_levels.insert(storage, &[key, PRICE_LEVELS], price.atomics(), price_level, sequence)?;
+            }
         }
+
+        let mut order_positions = PrefixedStorage::multilevel(&[key, ORDER_POSITIONS], storage);
+        save(&mut order_positions, &id.to_be_bytes(), &OrderPosition { price, sequence })?;
+
+        match expiration {
+            Some(Expiration::AtHeight(height)) => self.height_expirations.insert(storage, &[key, HEIGHT_EXPIRATIONS], expiration_key(height, sequence), id, sequence)?,
+            Some(Expiration::AtTime(time)) => self.time_expirations.insert(storage, &[key, TIME_EXPIRATIONS], expiration_key(time, sequence), id, sequence)?,
+            None => {}
+        }
+        Ok(())
     }
 
-    pub fn insert(&mut self, id: HumanAddr, price: Uint128, timestamp:u64 ) -> bool {
-        self.idx_queue.as_mut().unwrap().push(OrderIndex {
-            id,
-            price,
-            timestamp,
-            is_bid: self.is_bid
-        });
-        true
+    /// Takes an order out of the queue. The header has to be saved afterwards.
+    pub fn remove<S: Storage>(&mut self, storage: &mut S, id: u64) -> StdResult<Option<OrderIndex>> {
+        let key = self.key();
+        let mut order_positions = PrefixedStorage::multilevel(&[key, ORDER_POSITIONS], storage);
+        let position: OrderPosition = match may_load(&order_positions, &id.to_be_bytes())? {
+            Some(position) => position,
+            None => return Ok(None)
+        };
+        remove(&mut order_positions, &id.to_be_bytes());
+        let price = position.price;
+
+        let mut queued_orders = PrefixedStorage::multilevel(&[key, QUEUED_ORDERS], storage);
+        let queued_order: QueuedOrder = load(&queued_orders, &order_key(price, position.sequence))?;
+        remove(&mut queued_orders, &order_key(price, position.sequence));
+        if let Some(previous) = queued_order.previous_sequence {
+            let mut previous_order: QueuedOrder = load(&queued_orders, &order_key(price, previous))?;
+            previous_order.next_sequence = queued_order.next_sequence;
+            save(&mut queued_orders, &order_key(price, previous), &previous_order)?;
+        }
+        if let Some(next) = queued_order.next_sequence {
+            let mut next_order: QueuedOrder = load(&queued_orders, &order_key(price, next))?;
+            next_order.previous_sequence = queued_order.previous_sequence;
+            save(&mut queued_orders, &order_key(price, next), &next_order)?;
+        }
+
+        match &queued_order.order_index.expires_at {
+            Some(Expiration::AtHeight(height)) => { self.height_expirations.remove::<u64, _>(storage, &[key, HEIGHT_EXPIRATIONS], expiration_key(*height, position.sequence))?; }
+            Some(Expiration::AtTime(time)) => { self.time_expirations.remove::<u64, _>(storage, &[key, TIME_EXPIRATIONS], expiration_key(*time, position.sequence))?; }
+            None => {}
+        }
+
+        let mut price_level: PriceLevel = self.price_levels.get(&*storage, &[key, PRICE_LEVELS], price.atomics())?
+            .ok_or_else(|| StdError::generic_err(format!("Missing price level {}", price)))?;
+        price_level.order_count -= 1;
+        if price_level.order_count == 0 {
+            self.price_levels.remove::<PriceLevel, _>(storage, &[key, PRICE_LEVELS], price.atomics())?;
+        } else {
+            if queued_order.previous_sequence.is_none() {
+                price_level.first_sequence = queued_order.next_sequence.unwrap_or(price_level.first_sequence);
+            }
+            if queued_order.next_sequence.is_none() {
+                price_level.last_sequence = queued_order.previous_sequence.unwrap_or(price_level.last_sequence);
+            }
+            self.price_levels.set(storage, &[key, PRICE_LEVELS], price.atomics(), price_level)?;
+        }
+        Ok(Some(queued_order.order_index))
     }
 
-    pub fn peek(&mut self) -> Option<&OrderIndex> {
-        self.idx_queue.as_mut().unwrap().peek()
+    /// Orders in the order they are served, loaded one by one as the iterator advances
+    pub fn orders<'a, S: ReadonlyStorage>(&'a self, storage: &'a S) -> OrderQueueIter<'a, S> {
+        OrderQueueIter {
+            storage,
+            key: self.key(),
+            price_levels: self.price_levels.iter(storage, &[self.key(), PRICE_LEVELS], self.is_descending()),
+            next_order: None
+        }
     }
 
-    pub fn pop(&mut self) -> Option<OrderIndex> {
-        self.idx_queue.as_mut().unwrap().pop()
+    pub fn peek<S: ReadonlyStorage>(&self, storage: &S) -> StdResult<Option<OrderIndex>> {
+        self.orders(storage).next().transpose()
     }
 
-    pub fn remove(&mut self, id: HumanAddr) {
-        if let Some(idx_queue) = self.idx_queue.take() {
-            let mut active_orders = idx_queue.into_vec();
-            active_orders.retain(|order_id| id != order_id.id);
-            self.idx_queue = Some(BinaryHeap::from(active_orders));
+    /// Up to limit expired orders, soonest expiration first, without reading the orders still live
+    pub fn expired<S: ReadonlyStorage>(&self, storage: &S, block: &BlockInfo, limit: u32) -> StdResult<Vec<OrderIndex>> {
+        let key = self.key();
+        let mut expired_ids: Vec<u64> = vec![];
+        for (expirations, prefix, now) in vec![
+            (&self.height_expirations, HEIGHT_EXPIRATIONS, block.height),
+            (&self.time_expirations, TIME_EXPIRATIONS, block.time)
+        ] {
+            for expiration in expirations.iter::<u64, _>(storage, &[key, prefix], false) {
+                let (expiration_key, id) = expiration?;
+                if expired_ids.len() as u32 >= limit || (expiration_key >> 64) as u64 > now {
+                    break;
+                }
+                expired_ids.push(id);
+            }
         }
+
+        let order_positions = ReadonlyPrefixedStorage::multilevel(&[key, ORDER_POSITIONS], storage);
+        let queued_orders = ReadonlyPrefixedStorage::multilevel(&[key, QUEUED_ORDERS], storage);
+        expired_ids.iter().map(|id| {
+            let position: OrderPosition = load(&order_positions, &id.to_be_bytes())?;
+            let queued_order: QueuedOrder = load(&queued_orders, &order_key(position.price, position.sequence))?;
+            Ok(queued_order.order_index)
+        }).collect()
     }
-}
\ No newline at end of file
+}
+
+pub struct OrderQueueIter<'a, S: ReadonlyStorage> {
+    storage: &'a S,
+    key: &'static [u8],
+    price_levels: SkipListIter<'a, PriceLevel, S>,
+    // Price and sequence of the next order in the current level
+    next_order: Option<(Price, u64)>
+}
+
+impl<'a, S: ReadonlyStorage> Iterator for OrderQueueIter<'a, S> {
+    type Item = StdResult<OrderIndex>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_order.is_none() {
+            self.next_order = match self.price_levels.next()? {
+                Ok((price, price_level)) => Some((Price::from_atomics(price), price_level.first_sequence)),
+                Err(err) => return Some(Err(err))
+            };
+        }
+
+        let (price, sequence) = self.next_order?;
+        let queued_orders = ReadonlyPrefixedStorage::multilevel(&[self.key, QUEUED_ORDERS], self.storage);
+        match load::<QueuedOrder, _>(&queued_orders, &order_key(price, sequence)) {
+            Ok(queued_order) => {
+                self.next_order = queued_order.next_sequence.map(|next| (price, next));
+                Some(Ok(queued_order.order_index))
+            }
+            Err(err) => {
+                self.next_order = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+pub fn order_queue_key(is_bid: bool, order_type: &OrderType) -> &'static [u8] {
+    match (order_type, is_bid) {
+        (OrderType::Limit, true) => BID_ORDER_QUEUE,
+        (OrderType::Limit, false) => ASK_ORDER_QUEUE,
+        (OrderType::Stop, true) => STOP_BID_ORDER_QUEUE,
+        (OrderType::Stop, false) => STOP_ASK_ORDER_QUEUE,
+    }
+}
+
+// Expiration in the high bits, sequence in the low bits so equal expirations stay unique
+fn expiration_key(expiration: u64, sequence: u64) -> u128 {
+    (expiration as u128) << 64 | sequence as u128
+}
+
+// Big-endian price then sequence number
+fn order_key(price: Price, sequence: u64) -> Vec<u8> {
+    let mut key = price.to_be_bytes().to_vec();
+    key.extend_from_slice(&sequence.to_be_bytes());
+    key
+}
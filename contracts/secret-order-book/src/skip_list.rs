@@ -0,0 +1,207 @@
+use std::marker::PhantomData;
+use cosmwasm_std::{ReadonlyStorage, StdResult, Storage};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use crate::state::{load, may_load, remove, save};
+
+// Lanes a node can be linked on, one node in four moves up a lane
+// so searches stay logarithmic up to about 4^12 nodes
+pub const MAX_HEIGHT: usize = 12;
+
+/// Sorted list of u128 keys with one storage entry per node under a storage path.
+/// Finding, inserting or removing a key loads a logarithmic number of nodes and walking
+/// the list loads one node per step, whatever the size of the list.
+/// Only the first key of each lane is kept here, the owner saves it after every change.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SkipList {
+    // First key on each lane, lane 0 links every node
+    heads: Vec<Option<u128>>,
+    last: Option<u128>,
+    len: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SkipListNode<T> {
+    // Following key on each lane the node is linked on
+    next: Vec<Option<u128>>,
+    previous: Option<u128>,
+    value: T,
+}
+
+impl SkipList {
+    pub fn new() -> Self {
+        SkipList::default()
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get<T: DeserializeOwned, S: ReadonlyStorage>(&self, storage: &S, path: &[&[u8]], key: u128) -> StdResult<Option<T>> {
+        let nodes = ReadonlyPrefixedStorage::multilevel(path, storage);
+        Ok(may_load::<SkipListNode<T>, _>(&nodes, &key.to_be_bytes())?.map(|node| node.value))
+    }
+
+    /// Replaces the value of a key already in the list
+    pub fn set<T: Serialize + DeserializeOwned, S: Storage>(&self, storage: &mut S, path: &[&[u8]], key: u128, value: T) -> StdResult<()> {
+        let mut nodes = PrefixedStorage::multilevel(path, storage);
+        let mut node: SkipListNode<T> = load(&nodes, &key.to_be_bytes())?;
+        node.value = value;
+        save(&mut nodes, &key.to_be_bytes(), &node)
+    }
+
+    /// Adds a key that is not in the list yet, seed decides how many lanes it is linked on
+    pub fn insert<T: Serialize + DeserializeOwned, S: Storage>(&mut self, storage: &mut S, path: &[&[u8]], key: u128, value: T, seed: u64) -> StdResult<()> {
+        let height = node_height(seed);
+        let predecessors = self.predecessors::<T, _>(&*storage, path, key)?;
+        while self.heads.len() < height {
+            self.heads.push(None);
+        }
+
+        let mut nodes = PrefixedStorage::multilevel(path, storage);
+        let mut node = SkipListNode {
+            next: vec![None; height],
+            previous: predecessors.get(0).cloned().flatten(),
+            value
+        };
+        for lane in 0..height {
+            match predecessors.get(lane).cloned().flatten() {
+                Some(predecessor) => {
+                    let mut predecessor_node: SkipListNode<T> = load(&nodes, &predecessor.to_be_bytes())?;
+                    node.next[lane] = predecessor_node.next[lane];
+                    predecessor_node.next[lane] = Some(key);
+                    save(&mut nodes, &predecessor.to_be_bytes(), &predecessor_node)?;
+                }
+                None => {
+                    node.next[lane] = self.heads[lane];
+                    self.heads[lane] = Some(key);
+                }
+            }
+        }
+        match node.next[0] {
+            Some(following) => {
+                let mut following_node: SkipListNode<T> = load(&nodes, &following.to_be_bytes())?;
+                following_node.previous = Some(key);
+                save(&mut nodes, &following.to_be_bytes(), &following_node)?;
+            }
+            None => self.last = Some(key)
+        }
+        save(&mut nodes, &key.to_be_bytes(), &node)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Takes a key out of the list and returns its value
+    pub fn remove<T: Serialize + DeserializeOwned, S: Storage>(&mut self, storage: &mut S, path: &[&[u8]], key: u128) -> StdResult<Option<T>> {
+        let node: SkipListNode<T> = match self.get_node(&*storage, path, key)? {
+            Some(node) => node,
+            None => return Ok(None)
+        };
+        let predecessors = self.predecessors::<T, _>(&*storage, path, key)?;
+
+        let mut nodes = PrefixedStorage::multilevel(path, storage);
+        for lane in 0..node.next.len() {
+            match predecessors[lane] {
+                Some(predecessor) => {
+                    let mut predecessor_node: SkipListNode<T> = load(&nodes, &predecessor.to_be_bytes())?;
+                    predecessor_node.next[lane] = node.next[lane];
+                    save(&mut nodes, &predecessor.to_be_bytes(), &predecessor_node)?;
+                }
+                None => self.heads[lane] = node.next[lane]
+            }
+        }
+        match node.next[0] {
+            Some(following) => {
+                let mut following_node: SkipListNode<T> = load(&nodes, &following.to_be_bytes())?;
+                following_node.previous = node.previous;
+                save(&mut nodes, &following.to_be_bytes(), &following_node)?;
+            }
+            None => self.last = node.previous
+        }
+        remove(&mut nodes, &key.to_be_bytes());
+        while self.heads.last() == Some(&None) {
+            self.heads.pop();
+        }
+        self.len -= 1;
+        Ok(Some(node.value))
+    }
+
+    /// Keys and values from the lowest key up, or from the highest down
+    pub fn iter<'a, T: DeserializeOwned, S: ReadonlyStorage>(&self, storage: &'a S, path: &[&[u8]], descending: bool) -> SkipListIter<'a, T, S> {
+        SkipListIter {
+            nodes: ReadonlyPrefixedStorage::multilevel(path, storage),
+            next_key: match descending {
+                true => self.last,
+                false => self.heads.get(0).cloned().flatten()
+            },
+            descending,
+            value: PhantomData
+        }
+    }
+
+    fn get_node<T: DeserializeOwned, S: ReadonlyStorage>(&self, storage: &S, path: &[&[u8]], key: u128) -> StdResult<Option<SkipListNode<T>>> {
+        let nodes = ReadonlyPrefixedStorage::multilevel(path, storage);
+        may_load(&nodes, &key.to_be_bytes())
+    }
+
+    // Last key below key on each lane, None where key comes first
+    fn predecessors<T: DeserializeOwned, S: ReadonlyStorage>(&self, storage: &S, path: &[&[u8]], key: u128) -> StdResult<Vec<Option<u128>>> {
+        let nodes = ReadonlyPrefixedStorage::multilevel(path, storage);
+        let mut predecessors: Vec<Option<u128>> = vec![None; self.heads.len()];
+        let mut current: Option<u128> = None;
+        let mut current_next: Vec<Option<u128>> = self.heads.clone();
+        for lane in (0..self.heads.len()).rev() {
+            while let Some(next) = current_next[lane] {
+                if next >= key {
+                    break;
+                }
+                let node: SkipListNode<T> = load(&nodes, &next.to_be_bytes())?;
+                current = Some(next);
+                current_next = node.next;
+            }
+            predecessors[lane] = current;
+        }
+        Ok(predecessors)
+    }
+}
+
+pub struct SkipListIter<'a, T, S: ReadonlyStorage> {
+    nodes: ReadonlyPrefixedStorage<'a, S>,
+    next_key: Option<u128>,
+    descending: bool,
+    value: PhantomData<T>
+}
+
+impl<'a, T: DeserializeOwned, S: ReadonlyStorage> Iterator for SkipListIter<'a, T, S> {
+    type Item = StdResult<(u128, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.next_key?;
+        match load::<SkipListNode<T>, _>(&self.nodes, &key.to_be_bytes()) {
+            Ok(node) => {
+                self.next_key = match self.descending {
+                    true => node.previous,
+                    false => node.next[0]
+                };
+                Some(Ok((key, node.value)))
+            }
+            Err(err) => {
+                self.next_key = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// Lanes of a new node, drawn from a hash of its seed so every node replays the same list
+fn node_height(seed: u64) -> usize {
+    let mut hash = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    (1 + hash.trailing_zeros() as usize / 2).min(MAX_HEIGHT)
+}
@@ -1,20 +1,79 @@
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::{CanonicalAddr, Binary, Coin, Decimal, Extern, HumanAddr, Querier, QuerierResult, QueryRequest, StdResult, Uint128, WasmQuery, from_binary, testing::*, to_vec};
+    use cosmwasm_std::{BankMsg, CanonicalAddr, Binary, Coin, CosmosMsg, Decimal, Empty, Extern, HumanAddr, Querier, QuerierResult, QueryRequest, ReadonlyStorage, StdResult, Uint128, WasmMsg, WasmQuery, from_binary, from_slice, log, testing::*, to_vec};
     use secret_toolkit::storage::AppendStore;
-    use crate::{contract::{BID_ORDER_QUEUE, SWAPPED_LIMIT_ORDER, FACTORY_DATA, ACTIVE_LIMIT_ORDERS, HISTORY_LIMIT_ORDERS, TOKEN1_DATA, TOKEN2_DATA, handle}, msg::{QueryAnswer, AssetInfo, HandleMsg, NativeToken, QueryMsg, Token}, state::{load, save, may_load}};
-    use crate::contract::{init};
-    use crate::order_queues::OrderQueue;
+    use secret_toolkit::snip20::{self, transfer_msg};
+    use crate::{contract::{PENDING_SWAPS, SWAP_TIMEOUT_BLOCKS, KEEPER_REWARDS, FACTORY_DATA, ACTIVE_LIMIT_ORDERS, USER_ORDERS, HISTORY_LIMIT_ORDERS, TOKEN1_DATA, TOKEN2_DATA, BLOCK_SIZE, ORDER_EVENTS, TREASURY, PROTOCOL_FEE, BID_FEE_SCHEDULE, ASK_FEE_SCHEDULE, SCAN_LIMIT, DEPTH_CONFIG, handle}, msg::{QueryAnswer, AssetInfo, ContractStatus, DepthBand, MigrationContract, DepthConfig, Expiration, FeeSchedule, FeeSource, HandleMsg, NativeToken, OrderEvent, OrderEventAction, OrderStatus, OrderType, Price, QueryMsg, Snip20Msg, Token, TriggerCandidate}, state::{load, save, may_load}};
+    use crate::contract::{init, credit_keeper_reward, padded_binary};
+    use crate::order_queues::{ASK_ORDER_QUEUE, BID_ORDER_QUEUE, OrderIndex, OrderQueue, PendingSwap, STOP_ASK_ORDER_QUEUE, order_queue_key};
     use cosmwasm_std::{Api, InitResponse, to_binary};
     use crate::contract::query;
     use crate::{msg::{InitMsg, 
         UserOrderMap,
         LimitOrderState,
+        AmmAssetInfo,
         AmmSimulationQuery,
+        AmmSimulationOfferAsset,
+        AmmPairHandleMsg,
+        AmmPairReceiveMsg,
         AmmPairSimulationResponse
     }};
 
     use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+    use serde::Deserialize;
+
+    // Constant price pair that stops paying out once more than max_offer_token1 is offered
+    struct AmmPairMockQuerier {
+        token1_price: Uint128,
+        token1_base_amount: Uint128,
+        max_offer_token1: Uint128,
+    }
+
+    impl Querier for AmmPairMockQuerier {
+        fn raw_query(&self, request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(request).unwrap();
+            let msg = match request {
+                QueryRequest::Wasm(WasmQuery::Smart { msg, .. }) => msg,
+                _ => panic!("Unexpected query"),
+            };
+            let offer_asset = match from_slice(msg.as_slice()).unwrap() {
+                AmmSimulationQuery::simulation { offer_asset } => offer_asset,
+                _ => panic!("Unexpected simulation"),
+            };
+            // The pair holds native SCRT, never its wrapper
+            let is_token1 = match offer_asset.info {
+                AmmAssetInfo::Token { contract_addr, .. } if contract_addr != HumanAddr("sscrtaddress".to_string()) => contract_addr == HumanAddr("token1address".to_string()),
+                AmmAssetInfo::NativeToken { denom } if denom == "uscrt" => false,
+                _ => panic!("Unexpected asset"),
+            };
+
+            let max_return_token2 = self.max_offer_token1.multiply_ratio(self.token1_price, self.token1_base_amount);
+            let return_amount = if is_token1 {
+                let offer_amount = std::cmp::min(offer_asset.amount, self.max_offer_token1);
+                offer_amount.multiply_ratio(self.token1_price, self.token1_base_amount)
+            } else {
+                let offer_amount = std::cmp::min(offer_asset.amount, max_return_token2);
+                offer_amount.multiply_ratio(self.token1_base_amount, self.token1_price)
+            };
+
+            Ok(to_binary(&AmmPairSimulationResponse {
+                return_amount,
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }))
+        }
+    }
+
+    // Part of the snip20 Send message the order book sends to the pair
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Snip20SendMsg {
+        Send {
+            recipient: HumanAddr,
+            amount: Uint128,
+            msg: Option<Binary>
+        }
+    }
 
     fn init_helper(
         factory_address: HumanAddr,
@@ -38,12 +97,22 @@ mod tests {
             token1_info,
             token2_info,
             amm_pair_contract_address,
-            amm_pair_contract_hash
+            amm_pair_contract_hash,
+            migrate_from: None
         };
 
         (init(&mut deps, env, init_msg), deps)
     }
 
+    fn queued_orders<S: ReadonlyStorage>(storage: &S, queue_key: &[u8]) -> Vec<OrderIndex> {
+        let order_queue: OrderQueue = load(storage, queue_key).unwrap();
+        order_queue.orders(storage).collect::<StdResult<Vec<OrderIndex>>>().unwrap()
+    }
+
+    fn queued_order_ids<S: ReadonlyStorage>(storage: &S, queue_key: &[u8]) -> Vec<u64> {
+        queued_orders(storage, queue_key).iter().map(|order_index| order_index.id).collect()
+    }
+
     /* 
     #[test]
     fn test_init() {
@@ -131,6 +200,7 @@ mod tests {
                         token_code_hash: "token1hash".to_string()
                     }
                 ),
+                native_token: None
             },
             AssetInfo {
                 decimal_places: 18,
@@ -143,6 +213,7 @@ mod tests {
                         token_code_hash: "token2hash".to_string()
                     }
                 ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -160,8 +231,10 @@ mod tests {
             amount: Uint128(4000000000000000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: true,
-                price: Uint128(4000000000000000000),
-                expected_amount: Uint128(1000000000000000000)
+                price: Price::from_atomics(4000000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
 
@@ -180,8 +253,10 @@ mod tests {
             amount: Uint128(5000000000000000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: true,
-                price: Uint128(5000000000000000000),
-                expected_amount: Uint128(1000000000000000000)
+                price: Price::from_atomics(5000000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
 
@@ -196,33 +271,32 @@ mod tests {
         // Check Bob limit order
         let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
 
-        let limit_orders = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_bob.as_slice()).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap();
 
+        assert_eq!(load_limit_order.clone().unwrap().id, 1);
         assert_eq!(load_limit_order.clone().unwrap().is_bid, true);
-        assert_eq!(load_limit_order.clone().unwrap().status, "Active".to_string());
-        assert_eq!(load_limit_order.clone().unwrap().price, Uint128(4000000000000000000));
+        assert_eq!(load_limit_order.clone().unwrap().status, OrderStatus::Active);
+        assert_eq!(load_limit_order.clone().unwrap().price, Price::from_atomics(4000000000000000000));
         assert_eq!(load_limit_order.clone().unwrap().balances, vec![Uint128(0),Uint128(4000000000000000000)]);
 
         // Check Alice limit order
         let user_address_alice = &deps.api.canonical_address(&HumanAddr("alice".to_string())).unwrap();
 
-        let limit_orders = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_alice.as_slice()).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_alice.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap();
 
+        assert_eq!(load_limit_order.clone().unwrap().id, 2);
         assert_eq!(load_limit_order.clone().unwrap().is_bid, true);
-        assert_eq!(load_limit_order.clone().unwrap().status, "Active".to_string());
-        assert_eq!(load_limit_order.clone().unwrap().price, Uint128(5000000000000000000));
+        assert_eq!(load_limit_order.clone().unwrap().status, OrderStatus::Active);
+        assert_eq!(load_limit_order.clone().unwrap().price, Price::from_atomics(5000000000000000000));
         assert_eq!(load_limit_order.clone().unwrap().balances, vec![Uint128(0),Uint128(5000000000000000000)]);
 
         // Check order queue
-        let mut bid_order_book:OrderQueue=load(&deps.storage, BID_ORDER_QUEUE).unwrap();
-        assert_eq!(bid_order_book.peek().unwrap().id, HumanAddr("alice".to_string()));
-        assert_eq!(bid_order_book.peek().unwrap().price, Uint128(5000000000000000000));
-        assert_eq!(bid_order_book.pop().unwrap().id, HumanAddr("alice".to_string()));
-        assert_eq!(bid_order_book.pop().unwrap().id, HumanAddr("bob".to_string()));
-        assert_eq!(bid_order_book.peek(), None);
-        assert_eq!(bid_order_book.pop(), None);
+        let bid_order_book: Vec<OrderIndex> = queued_orders(&deps.storage, BID_ORDER_QUEUE);
+        assert_eq!(bid_order_book[0].owner, HumanAddr("alice".to_string()));
+        assert_eq!(bid_order_book[0].price, Price::from_atomics(5000000000000000000));
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), vec![2, 1]);
 
         // get limit orders
         /*let query_msg = QueryMsg::GetLimitOrders {
@@ -234,7 +308,7 @@ mod tests {
         let result:OrderBookPairResponse = from_binary(&query_result.unwrap()).unwrap();
         */
         // withdraw
-        let handle_msg = HandleMsg::CancelLimitOrder {};
+        let handle_msg = HandleMsg::CancelLimitOrder { order_id: 2 };
 
         let handle_result = handle(&mut deps, mock_env("alice", &[]), handle_msg.clone());
         assert!(
@@ -243,8 +317,8 @@ mod tests {
             handle_result.err().unwrap()
         ); 
 
-        let limit_orders = ReadonlyPrefixedStorage::new(ACTIVE_LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_alice.as_slice()).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_alice.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap();
         assert_eq!(load_limit_order, None);
 
         // add again
@@ -255,8 +329,10 @@ mod tests {
             amount: Uint128(14000000000000000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: true,
-                price: Uint128(7000000000000000000),
-                expected_amount: Uint128(2000000000000000000)
+                price: Price::from_atomics(7000000000000000000),
+                expected_amount: Uint128(2000000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
 
@@ -268,8 +344,9 @@ mod tests {
             handle_result.err().unwrap()
         ); 
 
-        let mut bid_order_book:OrderQueue=load(&deps.storage, BID_ORDER_QUEUE).unwrap();
-        assert_eq!(bid_order_book.peek().unwrap().id, HumanAddr("alice".to_string()));
+        let bid_order_book: Vec<OrderIndex> = queued_orders(&deps.storage, BID_ORDER_QUEUE);
+        assert_eq!(bid_order_book[0].owner, HumanAddr("alice".to_string()));
+        assert_eq!(bid_order_book[0].id, 3);
 
         //check limit orders
         let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_alice.as_slice()], &deps.storage);
@@ -280,7 +357,7 @@ mod tests {
         assert_eq!(txs.unwrap().len(), 1);
 
         //widthdraw again
-        let handle_msg = HandleMsg::CancelLimitOrder {};
+        let handle_msg = HandleMsg::CancelLimitOrder { order_id: 3 };
 
         let handle_result = handle(&mut deps, mock_env("alice", &[]), handle_msg.clone());
         assert!(
@@ -298,32 +375,37 @@ mod tests {
         assert_eq!(txs.unwrap().len(), 2);
     }
 
-    /*#[test]
-    fn test_handle_receive_create_n_limit_order_native_token() {
+    #[test]
+    fn test_handle_receive_create_multiple_limit_orders_same_user() {
         let (init_result, mut deps) = init_helper(
             HumanAddr("factoryaddress".to_string()),
             "factoryhash".to_string(),
             "factorykey".to_string(),
             AssetInfo {
-                is_native_token: false,
                 decimal_places: 18,
                 base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
                 token: Some(
                     Token {
-                        contract_addr: HumanAddr("token2address".to_string()),
-                        token_code_hash: "token2hash".to_string()
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
                     }
                 ),
                 native_token: None
             },
             AssetInfo {
-                is_native_token: true,
-                decimal_places: 6,
-                base_amount: Uint128(1000000),
-                token: None,
-                native_token: Some(
-                    NativeToken{denom:"uscrt".to_string()}
-                )
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -334,119 +416,196 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Bob send
-        let handle_msg = HandleMsg::ReceiveNativeToken {
-            is_bid: true,
-            price: Uint128(40)
-        };
+        // Bob ladders two bids on the same pair
+        for (amount, price) in vec![(4000000000000000000u128, 4000000000000000000u128), (3000000000000000000, 3000000000000000000)] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token2address".to_string()),
+                from: HumanAddr("bob".to_string()),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: true,
+                    price: Price::from_atomics(price),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
 
-        let handle_result = handle(&mut deps, mock_env(
-            "bob", 
-            &[Coin{amount:Uint128(400),denom:"uscrt".to_string(),}]), 
-            handle_msg.clone()
-        );
+            let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
 
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let user_orders = ReadonlyPrefixedStorage::new(USER_ORDERS, &deps.storage);
+        let user_order_map: UserOrderMap = load(&user_orders, user_address_bob.as_slice()).unwrap();
+        assert_eq!(user_order_map.active_orders, vec![1, 2]);
+
+        // Cancel only the first one
+        let handle_msg = HandleMsg::CancelLimitOrder { order_id: 1 };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        ); 
+        );
 
-        // Alice send
-        let handle_msg = HandleMsg::ReceiveNativeToken {
-            is_bid: true,
-            price: Uint128(50)
-        };
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order, None);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order.unwrap().price, Price::from_atomics(3000000000000000000));
 
-        let handle_result = handle(&mut deps, mock_env(
-            "alice", 
-            &[Coin{amount:Uint128(500000000000000),denom:"uscrt".to_string()}]), 
-            handle_msg.clone()
+        let user_orders = ReadonlyPrefixedStorage::new(USER_ORDERS, &deps.storage);
+        let user_order_map: UserOrderMap = load(&user_orders, user_address_bob.as_slice()).unwrap();
+        assert_eq!(user_order_map.active_orders, vec![2]);
+
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), vec![2]);
+
+        // Canceling it twice fails
+        let handle_msg = HandleMsg::CancelLimitOrder { order_id: 1 };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_handle_receive_match_crossing_limit_orders() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
         );
 
+        // Bob bids 10 token2 for 5 token1 at 2
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(10000000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(5000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        ); 
-
-        // Check Bob limit order
-        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
-
-        let limit_orders = ReadonlyPrefixedStorage::new(LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_bob.as_slice()).unwrap();
-
-        assert_eq!(load_limit_order.clone().unwrap().is_bid, true);
-        assert_eq!(load_limit_order.clone().unwrap().status, "Active".to_string());
-        assert_eq!(load_limit_order.clone().unwrap().price, Uint128(400000000000000));
-        assert_eq!(load_limit_order.clone().unwrap().balances, vec![Uint128(0),Uint128(400000000000000)]);
-
-        // Check Alice limit order
-        let user_address_alice = &deps.api.canonical_address(&HumanAddr("alice".to_string())).unwrap();
-
-        let limit_orders = ReadonlyPrefixedStorage::new(LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_alice.as_slice()).unwrap();
-
-        assert_eq!(load_limit_order.clone().unwrap().is_bid, true);
-        assert_eq!(load_limit_order.clone().unwrap().status, "Active".to_string());
-        assert_eq!(load_limit_order.clone().unwrap().price, Uint128(50));
-        assert_eq!(load_limit_order.clone().unwrap().balances, vec![Uint128(0),Uint128(500000000000000)]);
-
-        let mut bid_order_book:OrderQueue=load(&deps.storage, BID_ORDER_QUEUE).unwrap();
-        assert_eq!(bid_order_book.peek().unwrap().id, user_address_alice.clone());
-        assert_eq!(bid_order_book.peek().unwrap().price, Uint128(500000000000000));
-        assert_eq!(bid_order_book.pop().unwrap().id, user_address_alice.clone());
-        assert_eq!(bid_order_book.pop().unwrap().id, user_address_bob.clone());
-        assert_eq!(bid_order_book.peek(), None);
-        assert_eq!(bid_order_book.pop(), None);
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
 
-        // Trigerer send
-        /*let handle_msg = HandleMsg::TriggerLimitOrders {};
-        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), handle_msg.clone());
+        // Charlie asks 1.5 for 3 token1, which crosses Bob's bid
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(3000000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(1500000000000000000),
+                expected_amount: Uint128(4500000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        ); 
+        );
+        // Bob gets token1 and Charlie token2 directly
+        assert_eq!(handle_result.unwrap().messages.len(), 2);
 
-        let limit_orders = ReadonlyPrefixedStorage::new(LIMIT_ORDERS,&deps.storage);
-        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &user_address_alice.as_slice()).unwrap();
-        assert_eq!(load_limit_order.clone().unwrap().balances, vec![Uint128(0),Uint128(500000000000000)]);
-        */
+        // Charlie sold everything at Bob's price of 2
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_charlie.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Filled);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![Uint128(0), Uint128(6000000000000000000)]));
+
+        // The rest of Bob's bid stays on the book for the pair
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(load_limit_order.balances, vec![Uint128(0), Uint128(4000000000000000000)]);
+        assert_eq!(load_limit_order.withdrew_balance, Some(vec![Uint128(3000000000000000000), Uint128(0)]));
+
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), vec![1]);
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), Vec::<u64>::new());
     }
-*/
+
     #[test]
-    fn test_get_limit_order_to_trigger() {
-        // Test Limit Orders
-        // 1 Order - price 10, quantity 10
-        // 2 Order - price 9, quantity 2 
+    fn test_handle_receive_match_limit_orders_refund_dust() {
         let (init_result, mut deps) = init_helper(
             HumanAddr("factoryaddress".to_string()),
             "factoryhash".to_string(),
             "factorykey".to_string(),
             AssetInfo {
-                decimal_places: 6,
-                base_amount: Uint128(1000000),
-                min_amount: Uint128(1000000),
-                fee_amount: Uint128(500000),
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
                 token: Some(
                     Token {
                         contract_addr: HumanAddr("token1address".to_string()),
                         token_code_hash: "token1hash".to_string()
                     }
                 ),
+                native_token: None
             },
             AssetInfo {
                 decimal_places: 18,
-                min_amount: Uint128(1500000000000000),
                 base_amount: Uint128(1000000000000000000),
                 fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(500000000000000000),
                 token: Some(
                     Token {
                         contract_addr: HumanAddr("token2address".to_string()),
                         token_code_hash: "token2hash".to_string()
                     }
                 ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -457,258 +616,3703 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Bob send
+        // Bob bids 10 token2 for 5 token1 at 2 and Charlie sells him 4.9 token1, 0.2 token2 would be left resting
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token2address".to_string()), 
-            from: HumanAddr("bob".to_string()), 
-            amount: Uint128(2000000000000000000), // 2e18
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(10000000000000000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: true,
-                price: Uint128(8000000000000000000), // 8e18
-                expected_amount: Uint128(250000) //0.25e6
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(5000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(4900000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(1500000000000000000),
+                expected_amount: Uint128(7350000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        // Bob gets his token1 and the rest of his token2 back, Charlie gets token2
+        assert_eq!(handle_result.unwrap().messages, vec![
+            transfer_msg(HumanAddr("bob".to_string()), Uint128(4900000000000000000), None, BLOCK_SIZE, "token1hash".to_string(), HumanAddr("token1address".to_string())).unwrap(),
+            transfer_msg(HumanAddr("bob".to_string()), Uint128(200000000000000000), None, BLOCK_SIZE, "token2hash".to_string(), HumanAddr("token2address".to_string())).unwrap(),
+            transfer_msg(HumanAddr("charlie".to_string()), Uint128(9800000000000000000), None, BLOCK_SIZE, "token2hash".to_string(), HumanAddr("token2address".to_string())).unwrap()
+        ]);
 
-        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order, None);
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Filled);
+        assert_eq!(txs[0].balances, vec![Uint128(0), Uint128(0)]);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![Uint128(4900000000000000000), Uint128(200000000000000000)]));
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), Vec::<u64>::new());
 
+        // Eve asks 1.5 for 2 token1 and Frank buys them with 3.2 token2, 0.2 token2 of his bid would be left
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("eve".to_string()),
+            amount: Uint128(2000000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(1500000000000000000),
+                expected_amount: Uint128(3000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg);
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
         );
-
-        // Alice send
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token2address".to_string()), 
-            from: HumanAddr("alice".to_string()), 
-            amount: Uint128(10000000000000000000), // 10e18
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("frank".to_string()),
+            amount: Uint128(3200000000000000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: true,
-                price: Uint128(9000000000000000000), // 9e18
-                expected_amount: Uint128(1111111) // 1.11e6
+                price: Price::from_atomics(1600000000000000000),
+                expected_amount: Uint128(2000000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages, vec![
+            transfer_msg(HumanAddr("eve".to_string()), Uint128(3000000000000000000), None, BLOCK_SIZE, "token2hash".to_string(), HumanAddr("token2address".to_string())).unwrap(),
+            transfer_msg(HumanAddr("frank".to_string()), Uint128(2000000000000000000), None, BLOCK_SIZE, "token1hash".to_string(), HumanAddr("token1address".to_string())).unwrap(),
+            transfer_msg(HumanAddr("frank".to_string()), Uint128(200000000000000000), None, BLOCK_SIZE, "token2hash".to_string(), HumanAddr("token2address".to_string())).unwrap()
+        ]);
 
-        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        // Frank's bid does not rest on the book
+        let user_address_frank = &deps.api.canonical_address(&HumanAddr("frank".to_string())).unwrap();
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_frank.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Filled);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![Uint128(2000000000000000000), Uint128(200000000000000000)]));
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), Vec::<u64>::new());
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_handle_update_and_increase_limit_order() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Bob ladders two bids on the same pair
+        for (amount, price) in vec![(4000000000000000000u128, 4000000000000000000u128), (3000000000000000000, 3000000000000000000)] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token2address".to_string()),
+                from: HumanAddr("bob".to_string()),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: true,
+                    price: Price::from_atomics(price),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+
+            let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        // Amounts are validated again
+        let handle_msg = HandleMsg::UpdateLimitOrder { order_id: 2, price: Price::from_atomics(5000000000000000000), expected_amount: Uint128(1) };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        // Only the owner can update an order
+        let handle_msg = HandleMsg::UpdateLimitOrder { order_id: 2, price: Price::from_atomics(5000000000000000000), expected_amount: Uint128(1200000000000000000) };
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
 
+        // Bob raises his second bid above the first one
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        );      
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
 
-        use serde::{Deserialize, Serialize};
-        #[derive(Debug)]
-        struct MyMockQuerier {
-            expected_bid_base_request: Vec<u8>,
-            expected_bid_base_response: AmmPairSimulationResponse,
-            expected_bid_amount_request: Option<Vec<u8>>,
-            expected_bid_amount_response: Option<AmmPairSimulationResponse>,
-            expected_bid_amount2_request: Option<Vec<u8>>,
-            expected_bid_amount2_response: Option<AmmPairSimulationResponse>,
-        }
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.price, Price::from_atomics(5000000000000000000));
+        assert_eq!(load_limit_order.expected_amount, Uint128(1200000000000000000));
+        assert_eq!(load_limit_order.amendments.len(), 1);
+        assert_eq!(load_limit_order.amendments[0].price, Price::from_atomics(5000000000000000000));
 
-        #[derive(Serialize, Deserialize, Debug)]
-        pub struct MockedSimulation {
-            pub return_amount: Uint128,
-            pub spread_amount: Uint128,
-            pub commission_amount: Uint128
-        }
-        
-        pub fn space_pad(message: &mut Vec<u8>, block_size: usize) -> &mut Vec<u8> {
-            let len = message.len();
-            let surplus = len % block_size;
-            if surplus == 0 {
-                return message;
-            }
-        
-            let missing = block_size - surplus;
-            message.reserve(missing);
-            message.extend(std::iter::repeat(b' ').take(missing));
-            message
-        }
+        let bid_order_book: Vec<OrderIndex> = queued_orders(&deps.storage, BID_ORDER_QUEUE);
+        assert_eq!((bid_order_book[0].id, bid_order_book[0].price), (2, Price::from_atomics(5000000000000000000)));
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), vec![2, 1]);
 
-        pub fn check_mock_request(msg: Vec<u8>) -> Vec<u8>{
-            let mut expected_msg = msg.clone();
-            space_pad(&mut expected_msg, 256);
-            let expected_request: QueryRequest<AmmSimulationQuery> =
-            QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: HumanAddr("ammpairaddress".to_string()),
-                callback_code_hash: "ammpairhash".to_string(),
-                msg: Binary(expected_msg),
-            });
-            to_vec(&expected_request).unwrap()       
-        }
+        // Top ups must be sent in the deposit token
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(4000000000000000000),
+            msg: Some(to_binary(&HandleMsg::IncreaseLimitOrder { order_id: 1 }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
 
-        impl Querier for MyMockQuerier {
-            fn raw_query(&self, request: &[u8] ) -> QuerierResult {
-                let bid_base_req = check_mock_request(self.expected_bid_base_request.clone());
-                if request == bid_base_req {
-                    return Ok(to_binary(&MockedSimulation {
-                        return_amount: self.expected_bid_base_response.return_amount,
-                        spread_amount: self.expected_bid_base_response.spread_amount,
-                        commission_amount: self.expected_bid_base_response.commission_amount
-                    }));
-                }
-                if self.expected_bid_amount_request.clone() != None {
-                    let bid_amount_req = check_mock_request(self.expected_bid_amount_request.clone().unwrap());
-                    if request == bid_amount_req {
-                        return Ok(to_binary(&MockedSimulation {
-                            return_amount: self.expected_bid_amount_response.as_ref().unwrap().return_amount,
-                            spread_amount: self.expected_bid_amount_response.as_ref().unwrap().spread_amount,
-                            commission_amount: self.expected_bid_amount_response.as_ref().unwrap().commission_amount
-                        }));
-                    }  
-                }
-                
-                if self.expected_bid_amount2_request.clone() != None { 
-                    let bid_amount2_req = check_mock_request(self.expected_bid_amount2_request.clone().unwrap());
-                    if request == bid_amount2_req {
-                        return Ok(to_binary(&MockedSimulation {
-                            return_amount: self.expected_bid_amount2_response.as_ref().unwrap().return_amount,
-                            spread_amount: self.expected_bid_amount2_response.as_ref().unwrap().spread_amount,
-                            commission_amount: self.expected_bid_amount2_response.as_ref().unwrap().commission_amount
-                        }));
-                    } 
-                }
-                return Ok(to_binary(&request))
-            }
-        }
-        // BIDS
-        // sell token 2 for token 1
-        // Test 1 => AMM Base Price = 11 && Expected False
-        let mocked_deps = deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(11000000000000000000), // 11e18
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
+        // Bob doubles his first bid at the same price
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(4000000000000000000),
+            msg: Some(to_binary(&HandleMsg::IncreaseLimitOrder { order_id: 1 }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.deposit_amount, Uint128(8000000000000000000));
+        assert_eq!(load_limit_order.balances, vec![Uint128(0), Uint128(8000000000000000000)]);
+        assert_eq!(load_limit_order.expected_amount, Uint128(2000000000000000000));
+        assert_eq!(load_limit_order.amendments.len(), 1);
+    }
+
+    #[test]
+    fn test_handle_update_partially_filled_limit_order() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1000000000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
             },
-            expected_bid_amount_request: None,
-            expected_bid_amount_response: None,
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
-        });
-        
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        // Bob bids 10 token2 for 5 token1 and Charlie sells him 3 token1, 4 token2 are left
+        let handle_msgs = vec![
+            ("token2address", "bob", Uint128(10000000000000000000), HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(5000000000000000000),
+                order_type: None,
+                expires_at: None
+            }),
+            ("token1address", "charlie", Uint128(3000000000000000000), HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(1500000000000000000),
+                expected_amount: Uint128(4500000000000000000),
+                order_type: None,
+                expires_at: None
+            })
+        ];
+        for (token, user, amount, msg) in handle_msgs {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr(token.to_string()),
+                from: HumanAddr(user.to_string()),
+                amount,
+                msg: Some(to_binary(&msg).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env(token, &[]), handle_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(load_limit_order.balances, vec![Uint128(0), Uint128(4000000000000000000)]);
 
-        assert_eq!(needs_trigger, false);
+        // 2.2 token1 for the whole deposit is enough, but the 4 token2 left would only ask for 0.88 token1
+        let handle_msg = HandleMsg::UpdateLimitOrder { order_id: 1, price: Price::from_atomics(4500000000000000000), expected_amount: Uint128(2200000000000000000) };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg);
+        assert!(handle_result.is_err());
 
- 
-        // Test 2 => AMM Base Price = 9 && AMM Amount = 11 && Expected False
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(9000000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
+        // 3 token1 for the whole deposit asks 1.2 token1 for what is left
+        let handle_msg = HandleMsg::UpdateLimitOrder { order_id: 1, price: Price::from_atomics(3300000000000000000), expected_amount: Uint128(3000000000000000000) };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.expected_amount, Uint128(3000000000000000000));
+        assert_eq!(load_limit_order.balances, vec![Uint128(0), Uint128(4000000000000000000)]);
+    }
+
+    #[test]
+    fn test_handle_receive_create_n_limit_order_native_token() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
             },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(13000000000000000000), //1.111111 * 11 (rounded)
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("sscrtaddress".to_string()),
+                        token_code_hash: "sscrthash".to_string()
+                    }
+                ),
+                native_token: Some(
+                    NativeToken{denom:"uscrt".to_string()}
+                )
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Bob bids 10 SCRT for token1 at 1 SCRT
+        let handle_msg = HandleMsg::ReceiveNativeToken {
+            msg: to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(1000000000000000000),
+                expected_amount: Uint128(10000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap()
+        };
+        let handle_result = handle(&mut deps, mock_env(
+            "bob",
+            &[Coin{amount:Uint128(10000000),denom:"uscrt".to_string()}]),
+            handle_msg.clone()
+        );
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        // The deposit is wrapped to sSCRT
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, send, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("sscrtaddress".to_string()));
+                assert_eq!(*send, vec![Coin{amount:Uint128(10000000),denom:"uscrt".to_string()}]);
+            },
+            _ => panic!("Unexpected message")
+        }
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.deposit_token_index, 1);
+        assert_eq!(load_limit_order.balances, vec![Uint128(0),Uint128(10000000)]);
+
+        // Only the native denom of the pair is accepted
+        let handle_result = handle(&mut deps, mock_env(
+            "bob",
+            &[Coin{amount:Uint128(10000000),denom:"uatom".to_string()}]),
+            handle_msg.clone()
+        );
+        assert!(handle_result.is_err());
+
+        // Charlie asks 3 token1 at 2 SCRT
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(3000000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(6000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Token1 is worth 2 SCRT, so only Charlie's ask triggers. The pair pays it in SCRT with a bank send
+        deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(2000000),
+            token1_base_amount: Uint128(1000000000000000000),
+            max_offer_token1: Uint128(1000000000000000000000),
         });
+        let trigger_env = mock_env("trigerer", &[]);
+        let handle_result = handle(&mut deps, trigger_env.clone(), HandleMsg::TriggerLimitOrders { max_orders: Some(2) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        // Token1 is sent to the pair, then the order book settles the coins it got back
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], snip20::send_msg(
+            HumanAddr("ammpairaddress".to_string()),
+            Uint128(3000000000000000000),
+            Some(padded_binary(&AmmPairReceiveMsg::Swap {
+                expected_return: Some(Uint128(6000000)),
+                to: None
+            }).unwrap()),
+            None,
+            BLOCK_SIZE,
+            "token1hash".to_string(),
+            HumanAddr("token1address".to_string())
+        ).unwrap());
+        assert_eq!(messages[1], CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+            callback_code_hash: trigger_env.contract_code_hash.clone(),
+            msg: padded_binary(&HandleMsg::SettleNativeSwap {}).unwrap(),
+            send: vec![]
+        }));
 
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        // Only the order book settles its native swaps
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), HandleMsg::SettleNativeSwap {});
+        assert!(handle_result.is_err());
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        // The pair paid 6.5 SCRT, they are wrapped again before Charlie's share is redeemed and sent
+        deps.change_querier(|_| MockQuerier::new(&[(
+            &HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+            &[Coin{amount:Uint128(6500000),denom:"uscrt".to_string()}]
+        )]));
+        let handle_result = handle(&mut deps, mock_env(MOCK_CONTRACT_ADDR, &[]), HandleMsg::SettleNativeSwap {});
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages, vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr("sscrtaddress".to_string()),
+                callback_code_hash: "sscrthash".to_string(),
+                msg: padded_binary(&Snip20Msg::deposit()).unwrap(),
+                send: vec![Coin{amount:Uint128(6500000),denom:"uscrt".to_string()}]
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr("sscrtaddress".to_string()),
+                callback_code_hash: "sscrthash".to_string(),
+                msg: padded_binary(&Snip20Msg::redeem(Uint128(6000000))).unwrap(),
+                send: vec![]
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                to_address: HumanAddr("charlie".to_string()),
+                amount: vec![Coin{amount:Uint128(6000000),denom:"uscrt".to_string()}]
+            })
+        ]);
+        let pending_swaps: Option<Vec<PendingSwap>> = may_load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert!(pending_swaps.is_none());
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order, None);
 
-        assert_eq!(needs_trigger, false);
-   
-        // Test 3 => AMM Base Price = 8 && AMM Amount = 9 && Expected True
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
+        // Token1 drops to 0.5 SCRT so Bob's bid triggers, his sSCRT is redeemed and sent to the pair as SCRT
+        deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(500000),
+            token1_base_amount: Uint128(1000000000000000000),
+            max_offer_token1: Uint128(1000000000000000000000),
+        });
+        let handle_result = handle(&mut deps, trigger_env.clone(), HandleMsg::TriggerLimitOrders { max_orders: Some(2) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages, vec![
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr("sscrtaddress".to_string()),
+                callback_code_hash: "sscrthash".to_string(),
+                msg: padded_binary(&Snip20Msg::redeem(Uint128(10000000))).unwrap(),
+                send: vec![]
+            }),
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr("ammpairaddress".to_string()),
+                callback_code_hash: "ammpairhash".to_string(),
+                msg: padded_binary(&AmmPairHandleMsg::Swap {
+                    offer_asset: AmmSimulationOfferAsset {
+                        info: AmmAssetInfo::NativeToken { denom: "uscrt".to_string() },
+                        amount: Uint128(10000000)
+                    },
+                    expected_return: Some(Uint128(10000000000000000000)),
+                    to: None
+                }).unwrap(),
+                send: vec![Coin{amount:Uint128(10000000),denom:"uscrt".to_string()}]
+            })
+        ]);
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::Executing);
+
+        // The pair pays token1 through the Receive hook like for any other order
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(20000000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages, vec![transfer_msg(
+            HumanAddr("bob".to_string()),
+            Uint128(19500000000000000000),
+            None,
+            BLOCK_SIZE,
+            "token1hash".to_string(),
+            HumanAddr("token1address".to_string())
+        ).unwrap()]);
+    }
+
+    #[test]
+    fn test_order_queue_price_levels() {
+        let mut storage = MockStorage::new();
+        let mut order_queue = OrderQueue::new(true, OrderType::Limit);
+        for (id, price) in vec![(1u64, 2u128), (2, 3), (3, 2), (4, 1), (5, 3)] {
+            order_queue.insert(&mut storage, id, HumanAddr("bob".to_string()), Price::from_atomics(price), id, id, None, None).unwrap();
+        }
+        save(&mut storage, BID_ORDER_QUEUE, &order_queue).unwrap();
+
+        // Bids are served from the highest price, first in first out within a price
+        assert_eq!(queued_order_ids(&storage, BID_ORDER_QUEUE), vec![2, 5, 1, 3, 4]);
+
+        // Removing touches only its own level, empty levels go away
+        assert_eq!(order_queue.remove(&mut storage, 5).unwrap().unwrap().id, 5);
+        assert_eq!(order_queue.remove(&mut storage, 2).unwrap().unwrap().id, 2);
+        assert_eq!(order_queue.remove(&mut storage, 2).unwrap(), None);
+        save(&mut storage, BID_ORDER_QUEUE, &order_queue).unwrap();
+        assert_eq!(queued_order_ids(&storage, BID_ORDER_QUEUE), vec![1, 3, 4]);
+        assert_eq!(order_queue.peek(&storage).unwrap().unwrap().price, Price::from_atomics(2));
+
+        // Asks start from the lowest price
+        let mut order_queue = OrderQueue::new(false, OrderType::Limit);
+        for (id, price) in vec![(6u64, 2u128), (7, 1)] {
+            order_queue.insert(&mut storage, id, HumanAddr("bob".to_string()), Price::from_atomics(price), id, id, None, None).unwrap();
+        }
+        save(&mut storage, ASK_ORDER_QUEUE, &order_queue).unwrap();
+        assert_eq!(queued_order_ids(&storage, ASK_ORDER_QUEUE), vec![7, 6]);
+    }
+
+    // Small xorshift generator so the property tests below are repeatable without extra crates
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn random_order_indexes(state: &mut u64, is_bid: bool, order_type: OrderType, count: u64) -> Vec<OrderIndex> {
+        (1..=count).map(|sequence| OrderIndex {
+            id: next_random(state) % 1000,
+            owner: HumanAddr("bob".to_string()),
+            // Few distinct prices so ties are common
+            price: Price::from_atomics((next_random(state) % 5) as u128),
+            // Orders in the same block share their timestamp
+            timestamp: next_random(state) % 3,
+            sequence,
+            is_bid,
+            order_type: order_type.clone(),
+            linked_order_id: None,
+            expires_at: None
+        }).collect()
+    }
+
+    #[test]
+    fn test_order_index_is_strict_total_order() {
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        for (is_bid, order_type) in vec![(true, OrderType::Limit), (false, OrderType::Limit), (true, OrderType::Stop), (false, OrderType::Stop)] {
+            for _ in 0..20 {
+                let order_indexes = random_order_indexes(&mut state, is_bid, order_type.clone(), 12);
+                for a in order_indexes.iter() {
+                    // Never ordered before itself, and only equal to itself
+                    assert_eq!(a.cmp(a), std::cmp::Ordering::Equal);
+                    for b in order_indexes.iter() {
+                        assert_eq!(a == b, a.sequence == b.sequence);
+                        // Antisymmetric
+                        assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                        for c in order_indexes.iter() {
+                            // Transitive
+                            if a > b && b > c {
+                                assert!(a > c);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_queue_follows_order_index_priority() {
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        for (is_bid, order_type) in vec![(true, OrderType::Limit), (false, OrderType::Limit), (true, OrderType::Stop), (false, OrderType::Stop)] {
+            let mut storage = MockStorage::new();
+            let queue_key = order_queue_key(is_bid, &order_type);
+            let mut order_queue = OrderQueue::new(is_bid, order_type.clone());
+            let mut order_indexes = random_order_indexes(&mut state, is_bid, order_type, 30);
+            for (position, order_index) in order_indexes.iter_mut().enumerate() {
+                order_index.id = position as u64;
+                order_queue.insert(&mut storage, order_index.id, order_index.owner.clone(), order_index.price, order_index.timestamp, order_index.sequence, None, None).unwrap();
+            }
+            save(&mut storage, queue_key, &order_queue).unwrap();
+
+            // The queue serves the greatest order first, ties at a price go to the lowest sequence
+            order_indexes.sort();
+            order_indexes.reverse();
+            let queued_ids: Vec<u64> = queued_order_ids(&storage, queue_key);
+            assert_eq!(queued_ids, order_indexes.iter().map(|order_index| order_index.id).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn test_order_queue_keeps_priority_through_removals() {
+        let mut state: u64 = 0x3c6ef372fe94f82b;
+        for (is_bid, order_type) in vec![(true, OrderType::Limit), (false, OrderType::Limit)] {
+            let mut storage = MockStorage::new();
+            let queue_key = order_queue_key(is_bid, &order_type);
+            let mut order_queue = OrderQueue::new(is_bid, order_type.clone());
+            let mut order_indexes = random_order_indexes(&mut state, is_bid, order_type, 200);
+            for (position, order_index) in order_indexes.iter_mut().enumerate() {
+                order_index.id = position as u64;
+                // Many price levels so the skip list grows several lanes
+                order_index.price = Price::from_atomics((next_random(&mut state) % 300) as u128);
+            }
+            // Sequences out of order within a level are placed by sequence
+            let mut insert_order: Vec<usize> = (0..order_indexes.len()).collect();
+            for position in (1..insert_order.len()).rev() {
+                insert_order.swap(position, (next_random(&mut state) % (position as u64 + 1)) as usize);
+            }
+            for position in insert_order {
+                let order_index = &order_indexes[position];
+                order_queue.insert(&mut storage, order_index.id, order_index.owner.clone(), order_index.price, order_index.timestamp, order_index.sequence, None, None).unwrap();
+            }
+
+            let mut remaining: Vec<OrderIndex> = vec![];
+            for order_index in order_indexes {
+                if next_random(&mut state) % 2 == 0 {
+                    assert_eq!(order_queue.remove(&mut storage, order_index.id).unwrap().unwrap().id, order_index.id);
+                } else {
+                    remaining.push(order_index);
+                }
+            }
+            save(&mut storage, queue_key, &order_queue).unwrap();
+
+            remaining.sort();
+            remaining.reverse();
+            assert_eq!(queued_order_ids(&storage, queue_key), remaining.iter().map(|order_index| order_index.id).collect::<Vec<u64>>());
+            assert_eq!(order_queue.peek(&storage).unwrap().unwrap().id, remaining[0].id);
+
+            for order_index in remaining.iter() {
+                order_queue.remove(&mut storage, order_index.id).unwrap();
+            }
+            assert!(order_queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_order_queue_expired_reads_only_expired_orders() {
+        let mut storage = MockStorage::new();
+        let block = mock_env("trigerer", &[]).block;
+        let mut order_queue = OrderQueue::new(false, OrderType::Limit);
+        let expirations = vec![
+            (1u64, Some(Expiration::AtHeight(block.height + 5))),
+            (2, Some(Expiration::AtHeight(block.height))),
+            (3, None),
+            (4, Some(Expiration::AtTime(block.time - 1))),
+            (5, Some(Expiration::AtHeight(block.height - 1))),
+            (6, Some(Expiration::AtTime(block.time + 5))),
+        ];
+        for (id, expires_at) in expirations {
+            order_queue.insert(&mut storage, id, HumanAddr("bob".to_string()), Price::from_atomics(id as u128), id, id, None, expires_at).unwrap();
+        }
+
+        // Soonest expiration first, heights before times, never past the limit
+        let expired_ids = |order_queue: &OrderQueue, storage: &MockStorage, limit: u32| -> Vec<u64> {
+            order_queue.expired(storage, &block, limit).unwrap().iter().map(|order_index| order_index.id).collect()
+        };
+        assert_eq!(expired_ids(&order_queue, &storage, 10), vec![5, 2, 4]);
+        assert_eq!(expired_ids(&order_queue, &storage, 2), vec![5, 2]);
+
+        // Removed orders leave the index
+        order_queue.remove(&mut storage, 5).unwrap();
+        order_queue.remove(&mut storage, 4).unwrap();
+        assert_eq!(expired_ids(&order_queue, &storage, 10), vec![2]);
+    }
+
+    #[test]
+    fn test_price_decimals() {
+        let token2_info = AssetInfo {
+            decimal_places: 6,
+            base_amount: Uint128(1000000),
+            fee_amount: Uint128(500000),
+            min_amount: Uint128(1000000),
+            token: Some(
+                Token {
+                    contract_addr: HumanAddr("token2address".to_string()),
+                    token_code_hash: "token2hash".to_string()
+                }
+            ),
+            native_token: None
+        };
+
+        let price: Price = "1.25".parse().unwrap();
+        assert_eq!(price, Price::from_atomics(1250000000000000000));
+        assert_eq!(price.to_string(), "1.25".to_string());
+        assert_eq!(to_binary(&price).unwrap(), Binary::from(b"\"1.25\"".to_vec()));
+        assert_eq!(from_binary::<Price>(&Binary::from(b"\"0.5\"".to_vec())).unwrap(), Price::from_atomics(500000000000000000));
+        assert!("1.2.3".parse::<Price>().is_err());
+        assert!("".parse::<Price>().is_err());
+        assert!("-1".parse::<Price>().is_err());
+
+        // Prices are kept in whole tokens and converted with the decimals of both tokens
+        let token1_info = asset_info(6, Uint128(1000000), "token1address");
+        assert_eq!(price.to_base_amount(&token1_info, &token2_info).unwrap(), Uint128(1250000));
+        assert_eq!(Price::from_base_amount(Uint128(1250000), &token1_info, &token2_info).unwrap(), price);
+        // A price finer than token2 can pay is rejected instead of rounded
+        assert!("1.0000001".parse::<Price>().unwrap().to_base_amount(&token1_info, &token2_info).is_err());
+    }
+
+    fn asset_info(decimal_places: u8, base_amount: Uint128, contract_addr: &str) -> AssetInfo {
+        AssetInfo {
+            decimal_places,
+            base_amount,
+            fee_amount: Uint128(0),
+            min_amount: Uint128(1),
+            token: Some(
+                Token {
+                    contract_addr: HumanAddr(contract_addr.to_string()),
+                    token_code_hash: "tokenhash".to_string()
+                }
+            ),
+            native_token: None
+        }
+    }
+
+    #[test]
+    fn test_price_mixed_decimals() {
+        let price: Price = "1.25".parse().unwrap();
+
+        // 18 decimals token1 quoted per whole token, 6 decimals token2
+        let token1_info = asset_info(18, Uint128(1000000000000000000), "token1address");
+        let token2_info = asset_info(6, Uint128(1000000), "token2address");
+        assert_eq!(price.to_base_amount(&token1_info, &token2_info).unwrap(), Uint128(1250000));
+        assert_eq!(Price::from_base_amount(Uint128(1250000), &token1_info, &token2_info).unwrap(), price);
+
+        // 6 decimals token1 quoted per whole token, 18 decimals token2
+        let token1_info = asset_info(6, Uint128(1000000), "token1address");
+        let token2_info = asset_info(18, Uint128(1000000000000000000), "token2address");
+        assert_eq!(price.to_base_amount(&token1_info, &token2_info).unwrap(), Uint128(1250000000000000000));
+        assert_eq!(Price::from_base_amount(Uint128(1250000000000000000), &token1_info, &token2_info).unwrap(), price);
+
+        // 8 decimals token1 quoted per thousandth of a token, 6 decimals token2
+        let token1_info = asset_info(8, Uint128(100000), "token1address");
+        let token2_info = asset_info(6, Uint128(1000000), "token2address");
+        let price: Price = "2000".parse().unwrap();
+        assert_eq!(price.to_base_amount(&token1_info, &token2_info).unwrap(), Uint128(2000000));
+        assert_eq!(Price::from_base_amount(Uint128(2000000), &token1_info, &token2_info).unwrap(), price);
+        // A thousandth of token1 at 2000.0001 would cost a fraction of a token2 unit
+        assert!("2000.0001".parse::<Price>().unwrap().to_base_amount(&token1_info, &token2_info).is_err());
+        assert!("2000.001".parse::<Price>().unwrap().to_base_amount(&token1_info, &token2_info).is_ok());
+    }
+
+    #[test]
+    fn test_order_status_transitions() {
+        assert!(OrderStatus::Active.can_transition_to(&OrderStatus::Executing));
+        assert!(OrderStatus::Executing.can_transition_to(&OrderStatus::PartiallyFilled));
+        assert!(OrderStatus::Executing.can_transition_to(&OrderStatus::Filled));
+        assert!(OrderStatus::Executing.can_transition_to(&OrderStatus::Active));
+        assert!(OrderStatus::PartiallyFilled.can_transition_to(&OrderStatus::Canceled));
+        assert!(OrderStatus::Active.can_transition_to(&OrderStatus::Expired));
+        // An order in flight can not be canceled or expired and final states stay final
+        assert!(!OrderStatus::Executing.can_transition_to(&OrderStatus::Canceled));
+        assert!(!OrderStatus::Executing.can_transition_to(&OrderStatus::Executing));
+        assert!(!OrderStatus::Filled.can_transition_to(&OrderStatus::Canceled));
+        assert!(!OrderStatus::Canceled.can_transition_to(&OrderStatus::Active));
+        assert!(!OrderStatus::Expired.can_transition_to(&OrderStatus::Filled));
+    }
+
+    #[test]
+    fn test_get_limit_order_to_trigger() {
+        // Test Limit Orders
+        // 1 Order - price 10, quantity 10
+        // 2 Order - price 9, quantity 2 
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                min_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                min_amount: Uint128(1500000000000000),
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Bob send
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()), 
+            from: HumanAddr("bob".to_string()), 
+            amount: Uint128(2000000000000000000), // 2e18
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(8000000000000000000), // 8e18
+                expected_amount: Uint128(250000), //0.25e6
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Alice send
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()), 
+            from: HumanAddr("alice".to_string()), 
+            amount: Uint128(10000000000000000000), // 10e18
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(9000000000000000000), // 9e18
+                expected_amount: Uint128(1111111), // 1.11e6
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );      
+
+        use serde::{Deserialize, Serialize};
+        #[derive(Debug)]
+        struct MyMockQuerier {
+            expected_bid_base_request: Vec<u8>,
+            expected_bid_base_response: AmmPairSimulationResponse,
+            expected_bid_amount_request: Option<Vec<u8>>,
+            expected_bid_amount_response: Option<AmmPairSimulationResponse>,
+            expected_bid_amount2_request: Option<Vec<u8>>,
+            expected_bid_amount2_response: Option<AmmPairSimulationResponse>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        pub struct MockedSimulation {
+            pub return_amount: Uint128,
+            pub spread_amount: Uint128,
+            pub commission_amount: Uint128
+        }
+        
+        pub fn space_pad(message: &mut Vec<u8>, block_size: usize) -> &mut Vec<u8> {
+            let len = message.len();
+            let surplus = len % block_size;
+            if surplus == 0 {
+                return message;
+            }
+        
+            let missing = block_size - surplus;
+            message.reserve(missing);
+            message.extend(std::iter::repeat(b' ').take(missing));
+            message
+        }
+
+        pub fn check_mock_request(msg: Vec<u8>) -> Vec<u8>{
+            let mut expected_msg = msg.clone();
+            space_pad(&mut expected_msg, 256);
+            let expected_request: QueryRequest<AmmSimulationQuery> =
+            QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: HumanAddr("ammpairaddress".to_string()),
+                callback_code_hash: "ammpairhash".to_string(),
+                msg: Binary(expected_msg),
+            });
+            to_vec(&expected_request).unwrap()       
+        }
+
+        impl Querier for MyMockQuerier {
+            fn raw_query(&self, request: &[u8] ) -> QuerierResult {
+                let bid_base_req = check_mock_request(self.expected_bid_base_request.clone());
+                if request == bid_base_req {
+                    return Ok(to_binary(&MockedSimulation {
+                        return_amount: self.expected_bid_base_response.return_amount,
+                        spread_amount: self.expected_bid_base_response.spread_amount,
+                        commission_amount: self.expected_bid_base_response.commission_amount
+                    }));
+                }
+                if self.expected_bid_amount_request.clone() != None {
+                    let bid_amount_req = check_mock_request(self.expected_bid_amount_request.clone().unwrap());
+                    if request == bid_amount_req {
+                        return Ok(to_binary(&MockedSimulation {
+                            return_amount: self.expected_bid_amount_response.as_ref().unwrap().return_amount,
+                            spread_amount: self.expected_bid_amount_response.as_ref().unwrap().spread_amount,
+                            commission_amount: self.expected_bid_amount_response.as_ref().unwrap().commission_amount
+                        }));
+                    }  
+                }
+                
+                if self.expected_bid_amount2_request.clone() != None { 
+                    let bid_amount2_req = check_mock_request(self.expected_bid_amount2_request.clone().unwrap());
+                    if request == bid_amount2_req {
+                        return Ok(to_binary(&MockedSimulation {
+                            return_amount: self.expected_bid_amount2_response.as_ref().unwrap().return_amount,
+                            spread_amount: self.expected_bid_amount2_response.as_ref().unwrap().spread_amount,
+                            commission_amount: self.expected_bid_amount2_response.as_ref().unwrap().commission_amount
+                        }));
+                    } 
+                }
+                return Ok(to_binary(&request))
+            }
+        }
+        // BIDS
+        // sell token 2 for token 1
+        // Test 1 => AMM Base Price = 11 && Expected False
+        let mocked_deps = deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(11000000000000000000), // 11e18
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: None,
+            expected_bid_amount_response: None,
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+        
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);
+
+ 
+        // Test 2 => AMM Base Price = 9 && AMM Amount = 11 && Expected False
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(9000000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(13000000000000000000), //1.111111 * 11 (rounded)
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);
+   
+        // Test 3 => AMM Base Price = 8 && AMM Amount = 9 && Expected True
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(8000000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(10000000000000000000), // 1.1111*9 (rounded)
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, true);
+  
+        // Test 4 => AMM Base Price = 7 && AMM Amount = 11 && AMM Amount2 = 8 && Expected True
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(7000000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(13000000000000000000), // 1.1111*11 (rounded)  
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"250000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(2000000000000000000), //0.25*(simulated_price) => 0.25*8
+                spread_amount: Uint128(0),  
+                commission_amount: Uint128(0)
+            }), // 7*2 + 2 of spread
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, true);
+
+       // Test 5 => AMM Base Price = 8 && AMM Amount = 10 && AMM Amount2 = 9 && Expected False
+        let mut mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
                 return_amount: Uint128(8000000000000000000),
                 spread_amount: Uint128(0),
                 commission_amount: Uint128(0)
             },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(10000000000000000000), // 1.1111*9 (rounded)
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(12000000000000000000), // 1.1111*10 (rounded) => 12
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"250000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(2250000000000000000), //0.25*(simulated_price) => 0.25*9 = 2.25
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);
+    }
+
+    #[test]
+    fn test_get_limit_order_to_trigger_ask() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        use serde::{Deserialize, Serialize};
+        #[derive(Debug)]
+        struct MyMockQuerier {
+            expected_bid_base_request: Vec<u8>,
+            expected_bid_base_response: AmmPairSimulationResponse,
+            expected_bid_amount_request: Option<Vec<u8>>,
+            expected_bid_amount_response: Option<AmmPairSimulationResponse>,
+            expected_bid_amount2_request: Option<Vec<u8>>,
+            expected_bid_amount2_response: Option<AmmPairSimulationResponse>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug)]
+        pub struct MockedSimulation {
+            pub return_amount: Uint128,
+            pub spread_amount: Uint128,
+            pub commission_amount: Uint128
+        }
+        
+        pub fn space_pad(message: &mut Vec<u8>, block_size: usize) -> &mut Vec<u8> {
+            let len = message.len();
+            let surplus = len % block_size;
+            if surplus == 0 {
+                return message;
+            }
+        
+            let missing = block_size - surplus;
+            message.reserve(missing);
+            message.extend(std::iter::repeat(b' ').take(missing));
+            message
+        }
+
+        pub fn check_mock_request(msg: Vec<u8>) -> Vec<u8>{
+            let mut expected_msg = msg.clone();
+            space_pad(&mut expected_msg, 256);
+            let expected_request: QueryRequest<AmmSimulationQuery> =
+            QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: HumanAddr("ammpairaddress".to_string()),
+                callback_code_hash: "ammpairhash".to_string(),
+                msg: Binary(expected_msg),
+            });
+            to_vec(&expected_request).unwrap()       
+        }
+
+        impl Querier for MyMockQuerier {
+            fn raw_query(&self, request: &[u8] ) -> QuerierResult {
+                let bid_base_req = check_mock_request(self.expected_bid_base_request.clone());
+                if request == bid_base_req {
+                    return Ok(to_binary(&MockedSimulation {
+                        return_amount: self.expected_bid_base_response.return_amount,
+                        spread_amount: self.expected_bid_base_response.spread_amount,
+                        commission_amount: self.expected_bid_base_response.commission_amount
+                    }));
+                }
+                if self.expected_bid_amount_request.clone() != None {
+                    let bid_amount_req = check_mock_request(self.expected_bid_amount_request.clone().unwrap());
+                    if request == bid_amount_req {
+                        return Ok(to_binary(&MockedSimulation {
+                            return_amount: self.expected_bid_amount_response.as_ref().unwrap().return_amount,
+                            spread_amount: self.expected_bid_amount_response.as_ref().unwrap().spread_amount,
+                            commission_amount: self.expected_bid_amount_response.as_ref().unwrap().commission_amount
+                        }));
+                    }  
+                }
+                
+                if self.expected_bid_amount2_request.clone() != None { 
+                    let bid_amount2_req = check_mock_request(self.expected_bid_amount2_request.clone().unwrap());
+                    if request == bid_amount2_req {
+                        return Ok(to_binary(&MockedSimulation {
+                            return_amount: self.expected_bid_amount2_response.as_ref().unwrap().return_amount,
+                            spread_amount: self.expected_bid_amount2_response.as_ref().unwrap().spread_amount,
+                            commission_amount: self.expected_bid_amount2_response.as_ref().unwrap().commission_amount
+                        }));
+                    } 
+                }
+                
+                return Ok(to_binary(&request))
+            }
+        }
+
+        // SEND ASKS
+        // sell token 1 for token 2
+        // Charlie send
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()), 
+            from: HumanAddr("charlie".to_string()), 
+            amount: Uint128(120000), //0.12 
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(110000000000000000), //0.11
+                expected_amount: Uint128(13200000000000000), //0.0132 (0.12*0.11)
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );        
+
+        // Rob send
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()), 
+            from: HumanAddr("rob".to_string()), 
+            amount: Uint128(20000), //0.02
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(120000000000000000), //0.12
+                expected_amount: Uint128(2400000000000000), //0.0024 (0.12*0.02)
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );   
+   
+        // ASK Test 1 => AMM Base Price = 0.1 && Expected False
+        let mocked_deps = deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(100000000000000000), //0.1
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: None,
+            expected_bid_amount_response: None,
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);
+
+        // Test 2 => AMM Base Price = 0.11 && AMM Amount = 0.1 && Expected False
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request:  r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(110000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);
+ 
+        // Test 3 => AMM Base Price = 0.12 && AMM Amount = 0.11 && Expected True     
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(120000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(13200000000000000), // 0.12 * 0.11
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: None,
+            expected_bid_amount2_response: None,
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, true);
+
+        // Test 4 => AMM Base Price = 0.13 && AMM Amount = 0.1 && AMM Amount2 = 0.12 && Expected True
+        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(130000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"20000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(2400000000000000), // 0.02 * 0.12
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, true);
+ 
+        // Test 5 => AMM Base Price = 0.13 && AMM Amount = 0.1 && AMM Amount2 = 0.11 && Expected False
+        let mut mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
+            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
+            expected_bid_base_response: AmmPairSimulationResponse {
+                return_amount: Uint128(130000000000000000),
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            },
+            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"20000"}}}"#.as_bytes().to_vec()),
+            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
+                return_amount: Uint128(2200000000000000), // 0.02 * 0.11
+                spread_amount: Uint128(0),
+                commission_amount: Uint128(0)
+            }),
+        });
+
+        let query_msg = QueryMsg::CheckOrderBookTrigger {};
+        let query_result = query(&mocked_deps, query_msg);
+
+        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+
+        assert_eq!(needs_trigger, false);   
+    }
+
+    #[test]
+    fn test_handle_receive_from_swap() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Bob send
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()), 
+            from: HumanAddr("bob".to_string()), 
+            amount: Uint128(2000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(9000000000000000000),
+                expected_amount: Uint128(9000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()), 
+            from: HumanAddr("ammpairaddress".to_string()), 
+            amount: Uint128(9500000000000000000),
+            msg: None
+        };
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+
+        let swapped_order: OrderIndex = OrderIndex {
+            id: 1,
+            owner: HumanAddr("bob".to_string()),
+            price: Price::from_atomics(9000000000000000000),
+            timestamp: mock_env("token1address", &[]).block.time,
+            sequence: 1,
+            is_bid: false,
+            order_type: OrderType::Limit,
+            linked_order_id: None,
+            expires_at: None
+        };
+        save(&mut deps.storage, PENDING_SWAPS, &vec![PendingSwap {
+            order: swapped_order,
+            trigger_address: HumanAddr("trigerer".to_string()),
+            offer_amount: Uint128(2000000),
+            deposit_fee: Uint128(0),
+            expected_return: Uint128(9000000000000000000),
+            block_height: mock_env("trigerer", &[]).block.height
+        }]).unwrap();
+
+        // The pair paying less than the order expects is not settled
+        let short_handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()), 
+            from: HumanAddr("ammpairaddress".to_string()), 
+            amount: Uint128(8000000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), short_handle_msg);
+        assert!(handle_result.is_err());
+
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        ); 
+        // User transfer, the triggerer fee is booked
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order, None);
+
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Filled);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![Uint128(0), Uint128(9500000000000000000)]));
+    }
+
+    #[test]
+    fn test_handle_receive_from_swap_oco_order() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Bob takes profit at 4.5 or stops out at 0.5 with the same deposit
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()), 
+            from: HumanAddr("bob".to_string()), 
+            amount: Uint128(2000000),
+            msg: Some(to_binary(&HandleMsg::CreateOcoOrder {
+                is_bid: false,
+                limit_price: Price::from_atomics(4500000000000000000),
+                limit_expected_amount: Uint128(9000000000000000000),
+                stop_price: Price::from_atomics(500000000000000000),
+                stop_expected_amount: Uint128(900000000000000000),
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let limit_leg: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        let stop_leg: LimitOrderState = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(limit_leg.order_type, OrderType::Limit);
+        assert_eq!(limit_leg.linked_order_id, Some(2));
+        assert_eq!(stop_leg.order_type, OrderType::Stop);
+        assert_eq!(stop_leg.linked_order_id, Some(1));
+        assert_eq!(stop_leg.balances, vec![Uint128(2000000), Uint128(0)]);
+
+        assert_eq!(queued_order_ids(&deps.storage, STOP_ASK_ORDER_QUEUE), vec![2]);
+
+        // The take profit leg fills
+        let swapped_order: OrderIndex = queued_orders(&deps.storage, ASK_ORDER_QUEUE).remove(0);
+        assert_eq!(swapped_order.linked_order_id, Some(2));
+        save(&mut deps.storage, PENDING_SWAPS, &vec![PendingSwap {
+            order: swapped_order,
+            trigger_address: HumanAddr("trigerer".to_string()),
+            offer_amount: Uint128(2000000),
+            deposit_fee: Uint128(0),
+            expected_return: Uint128(9000000000000000000),
+            block_height: mock_env("trigerer", &[]).block.height
+        }]).unwrap();
+
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()), 
+            from: HumanAddr("ammpairaddress".to_string()), 
+            amount: Uint128(9500000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        ); 
+        // Only the filled leg pays out
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+
+        // Both legs are retired
+        let user_orders = ReadonlyPrefixedStorage::new(USER_ORDERS, &deps.storage);
+        let user_order_map: UserOrderMap = load(&user_orders, user_address_bob.as_slice()).unwrap();
+        assert_eq!(user_order_map.active_orders, Vec::<u64>::new());
+
+        assert_eq!(queued_order_ids(&deps.storage, STOP_ASK_ORDER_QUEUE), Vec::<u64>::new());
+
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs.len(), 2);
+        assert_eq!((txs[0].id, txs[0].status.clone()), (1, OrderStatus::Filled));
+        assert_eq!((txs[1].id, txs[1].status.clone()), (2, OrderStatus::Canceled));
+        assert_eq!(txs[1].balances, vec![Uint128(0), Uint128(0)]);
+    }
+
+    #[test]
+    fn test_oco_leg_waits_for_executing_leg() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // The pair pays 0.48 token2 per token1, under Bob's stop price
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(480000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Bob takes profit at 4.5 or stops out at 0.5 with the same deposit
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(2000000),
+            msg: Some(to_binary(&HandleMsg::CreateOcoOrder {
+                is_bid: false,
+                limit_price: Price::from_atomics(4500000000000000000),
+                limit_expected_amount: Uint128(9000000000000000000),
+                stop_price: Price::from_atomics(500000000000000000),
+                stop_expected_amount: Uint128(900000000000000000),
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // The stop loss leg is swapped
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps.iter().map(|pending_swap| pending_swap.order.id).collect::<Vec<u64>>(), vec![2]);
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let limit_leg: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        let stop_leg: LimitOrderState = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(stop_leg.status, OrderStatus::Executing);
+
+        // Alice's bid crosses the take profit leg, which waits for the stop loss swap instead of filling
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("alice".to_string()),
+            amount: Uint128(9000000000000000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: true,
+                price: Price::from_atomics(4500000000000000000),
+                expected_amount: Uint128(2000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
+
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let unchanged_limit_leg: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(unchanged_limit_leg.status, limit_leg.status);
+        assert_eq!(unchanged_limit_leg.balances, vec![Uint128(2000000), Uint128(0)]);
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), vec![1]);
+        assert_eq!(queued_order_ids(&deps.storage, BID_ORDER_QUEUE), vec![3]);
+
+        // The swap still in flight does not take the place of Alice's bid in the next batch
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps.iter().map(|pending_swap| pending_swap.order.id).collect::<Vec<u64>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_trigger_partial_fill() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // The pair pays 0.12 token2 per token1 but runs dry after 4 token1
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(4000000),
+        });
+
+        // Charlie sells 10 token1 at 0.1
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+
+        // Only the slice that still pays 0.1 per token1 (up to 4.8 token1) is swapped
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        let offer_amount: Uint128 = pending_swaps[0].offer_amount;
+        assert!(offer_amount > Uint128(4700000) && offer_amount <= Uint128(4800000));
+
+        // The pair is told to expect the order's price for that slice only
+        let (recipient, send_amount, swap_msg) = match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("token1address".to_string()));
+                match from_binary::<Snip20SendMsg>(msg).unwrap() {
+                    Snip20SendMsg::Send { recipient, amount, msg } => (recipient, amount, msg.unwrap())
+                }
+            },
+            _ => panic!("Unexpected message")
+        };
+        assert_eq!(recipient, HumanAddr("ammpairaddress".to_string()));
+        assert_eq!(send_amount, (offer_amount - pending_swaps[0].deposit_fee).unwrap());
+        let expected_return = Uint128(1000000000000000000).multiply_ratio(offer_amount, Uint128(10000000));
+        match from_binary::<AmmPairReceiveMsg>(&swap_msg).unwrap() {
+            AmmPairReceiveMsg::Swap { expected_return: sent_expected_return, .. } => assert_eq!(sent_expected_return, Some(expected_return))
+        }
+        assert_eq!(pending_swaps[0].expected_return, expected_return);
+
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(480000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // The remainder stays active and on the book
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(load_limit_order.balances, vec![(Uint128(10000000) - offer_amount).unwrap(), Uint128(0)]);
+        assert_eq!(load_limit_order.withdrew_balance, Some(vec![Uint128(0), Uint128(480000000000000000)]));
+
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), vec![1]);
+    }
+
+    #[test]
+    fn test_trigger_deposit_fee_schedule() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Asks pay 1% of the deposit, at least 0.2 token1
+        let fee_schedule = FeeSchedule {
+            flat_amount: Uint128(0),
+            basis_points: 100,
+            min_fee: Some(Uint128(200000)),
+            max_fee: None,
+            source: FeeSource::Deposit
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), HandleMsg::ChangeFeeSchedule { is_bid: false, fee_schedule: Some(fee_schedule.clone()) });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeFeeSchedule {
+            is_bid: false,
+            fee_schedule: Some(FeeSchedule { basis_points: 10001, ..fee_schedule.clone() })
+        });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeFeeSchedule { is_bid: false, fee_schedule: Some(fee_schedule.clone()) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Charlie sells 10 token1 at 0.1
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Later changes do not apply to the order
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeFeeSchedule { is_bid: false, fee_schedule: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.fee_schedule, fee_schedule);
+
+        let query_result = query(&deps, QueryMsg::TriggerPreview { max_orders: None });
+        let candidates = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TriggerPreview { candidates } => candidates,
+            _ => panic!("Unexpected query answer")
+        };
+        assert_eq!(candidates[0].fee_amount, Uint128(200000));
+        assert_eq!(candidates[0].fee_source, FeeSource::Deposit);
+        assert_eq!(candidates[0].simulated_return, Uint128(1176000000000000000));
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // The fee is kept out of the swap
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps[0].offer_amount, Uint128(10000000));
+        assert_eq!(pending_swaps[0].deposit_fee, Uint128(200000));
+
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1176000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Charlie gets the whole return and the triggerer is owed token1
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.withdrew_balance, Some(vec![Uint128(0), Uint128(1176000000000000000)]));
+
+        let user_address_trigerer = &deps.api.canonical_address(&HumanAddr("trigerer".to_string())).unwrap();
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = load(&keeper_rewards, user_address_trigerer.as_slice()).unwrap();
+        assert_eq!(rewards, vec![Uint128(200000), Uint128(0)]);
+    }
+
+    #[test]
+    fn test_claim_keeper_rewards() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ClaimKeeperRewards {});
+        assert!(handle_result.is_err());
+
+        // Charlie and Alice each sell 10 token1 at 0.1
+        for user in vec!["charlie", "alice"] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user.to_string()),
+                amount: Uint128(10000000),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: false,
+                    price: Price::from_atomics(100000000000000000),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: Some(2) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        for _ in 0..2 {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token2address".to_string()),
+                from: HumanAddr("ammpairaddress".to_string()),
+                amount: Uint128(1200000000000000000),
+                msg: None
+            };
+            let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            // Only the order owner is paid
+            assert_eq!(handle_result.unwrap().messages.len(), 1);
+        }
+
+        // Both fees are claimed in one transfer
+        let user_address_trigerer = &deps.api.canonical_address(&HumanAddr("trigerer".to_string())).unwrap();
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = load(&keeper_rewards, user_address_trigerer.as_slice()).unwrap();
+        assert_eq!(rewards, vec![Uint128(0), Uint128(1000000000000000000)]);
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ClaimKeeperRewards {});
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("token2address".to_string()));
+            },
+            _ => panic!("Unexpected message")
+        }
+
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = load(&keeper_rewards, user_address_trigerer.as_slice()).unwrap();
+        assert_eq!(rewards, vec![Uint128(0), Uint128(0)]);
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ClaimKeeperRewards {});
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_protocol_fee_treasury() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Only the factory sets the protocol fee, within bounds
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), HandleMsg::ChangeProtocolFee { basis_points: 100 });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeProtocolFee { basis_points: 1001 });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeProtocolFee { basis_points: 100 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Charlie sells 10 token1 at 0.1
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // 1% of the 1.2 token2 filled goes to the treasury and 0.5 to the triggerer
+        let query_result = query(&deps, QueryMsg::TreasuryBalances {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TreasuryBalances { protocol_fee_basis_points, balances } => {
+                assert_eq!(protocol_fee_basis_points, 100);
+                assert_eq!(balances, vec![Uint128(0), Uint128(12000000000000000)]);
+            },
+            _ => panic!("Unexpected query answer")
+        }
+        let user_address_trigerer = &deps.api.canonical_address(&HumanAddr("trigerer".to_string())).unwrap();
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = load(&keeper_rewards, user_address_trigerer.as_slice()).unwrap();
+        assert_eq!(rewards, vec![Uint128(0), Uint128(500000000000000000)]);
+
+        // Only the factory withdraws the treasury
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), HandleMsg::WithdrawTreasury { recipient: HumanAddr("bob".to_string()) });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::WithdrawTreasury { recipient: HumanAddr("treasury".to_string()) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(*contract_addr, HumanAddr("token2address".to_string()));
+            },
+            _ => panic!("Unexpected message")
+        }
+
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::WithdrawTreasury { recipient: HumanAddr("treasury".to_string()) });
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_contract_status() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        let create_limit_order_msg = |user: &str| HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr(user.to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), create_limit_order_msg("charlie"));
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Only the factory changes the status
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), HandleMsg::SetContractStatus { status: ContractStatus::StopTriggering });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::StopTriggering });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Orders are still placed but not triggered
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(handle_result.is_err());
+        let query_result = query(&deps, QueryMsg::CheckOrderBookTrigger {});
+        let needs_trigger: bool = from_binary(&query_result.unwrap()).unwrap();
+        assert_eq!(needs_trigger, false);
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), create_limit_order_msg("alice"));
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::Normal });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::WithdrawOnly });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let query_result = query(&deps, QueryMsg::ContractStatus {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status } => assert_eq!(status, ContractStatus::WithdrawOnly),
+            _ => panic!("Unexpected query answer")
+        }
+
+        // No new orders, but the swap already sent settles and Alice can cancel
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), create_limit_order_msg("bob"));
+        assert!(handle_result.is_err());
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_result = handle(&mut deps, mock_env("alice", &[]), HandleMsg::CancelLimitOrder { order_id: 2 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_migrate_order_book() {
+        let token1_info = AssetInfo {
+            decimal_places: 6,
+            base_amount: Uint128(1000000),
+            fee_amount: Uint128(500000),
+            min_amount: Uint128(1000000),
+            token: Some(
+                Token {
+                    contract_addr: HumanAddr("token1address".to_string()),
+                    token_code_hash: "token1hash".to_string()
+                }
+            ),
+            native_token: None
+        };
+        let token2_info = AssetInfo {
+            decimal_places: 18,
+            base_amount: Uint128(1000000000000000000),
+            fee_amount: Uint128(500000000000000000),
+            min_amount: Uint128(1500000000000000),
+            token: Some(
+                Token {
+                    contract_addr: HumanAddr("token2address".to_string()),
+                    token_code_hash: "token2hash".to_string()
+                }
+            ),
+            native_token: None
+        };
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            token1_info.clone(),
+            token2_info.clone(),
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Charlie sells at 3, Bob takes profit at 4.5 or stops out at 0.5 and Alice cancels her order
+        let handle_msgs = vec![
+            ("charlie", Uint128(10000000), HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(3000000000000000000),
+                expected_amount: Uint128(30000000000000000000),
+                order_type: None,
+                expires_at: None
+            }),
+            ("bob", Uint128(2000000), HandleMsg::CreateOcoOrder {
+                is_bid: false,
+                limit_price: Price::from_atomics(4500000000000000000),
+                limit_expected_amount: Uint128(9000000000000000000),
+                stop_price: Price::from_atomics(500000000000000000),
+                stop_expected_amount: Uint128(900000000000000000),
+                expires_at: None
+            }),
+            ("alice", Uint128(5000000), HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(10000000000000000000),
+                order_type: None,
+                expires_at: None
+            })
+        ];
+        for (user, amount, msg) in handle_msgs {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user.to_string()),
+                amount,
+                msg: Some(to_binary(&msg).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+        let handle_result = handle(&mut deps, mock_env("alice", &[]), HandleMsg::CancelLimitOrder { order_id: 4 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Protocol fees collected so far
+        save(&mut deps.storage, TREASURY, &vec![Uint128(100000), Uint128(0)]).unwrap();
+
+        // Settings the factory made on the old order book
+        let fee_schedule = FeeSchedule {
+            flat_amount: Uint128(0),
+            basis_points: 30,
+            min_fee: Some(Uint128(100000)),
+            max_fee: None,
+            source: FeeSource::Deposit
+        };
+        let depth_config = DepthConfig {
+            band_width: Price::from_atomics(100000000000000000),
+            min_orders_per_band: 3
+        };
+        let factory_msgs = vec![
+            HandleMsg::ChangeFee { token_index: 1, min_amount: Uint128(2000000000000000), fee_amount: Uint128(400000000000000000) },
+            HandleMsg::ChangeProtocolFee { basis_points: 25 },
+            HandleMsg::ChangeFeeSchedule { is_bid: false, fee_schedule: Some(fee_schedule.clone()) },
+            HandleMsg::ChangeScanLimit { scan_limit: 40 },
+            HandleMsg::ChangeDepthConfig { depth_config: Some(depth_config.clone()) }
+        ];
+        for factory_msg in factory_msgs {
+            let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), factory_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        // Only the factory starts a migration
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ExportLimitOrders { limit: None });
+        assert!(handle_result.is_err());
+        let start_migration_msg = HandleMsg::StartMigration {
+            contract_address: HumanAddr("newbookaddress".to_string()),
+            contract_hash: "newbookhash".to_string()
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), start_migration_msg.clone());
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), start_migration_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        // The factory can not resume it, the old order book stays migrating
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::Normal });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let query_result = query(&deps, QueryMsg::ContractStatus {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status } => assert_eq!(status, ContractStatus::Migrating),
+            _ => panic!("Unexpected query answer")
+        }
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::Migrating });
+        assert!(handle_result.is_err());
+
+        // Deposits are sent before the orders
+        let import_msg = |messages: &Vec<CosmosMsg>| -> HandleMsg {
+            match messages.last().unwrap() {
+                CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+                    assert_eq!(*contract_addr, HumanAddr("newbookaddress".to_string()));
+                    from_binary(msg).unwrap()
+                },
+                _ => panic!("Unexpected message")
+            }
+        };
+        let mut import_msgs: Vec<HandleMsg> = vec![];
+        for _ in 0..2 {
+            let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ExportLimitOrders { limit: Some(1) });
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            let messages = handle_result.unwrap().messages;
+            assert_eq!(messages.len(), 2);
+            import_msgs.push(import_msg(&messages));
+        }
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ExportLimitOrders { limit: None });
+        assert!(handle_result.is_err());
+
+        // Charlie first with the settings, then both legs of Bob's order with their deposit once and the treasury
+        match &import_msgs[0] {
+            HandleMsg::ImportLimitOrders { treasury, config, finished, .. } => {
+                assert_eq!(*treasury, vec![Uint128(0), Uint128(0)]);
+                assert_eq!(config.as_ref().unwrap().protocol_fee, 25);
+                assert_eq!(*finished, false);
+            },
+            _ => panic!("Unexpected message")
+        }
+        match &import_msgs[1] {
+            HandleMsg::ImportLimitOrders { limit_orders, last_order_id, treasury, config, finished, .. } => {
+                assert_eq!(limit_orders.iter().map(|migrated_limit_order| migrated_limit_order.limit_order.id).collect::<Vec<u64>>(), vec![2, 3]);
+                assert_eq!(*last_order_id, 4);
+                assert_eq!(*treasury, vec![Uint128(100000), Uint128(0)]);
+                assert_eq!(*config, None);
+                assert_eq!(*finished, true);
+            },
+            _ => panic!("Unexpected message")
+        }
+        assert_eq!(may_load::<Vec<Uint128>, _>(&deps.storage, TREASURY).unwrap(), None);
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), Vec::<u64>::new());
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let user_orders = ReadonlyPrefixedStorage::new(USER_ORDERS, &deps.storage);
+        let user_order_map: UserOrderMap = load(&user_orders, user_address_bob.as_slice()).unwrap();
+        assert_eq!(user_order_map.active_orders, Vec::<u64>::new());
+
+        // The new order book only takes imports from the old one
+        let mut new_deps = mock_dependencies(20, &[]);
+        let init_result = init(&mut new_deps, mock_env("factoryaddress", &[]), InitMsg {
+            factory_address: HumanAddr("factoryaddress".to_string()),
+            factory_hash: "factoryhash".to_string(),
+            factory_key: "factorykey".to_string(),
+            token1_info,
+            token2_info,
+            amm_pair_contract_address: HumanAddr("ammpairaddress".to_string()),
+            amm_pair_contract_hash: "ammpairhash".to_string(),
+            migrate_from: Some(MigrationContract {
+                address: HumanAddr(MOCK_CONTRACT_ADDR.to_string()),
+                code_hash: "oldbookhash".to_string()
+            })
+        });
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        let handle_result = handle(&mut new_deps, mock_env("bob", &[]), import_msgs[0].clone());
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut new_deps, mock_env(MOCK_CONTRACT_ADDR, &[]), import_msgs[0].clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Nothing is placed until the last orders arrive
+        let new_limit_order_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("alice".to_string()),
+            amount: Uint128(5000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(2000000000000000000),
+                expected_amount: Uint128(10000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut new_deps, mock_env("token1address", &[]), new_limit_order_msg.clone());
+        assert!(handle_result.is_err());
+
+        // A status set by the factory meanwhile applies once the import is done
+        let handle_result = handle(&mut new_deps, mock_env("factoryaddress", &[]), HandleMsg::SetContractStatus { status: ContractStatus::StopTriggering });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        let handle_result = handle(&mut new_deps, mock_env(MOCK_CONTRACT_ADDR, &[]), import_msgs[1].clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(queued_order_ids(&new_deps.storage, ASK_ORDER_QUEUE), vec![1, 2]);
+        assert_eq!(queued_order_ids(&new_deps.storage, STOP_ASK_ORDER_QUEUE), vec![3]);
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&new_deps.storage);
+        let limit_leg: LimitOrderState = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(limit_leg.linked_order_id, Some(3));
+        assert_eq!(limit_leg.balances, vec![Uint128(2000000), Uint128(0)]);
+        assert_eq!(load::<Vec<Uint128>, _>(&new_deps.storage, TREASURY).unwrap(), vec![Uint128(100000), Uint128(0)]);
+        let query_result = query(&new_deps, QueryMsg::ContractStatus {});
+        match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status } => assert_eq!(status, ContractStatus::StopTriggering),
+            _ => panic!("Unexpected query answer")
+        }
+
+        // The settings of the old order book survive the migration
+        let new_token2_info: AssetInfo = load(&new_deps.storage, TOKEN2_DATA).unwrap();
+        assert_eq!((new_token2_info.fee_amount, new_token2_info.min_amount), (Uint128(400000000000000000), Uint128(2000000000000000)));
+        assert_eq!(load::<u16, _>(&new_deps.storage, PROTOCOL_FEE).unwrap(), 25);
+        assert_eq!(load::<FeeSchedule, _>(&new_deps.storage, ASK_FEE_SCHEDULE).unwrap(), fee_schedule);
+        assert_eq!(may_load::<FeeSchedule, _>(&new_deps.storage, BID_FEE_SCHEDULE).unwrap(), None);
+        assert_eq!(load::<u32, _>(&new_deps.storage, SCAN_LIMIT).unwrap(), 40);
+        assert_eq!(load::<DepthConfig, _>(&new_deps.storage, DEPTH_CONFIG).unwrap(), depth_config);
+
+        // New orders are numbered after the old ones
+        let handle_result = handle(&mut new_deps, mock_env("token1address", &[]), new_limit_order_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(queued_order_ids(&new_deps.storage, ASK_ORDER_QUEUE), vec![5, 1, 2]);
+
+        // Bob and Dave, who never placed an order, triggered orders and have not claimed their fees yet
+        let user_address_dave = &deps.api.canonical_address(&HumanAddr("dave".to_string())).unwrap();
+        credit_keeper_reward(&mut deps.storage, user_address_bob, &vec![Uint128(0), Uint128(500000000000000000)]).unwrap();
+        credit_keeper_reward(&mut deps.storage, user_address_dave, &vec![Uint128(300000), Uint128(0)]).unwrap();
+
+        // History and order events are copied once
+        let export_history_msg = HandleMsg::ExportHistoryLimitOrders {
+            owners: vec![HumanAddr("alice".to_string()), HumanAddr("bob".to_string())],
+            limit: None
+        };
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), export_history_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let messages = handle_result.unwrap().messages;
+        assert_eq!(messages.len(), 1);
+        let import_history_msg = import_msg(&messages);
+        match &import_history_msg {
+            HandleMsg::ImportHistoryLimitOrders { histories } => {
+                assert_eq!(histories[0].owner, HumanAddr("alice".to_string()));
+                assert_eq!(histories[0].order_events.iter().map(|order_event| order_event.action.clone()).collect::<Vec<OrderEventAction>>(), vec![OrderEventAction::Create, OrderEventAction::Cancel]);
+                assert_eq!(histories[1].owner, HumanAddr("bob".to_string()));
+            },
+            _ => panic!("Unexpected message")
+        }
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), export_history_msg.clone());
+        assert!(handle_result.is_err());
+
+        let handle_result = handle(&mut new_deps, mock_env(MOCK_CONTRACT_ADDR, &[]), import_history_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let user_address_alice = &deps.api.canonical_address(&HumanAddr("alice".to_string())).unwrap();
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_alice.as_slice()], &new_deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!((txs[0].id, txs[0].status.clone()), (4, OrderStatus::Canceled));
+        let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, user_address_alice.as_slice()], &new_deps.storage);
+        let events: Vec<OrderEvent> = AppendStore::<OrderEvent, _>::attach(&order_events).unwrap().unwrap().iter().collect::<StdResult<Vec<OrderEvent>>>().unwrap();
+        assert_eq!(events.len(), 2);
+
+        // Keeper rewards are moved on their own, one keeper at a time here, after the tokens that pay them
+        let mut import_keeper_rewards_msgs: Vec<HandleMsg> = vec![];
+        for _ in 0..2 {
+            let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ExportKeeperRewards { limit: Some(1) });
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            let messages = handle_result.unwrap().messages;
+            assert_eq!(messages.len(), 2);
+            import_keeper_rewards_msgs.push(import_msg(&messages));
+        }
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::ExportKeeperRewards { limit: None });
+        assert!(handle_result.is_err());
+        match &import_keeper_rewards_msgs[1] {
+            HandleMsg::ImportKeeperRewards { keeper_rewards } => {
+                assert_eq!(keeper_rewards.len(), 1);
+                assert_eq!(keeper_rewards[0].keeper, HumanAddr("dave".to_string()));
+                assert_eq!(keeper_rewards[0].rewards, vec![Uint128(300000), Uint128(0)]);
+            },
+            _ => panic!("Unexpected message")
+        }
+        // Nothing is left to claim on the old order book
+        let handle_result = handle(&mut deps, mock_env("dave", &[]), HandleMsg::ClaimKeeperRewards {});
+        assert!(handle_result.is_err());
+
+        for import_keeper_rewards_msg in import_keeper_rewards_msgs {
+            let handle_result = handle(&mut new_deps, mock_env("bob", &[]), import_keeper_rewards_msg.clone());
+            assert!(handle_result.is_err());
+            let handle_result = handle(&mut new_deps, mock_env(MOCK_CONTRACT_ADDR, &[]), import_keeper_rewards_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &new_deps.storage);
+        assert_eq!(load::<Vec<Uint128>, _>(&keeper_rewards, user_address_bob.as_slice()).unwrap(), vec![Uint128(0), Uint128(500000000000000000)]);
+        assert_eq!(load::<Vec<Uint128>, _>(&keeper_rewards, user_address_dave.as_slice()).unwrap(), vec![Uint128(300000), Uint128(0)]);
+    }
+
+    #[test]
+    fn test_message_padding() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+        for message in init_result.unwrap().messages {
+            match message {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => assert_eq!(msg.as_slice().len() % BLOCK_SIZE, 0),
+                _ => panic!("Unexpected message")
+            }
+        }
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Charlie and Alice each sell 10 token1 at 0.1
+        let mut answer_sizes: Vec<usize> = vec![];
+        for user in vec!["charlie", "alice"] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user.to_string()),
+                amount: Uint128(10000000),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: false,
+                    price: Price::from_atomics(100000000000000000),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            answer_sizes.push(handle_result.unwrap().data.unwrap().as_slice().len());
+        }
+
+        // Charlie cancels
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), HandleMsg::CancelLimitOrder { order_id: 1 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_response = handle_result.unwrap();
+        answer_sizes.push(handle_response.data.unwrap().as_slice().len());
+        let mut message_sizes: Vec<usize> = vec![];
+        for message in handle_response.messages {
+            match message {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => message_sizes.push(msg.as_slice().len()),
+                _ => panic!("Unexpected message")
+            }
+        }
+
+        // Alice's order fills
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: Some(1) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_response = handle_result.unwrap();
+        answer_sizes.push(handle_response.data.unwrap().as_slice().len());
+        for message in handle_response.messages {
+            match message {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => message_sizes.push(msg.as_slice().len()),
+                _ => panic!("Unexpected message")
+            }
+        }
+
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_response = handle_result.unwrap();
+        answer_sizes.push(handle_response.data.unwrap().as_slice().len());
+        for message in handle_response.messages {
+            match message {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => message_sizes.push(msg.as_slice().len()),
+                _ => panic!("Unexpected message")
+            }
+        }
+
+        // A trigger that swaps nothing answers the same
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: Some(1) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_response = handle_result.unwrap();
+        assert_eq!(handle_response.messages.len(), 0);
+        answer_sizes.push(handle_response.data.unwrap().as_slice().len());
+
+        // The factory settings answer with a status as well
+        let factory_msgs = vec![
+            HandleMsg::ChangeFee { token_index: 0, min_amount: Uint128(1500000000000000), fee_amount: Uint128(600000000000000000) },
+            HandleMsg::ChangeScanLimit { scan_limit: 10 },
+            HandleMsg::ChangeProtocolFee { basis_points: 10 },
+            HandleMsg::ChangeFeeSchedule { is_bid: false, fee_schedule: None },
+            HandleMsg::ChangeDepthConfig { depth_config: None },
+            HandleMsg::SetContractStatus { status: ContractStatus::Normal }
+        ];
+        for factory_msg in factory_msgs {
+            let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), factory_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            answer_sizes.push(handle_result.unwrap().data.unwrap().as_slice().len());
+        }
+
+        // Creating, canceling, triggering, filling and the factory settings can not be told apart by the size of their answers
+        assert!(answer_sizes.iter().all(|size| *size == answer_sizes[0]));
+        assert_eq!(answer_sizes[0] % BLOCK_SIZE, 0);
+        assert_eq!(message_sizes.len(), 3);
+        assert!(message_sizes.iter().all(|size| *size % BLOCK_SIZE == 0));
+
+        for query_msg in vec![QueryMsg::OrderBookPairInfo {}, QueryMsg::ContractStatus {}, QueryMsg::TreasuryBalances {}] {
+            let query_result = query(&deps, query_msg);
+            assert!(
+                query_result.is_ok(),
+                "query() failed: {}",
+                query_result.err().unwrap()
+            );
+            assert_eq!(query_result.unwrap().as_slice().len() % BLOCK_SIZE, 0);
+        }
+    }
+
+    #[test]
+    fn test_order_event_logs() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Charlie and Alice each sell 10 token1 at 0.1
+        for user in vec!["charlie", "alice"] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user.to_string()),
+                amount: Uint128(10000000),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: false,
+                    price: Price::from_atomics(100000000000000000),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+            // Owners, amounts and even the side stay out of the public logs
+            assert!(handle_result.unwrap().log.is_empty());
+        }
+
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), HandleMsg::CancelLimitOrder { order_id: 1 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        // Cancels and fills would tell apart the answers the padding hides
+        assert!(handle_result.unwrap().log.is_empty());
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: Some(1) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(handle_result.unwrap().log.is_empty());
+
+        // The details are in the private event log of each owner
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, user_address_charlie.as_slice()], &deps.storage);
+        let store = AppendStore::<OrderEvent, _>::attach(&order_events).unwrap().unwrap();
+        let charlie_events: Vec<OrderEvent> = store.iter().collect::<StdResult<Vec<OrderEvent>>>().unwrap();
+        assert_eq!(charlie_events.len(), 2);
+        assert_eq!(charlie_events[0].action, OrderEventAction::Create);
+        assert_eq!(charlie_events[0].amounts, vec![Uint128(10000000), Uint128(0)]);
+        assert_eq!(charlie_events[1].action, OrderEventAction::Cancel);
+        assert_eq!(charlie_events[1].order_id, 1);
+        assert_eq!(charlie_events[1].amounts, vec![Uint128(10000000), Uint128(0)]);
+
+        let user_address_alice = &deps.api.canonical_address(&HumanAddr("alice".to_string())).unwrap();
+        let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, user_address_alice.as_slice()], &deps.storage);
+        let store = AppendStore::<OrderEvent, _>::attach(&order_events).unwrap().unwrap();
+        let alice_events: Vec<OrderEvent> = store.iter().collect::<StdResult<Vec<OrderEvent>>>().unwrap();
+        assert_eq!(alice_events.len(), 2);
+        assert_eq!(alice_events[1].action, OrderEventAction::Fill);
+        assert_eq!(alice_events[1].order_id, 2);
+        assert_eq!(alice_events[1].is_bid, false);
+        assert_eq!(alice_events[1].amounts, vec![Uint128(10000000), Uint128(700000000000000000)]);
+        assert_eq!(alice_events[1].fees, vec![Uint128(0), Uint128(500000000000000000)]);
+
+        // Fee changes are public settings
+        let handle_msg = HandleMsg::ChangeFee {
+            token_index: 1,
+            min_amount: Uint128(2000000000000000000),
+            fee_amount: Uint128(1000000000000000000)
+        };
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().log, vec![
+            log("action", "change_fee"),
+            log("pair", "ammpairaddress"),
+            log("token", "token2address"),
+            log("fee_amount", "1000000000000000000"),
+            log("min_amount", "2000000000000000000"),
+        ]);
+    }
+
+    #[test]
+    fn test_order_event_logs_of_order_changes() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        let env = mock_env("token1address", &[]);
+
+        // Bob takes profit at 4.5 or stops out at 0.5 for the next 10 blocks, both legs are created
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(2000000),
+            msg: Some(to_binary(&HandleMsg::CreateOcoOrder {
+                is_bid: false,
+                limit_price: Price::from_atomics(4500000000000000000),
+                limit_expected_amount: Uint128(9000000000000000000),
+                stop_price: Price::from_atomics(500000000000000000),
+                stop_expected_amount: Uint128(900000000000000000),
+                expires_at: Some(Expiration::AtHeight(env.block.height + 10))
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, env.clone(), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(handle_result.unwrap().log.is_empty());
+
+        // He raises the take profit to 5
+        let handle_msg = HandleMsg::UpdateLimitOrder { order_id: 1, price: Price::from_atomics(5000000000000000000), expected_amount: Uint128(10000000000000000000) };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(handle_result.unwrap().log.is_empty());
+
+        // And tops it up with 1 token1
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(1000000),
+            msg: Some(to_binary(&HandleMsg::IncreaseLimitOrder { order_id: 1 }).unwrap())
+        };
+        let handle_result = handle(&mut deps, env.clone(), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert!(handle_result.unwrap().log.is_empty());
+
+        // The order expires and is refunded once
+        let mut env = mock_env("pruner", &[]);
+        env.block.height += 10;
+        let handle_result = handle(&mut deps, env, HandleMsg::PruneExpiredOrders { limit: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let handle_response = handle_result.unwrap();
+        assert_eq!(handle_response.messages.len(), 1);
+        assert!(handle_response.log.is_empty());
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let order_events = ReadonlyPrefixedStorage::multilevel(&[ORDER_EVENTS, user_address_bob.as_slice()], &deps.storage);
+        let store = AppendStore::<OrderEvent, _>::attach(&order_events).unwrap().unwrap();
+        let bob_events: Vec<OrderEvent> = store.iter().collect::<StdResult<Vec<OrderEvent>>>().unwrap();
+        assert_eq!(
+            bob_events.iter().map(|order_event| (order_event.action.clone(), order_event.order_id, order_event.amounts.clone())).collect::<Vec<(OrderEventAction, u64, Vec<Uint128>)>>(),
+            vec![
+                (OrderEventAction::Create, 1, vec![Uint128(2000000), Uint128(0)]),
+                (OrderEventAction::Create, 2, vec![Uint128(2000000), Uint128(0)]),
+                (OrderEventAction::Update, 1, vec![Uint128(2000000), Uint128(0)]),
+                (OrderEventAction::Increase, 1, vec![Uint128(1000000), Uint128(0)]),
+                (OrderEventAction::Expire, 1, vec![Uint128(3000000), Uint128(0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trigger_batch_of_orders() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // The pair pays 0.12 token2 per token1 but runs dry after 12 token1
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(12000000),
+        });
+
+        // Charlie sells 10 token1 at 0.1, Bob 5 token1 at 0.11 and Alice 2 token1 at 0.115
+        for (user, amount, price, expected_amount) in vec![
+            ("charlie", 10000000u128, 100000000000000000u128, 1000000000000000000u128),
+            ("bob", 5000000, 110000000000000000, 550000000000000000),
+            ("alice", 2000000, 115000000000000000, 230000000000000000)
+        ] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user.to_string()),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: false,
+                    price: Price::from_atomics(price),
+                    expected_amount: Uint128(expected_amount),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        // Keepers can see what the batch would swap and earn before sending it
+        let query_result = query(&deps, QueryMsg::TriggerPreview { max_orders: Some(3) });
+        let candidates = match from_binary(&query_result.unwrap()).unwrap() {
+            QueryAnswer::TriggerPreview { candidates } => candidates,
+            _ => panic!("Unexpected query answer")
+        };
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], TriggerCandidate {
+            is_bid: false,
+            order_type: OrderType::Limit,
+            deposit_amount: Uint128(10000000),
+            offer_amount: Uint128(10000000),
+            simulated_return: Uint128(1200000000000000000),
+            expected_return: Uint128(1000000000000000000),
+            fee_amount: Uint128(500000000000000000),
+            fee_source: FeeSource::Proceeds
+        });
+        assert_eq!(candidates[1].deposit_amount, Uint128(5000000));
+        assert!(candidates[1].simulated_return >= candidates[1].expected_return);
+
+        // Charlie fills, Bob gets part of the 2 token1 of liquidity left after him and nothing is left for Alice
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: Some(3) });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 2);
+
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps.len(), 2);
+        assert_eq!((pending_swaps[0].order.id, pending_swaps[0].offer_amount), (1, Uint128(10000000)));
+        assert_eq!(pending_swaps[1].order.id, 2);
+        assert!(pending_swaps[1].offer_amount >= Uint128(2000000) && pending_swaps[1].offer_amount <= Uint128(2181818));
+        let bob_offer_amount = pending_swaps[1].offer_amount;
+        assert_eq!(bob_offer_amount, candidates[1].offer_amount);
+
+        // The pair pays the swaps back in order
+        for amount in vec![Uint128(1200000000000000000), Uint128(240000000000000000)] {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token2address".to_string()),
+                from: HumanAddr("ammpairaddress".to_string()),
+                amount,
+                msg: None
+            };
+            let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+        let pending_swaps: Option<Vec<PendingSwap>> = may_load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert!(pending_swaps.is_none());
+
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_charlie.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Filled);
+
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &2u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(load_limit_order.balances, vec![(Uint128(5000000) - bob_offer_amount).unwrap(), Uint128(0)]);
+
+        // A payout nobody is waiting for is rejected
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+    }
+
+    #[test]
+    fn test_trigger_search_skips_oversized_orders() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(5000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(5000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // The pair pays 0.12 token2 per token1 but runs dry after 1 token1
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(1000000),
         });
 
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        // Only the factory sets the scan limit, within bounds
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), HandleMsg::ChangeScanLimit { scan_limit: 1 });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeScanLimit { scan_limit: 0 });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeScanLimit { scan_limit: 2 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        // Twelve asks of 1.5 token1 at 0.1 are too big for the pair and can not be split,
+        // the 1 token1 ask at 0.11 behind them still fills
+        let mut asks = vec![];
+        for user in 0..12 {
+            asks.push((format!("user{}", user), 1500000u128, 100000000000000000u128, 150000000000000000u128));
+        }
+        asks.push(("alice".to_string(), 1000000, 110000000000000000, 110000000000000000));
+        for (user, amount, price, expected_amount) in asks {
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr("token1address".to_string()),
+                from: HumanAddr(user),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid: false,
+                    price: Price::from_atomics(price),
+                    expected_amount: Uint128(expected_amount),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+            let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
 
-        assert_eq!(needs_trigger, true);
-  
-        // Test 4 => AMM Base Price = 7 && AMM Amount = 11 && AMM Amount2 = 8 && Expected True
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(7000000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
+        let query_result = query(&deps, QueryMsg::CheckOrderBookTrigger {});
+        let would_trigger: bool = from_binary(&query_result.unwrap()).unwrap();
+        assert!(would_trigger);
+
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps.len(), 1);
+        assert_eq!(pending_swaps[0].order.owner, HumanAddr("alice".to_string()));
+        assert_eq!(pending_swaps[0].offer_amount, Uint128(1000000));
+    }
+
+    #[test]
+    fn test_unlock_timed_out_swaps() {
+        let (init_result, deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 6,
+                base_amount: Uint128(1000000),
+                fee_amount: Uint128(500000),
+                min_amount: Uint128(1000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
             },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(13000000000000000000), // 1.1111*11 (rounded)  
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"250000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(2000000000000000000), //0.25*(simulated_price) => 0.25*8
-                spread_amount: Uint128(0),  
-                commission_amount: Uint128(0)
-            }), // 7*2 + 2 of spread
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // The pair pays 0.12 token2 per token1 but runs dry after 4 token1
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(4000000),
         });
 
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        // Charlie sells 10 token1 at 0.1
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(1000000000000000000),
+                order_type: None,
+                expires_at: None
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
 
-        assert_eq!(needs_trigger, true);
+        let user_address_charlie = &deps.api.canonical_address(&HumanAddr("charlie".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::Executing);
 
-       // Test 5 => AMM Base Price = 8 && AMM Amount = 10 && AMM Amount2 = 9 && Expected False
-        let mut mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(8000000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1111111"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(12000000000000000000), // 1.1111*10 (rounded) => 12
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"250000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(2250000000000000000), //0.25*(simulated_price) => 0.25*9 = 2.25
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-        });
+        // The order is locked while its swap is in flight
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), HandleMsg::CancelLimitOrder { order_id: 1 });
+        assert!(handle_result.is_err());
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
 
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        // Nothing to unlock before the timeout
+        let handle_result = handle(&mut deps, mock_env("anyone", &[]), HandleMsg::UnlockTimedOutSwaps {});
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps.len(), 1);
+        let offer_amount: Uint128 = pending_swaps[0].offer_amount;
+        let deposit_fee: Uint128 = pending_swaps[0].deposit_fee;
+        assert!(offer_amount < Uint128(10000000));
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        // A payout after the timeout does not match the swap anymore
+        let mut env = mock_env("token2address", &[]);
+        env.block.height += SWAP_TIMEOUT_BLOCKS + 1;
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token2address".to_string()),
+            from: HumanAddr("ammpairaddress".to_string()),
+            amount: Uint128(1200000000000000000),
+            msg: None
+        };
+        let handle_result = handle(&mut deps, env, handle_msg);
+        assert!(handle_result.is_err());
 
-        assert_eq!(needs_trigger, false);
+        let mut env = mock_env("anyone", &[]);
+        env.block.height += SWAP_TIMEOUT_BLOCKS + 1;
+        let handle_result = handle(&mut deps, env, HandleMsg::UnlockTimedOutSwaps {});
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        let pending_swaps: Option<Vec<PendingSwap>> = may_load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert!(pending_swaps.is_none());
+
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_charlie.as_slice()],&deps.storage);
+        let load_limit_order: LimitOrderState = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap().unwrap();
+        assert_eq!(load_limit_order.status, OrderStatus::Active);
+        // The part sent to the pair is not unlocked again, its deposit fee goes to the keeper
+        let remaining_amount: Uint128 = (Uint128(10000000) - offer_amount).unwrap();
+        assert_eq!(load_limit_order.balances, vec![remaining_amount, Uint128(0)]);
+        let user_address_trigerer = &deps.api.canonical_address(&HumanAddr("trigerer".to_string())).unwrap();
+        let keeper_rewards = ReadonlyPrefixedStorage::new(KEEPER_REWARDS, &deps.storage);
+        let rewards: Vec<Uint128> = may_load(&keeper_rewards, user_address_trigerer.as_slice()).unwrap().unwrap_or(vec![Uint128(0), Uint128(0)]);
+        assert_eq!(rewards[0], deposit_fee);
+
+        // Back on the book, Charlie can cancel it again and gets only the remaining balance back
+        let handle_result = handle(&mut deps, mock_env("charlie", &[]), HandleMsg::CancelLimitOrder { order_id: 1 });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_charlie.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Canceled);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![remaining_amount, Uint128(0)]));
     }
 
     #[test]
-    fn test_get_limit_order_to_trigger_ask() {
+    fn test_prune_expired_orders() {
         let (init_result, mut deps) = init_helper(
             HumanAddr("factoryaddress".to_string()),
             "factoryhash".to_string(),
@@ -724,6 +4328,7 @@ mod tests {
                         token_code_hash: "token1hash".to_string()
                     }
                 ),
+                native_token: None
             },
             AssetInfo {
                 decimal_places: 18,
@@ -736,6 +4341,7 @@ mod tests {
                         token_code_hash: "token2hash".to_string()
                     }
                 ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -746,260 +4352,101 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        use serde::{Deserialize, Serialize};
-        #[derive(Debug)]
-        struct MyMockQuerier {
-            expected_bid_base_request: Vec<u8>,
-            expected_bid_base_response: AmmPairSimulationResponse,
-            expected_bid_amount_request: Option<Vec<u8>>,
-            expected_bid_amount_response: Option<AmmPairSimulationResponse>,
-            expected_bid_amount2_request: Option<Vec<u8>>,
-            expected_bid_amount2_response: Option<AmmPairSimulationResponse>,
-        }
-
-        #[derive(Serialize, Deserialize, Debug)]
-        pub struct MockedSimulation {
-            pub return_amount: Uint128,
-            pub spread_amount: Uint128,
-            pub commission_amount: Uint128
-        }
-        
-        pub fn space_pad(message: &mut Vec<u8>, block_size: usize) -> &mut Vec<u8> {
-            let len = message.len();
-            let surplus = len % block_size;
-            if surplus == 0 {
-                return message;
-            }
-        
-            let missing = block_size - surplus;
-            message.reserve(missing);
-            message.extend(std::iter::repeat(b' ').take(missing));
-            message
-        }
-
-        pub fn check_mock_request(msg: Vec<u8>) -> Vec<u8>{
-            let mut expected_msg = msg.clone();
-            space_pad(&mut expected_msg, 256);
-            let expected_request: QueryRequest<AmmSimulationQuery> =
-            QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: HumanAddr("ammpairaddress".to_string()),
-                callback_code_hash: "ammpairhash".to_string(),
-                msg: Binary(expected_msg),
-            });
-            to_vec(&expected_request).unwrap()       
-        }
-
-        impl Querier for MyMockQuerier {
-            fn raw_query(&self, request: &[u8] ) -> QuerierResult {
-                let bid_base_req = check_mock_request(self.expected_bid_base_request.clone());
-                if request == bid_base_req {
-                    return Ok(to_binary(&MockedSimulation {
-                        return_amount: self.expected_bid_base_response.return_amount,
-                        spread_amount: self.expected_bid_base_response.spread_amount,
-                        commission_amount: self.expected_bid_base_response.commission_amount
-                    }));
-                }
-                if self.expected_bid_amount_request.clone() != None {
-                    let bid_amount_req = check_mock_request(self.expected_bid_amount_request.clone().unwrap());
-                    if request == bid_amount_req {
-                        return Ok(to_binary(&MockedSimulation {
-                            return_amount: self.expected_bid_amount_response.as_ref().unwrap().return_amount,
-                            spread_amount: self.expected_bid_amount_response.as_ref().unwrap().spread_amount,
-                            commission_amount: self.expected_bid_amount_response.as_ref().unwrap().commission_amount
-                        }));
-                    }  
-                }
-                
-                if self.expected_bid_amount2_request.clone() != None { 
-                    let bid_amount2_req = check_mock_request(self.expected_bid_amount2_request.clone().unwrap());
-                    if request == bid_amount2_req {
-                        return Ok(to_binary(&MockedSimulation {
-                            return_amount: self.expected_bid_amount2_response.as_ref().unwrap().return_amount,
-                            spread_amount: self.expected_bid_amount2_response.as_ref().unwrap().spread_amount,
-                            commission_amount: self.expected_bid_amount2_response.as_ref().unwrap().commission_amount
-                        }));
-                    } 
-                }
-                
-                return Ok(to_binary(&request))
-            }
-        }
+        let env = mock_env("token1address", &[]);
 
-        // SEND ASKS
-        // sell token 1 for token 2
-        // Charlie send
+        // Orders can not be created already expired
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token1address".to_string()), 
-            from: HumanAddr("charlie".to_string()), 
-            amount: Uint128(120000), //0.12 
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(2000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(4500000000000000000),
+                expected_amount: Uint128(9000000000000000000),
+                order_type: None,
+                expires_at: Some(Expiration::AtHeight(env.block.height))
+            }).unwrap())
+        };
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        // Bob's order expires in 10 blocks, Charlie's never
+        let handle_msg = HandleMsg::Receive {
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(2000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: false,
-                price: Uint128(110000000000000000), //0.11
-                expected_amount: Uint128(13200000000000000)  //0.0132 (0.12*0.11)
+                price: Price::from_atomics(4500000000000000000),
+                expected_amount: Uint128(9000000000000000000),
+                order_type: None,
+                expires_at: Some(Expiration::AtHeight(env.block.height + 10))
             }).unwrap())
         };
-
-        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
-
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        );        
+        );
 
-        // Rob send
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token1address".to_string()), 
-            from: HumanAddr("rob".to_string()), 
-            amount: Uint128(20000), //0.02
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(2000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: false,
-                price: Uint128(120000000000000000), //0.12
-                expected_amount: Uint128(2400000000000000)  //0.0024 (0.12*0.02)
+                price: Price::from_atomics(5000000000000000000),
+                expected_amount: Uint128(10000000000000000000),
+                order_type: None,
+                expires_at: None
             }).unwrap())
         };
-
-        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
-
+        let handle_result = handle(&mut deps, env.clone(), handle_msg.clone());
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        );   
-   
-        // ASK Test 1 => AMM Base Price = 0.1 && Expected False
-        let mocked_deps = deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(100000000000000000), //0.1
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: None,
-            expected_bid_amount_response: None,
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
-        });
-
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
-
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
-
-        assert_eq!(needs_trigger, false);
-
-        // Test 2 => AMM Base Price = 0.11 && AMM Amount = 0.1 && Expected False
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request:  r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(110000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
-        });
-
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
-
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
-
-        assert_eq!(needs_trigger, false);
- 
-        // Test 3 => AMM Base Price = 0.12 && AMM Amount = 0.11 && Expected True     
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(120000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(13200000000000000), // 0.12 * 0.11
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: None,
-            expected_bid_amount2_response: None,
-        });
-
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
-
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
-
-        assert_eq!(needs_trigger, true);
-
-        // Test 4 => AMM Base Price = 0.13 && AMM Amount = 0.1 && AMM Amount2 = 0.12 && Expected True
-        let mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(130000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"20000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(2400000000000000), // 0.02 * 0.12
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-        });
-
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        );
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        // Nothing expired yet
+        let handle_result = handle(&mut deps, mock_env("pruner", &[]), HandleMsg::PruneExpiredOrders { limit: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
 
-        assert_eq!(needs_trigger, true);
- 
-        // Test 5 => AMM Base Price = 0.13 && AMM Amount = 0.1 && AMM Amount2 = 0.11 && Expected False
-        let mut mocked_deps = mocked_deps.change_querier(|_| MyMockQuerier {
-            expected_bid_base_request: r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"1000000"}}}"#.as_bytes().to_vec(),
-            expected_bid_base_response: AmmPairSimulationResponse {
-                return_amount: Uint128(130000000000000000),
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            },
-            expected_bid_amount_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"120000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(12000000000000000), // 0.12 * 0.1
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-            expected_bid_amount2_request: Some(r#"{"simulation":{"offer_asset":{"info":{"token":{"contract_addr":"token1address","token_code_hash":"token1hash","viewing_key":""}},"amount":"20000"}}}"#.as_bytes().to_vec()),
-            expected_bid_amount2_response: Some(AmmPairSimulationResponse {
-                return_amount: Uint128(2200000000000000), // 0.02 * 0.11
-                spread_amount: Uint128(0),
-                commission_amount: Uint128(0)
-            }),
-        });
+        // Anyone can refund Bob once his order expired
+        let mut env = mock_env("pruner", &[]);
+        env.block.height += 10;
+        let handle_result = handle(&mut deps, env, HandleMsg::PruneExpiredOrders { limit: None });
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
 
-        let query_msg = QueryMsg::CheckOrderBookTrigger {};
-        let query_result = query(&mocked_deps, query_msg);
+        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
+        let limit_orders = ReadonlyPrefixedStorage::multilevel(&[ACTIVE_LIMIT_ORDERS, user_address_bob.as_slice()],&deps.storage);
+        let load_limit_order: Option<LimitOrderState> = may_load(&limit_orders, &1u64.to_be_bytes()).unwrap();
+        assert_eq!(load_limit_order, None);
 
-        let needs_trigger:bool = from_binary(&query_result.unwrap()).unwrap();
+        let history_limit_orders = ReadonlyPrefixedStorage::multilevel(&[HISTORY_LIMIT_ORDERS, user_address_bob.as_slice()], &deps.storage);
+        let store = AppendStore::<LimitOrderState, _>::attach(&history_limit_orders);
+        let txs: Vec<LimitOrderState> = store.unwrap().unwrap().iter().collect::<StdResult<Vec<LimitOrderState>>>().unwrap();
+        assert_eq!(txs[0].status, OrderStatus::Expired);
+        assert_eq!(txs[0].withdrew_balance, Some(vec![Uint128(2000000), Uint128(0)]));
 
-        assert_eq!(needs_trigger, false);   
+        // Only Charlie's order is left on the book
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), vec![2]);
     }
 
     #[test]
-    fn test_handle_receive_from_swap() {
-        let (init_result, mut deps) = init_helper(
+    fn test_trigger_stop_order() {
+        let (init_result, deps) = init_helper(
             HumanAddr("factoryaddress".to_string()),
             "factoryhash".to_string(),
             "factorykey".to_string(),
@@ -1014,6 +4461,7 @@ mod tests {
                         token_code_hash: "token1hash".to_string()
                     }
                 ),
+                native_token: None
             },
             AssetInfo {
                 decimal_places: 18,
@@ -1026,6 +4474,7 @@ mod tests {
                         token_code_hash: "token2hash".to_string()
                     }
                 ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -1036,44 +4485,82 @@ mod tests {
             init_result.err().unwrap()
         );
 
-        // Bob send
+        // The pair pays 0.12 token2 per token1
+        let mut deps = deps.change_querier(|_| AmmPairMockQuerier {
+            token1_price: Uint128(120000000000000000),
+            token1_base_amount: Uint128(1000000),
+            max_offer_token1: Uint128(100000000),
+        });
+
+        // Charlie stops out 10 token1 below 0.1, accepting at least 0.9 token2
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token1address".to_string()), 
-            from: HumanAddr("bob".to_string()), 
-            amount: Uint128(2000000),
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("charlie".to_string()),
+            amount: Uint128(10000000),
             msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
                 is_bid: false,
-                price: Uint128(9000000000000000000),
-                expected_amount: Uint128(9000000000000000000),
+                price: Price::from_atomics(100000000000000000),
+                expected_amount: Uint128(900000000000000000),
+                order_type: Some(OrderType::Stop),
+                expires_at: None
             }).unwrap())
         };
-
         let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Stop orders are kept out of the limit order book
+        assert_eq!(queued_order_ids(&deps.storage, ASK_ORDER_QUEUE), Vec::<u64>::new());
+        assert_eq!(queued_order_ids(&deps.storage, STOP_ASK_ORDER_QUEUE)[0], 1);
 
+        // The price is still above the stop so nothing triggers
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
         );
+        assert_eq!(handle_result.unwrap().messages.len(), 0);
 
+        // Bob stops out 10 token1 below 0.15, the price is already through it
         let handle_msg = HandleMsg::Receive {
-            sender: HumanAddr("token2address".to_string()), 
-            from: HumanAddr("ammpairaddress".to_string()), 
-            amount: Uint128(400000000000000),
-            msg: None
+            sender: HumanAddr("token1address".to_string()),
+            from: HumanAddr("bob".to_string()),
+            amount: Uint128(10000000),
+            msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                is_bid: false,
+                price: Price::from_atomics(150000000000000000),
+                expected_amount: Uint128(900000000000000000),
+                order_type: Some(OrderType::Stop),
+                expires_at: None
+            }).unwrap())
         };
+        let handle_result = handle(&mut deps, mock_env("token1address", &[]), handle_msg.clone());
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
 
-        let user_address_bob = &deps.api.canonical_address(&HumanAddr("bob".to_string())).unwrap();
-
-        let order_id: HumanAddr = HumanAddr("bob".to_string());
-        save(&mut deps.storage, SWAPPED_LIMIT_ORDER, &order_id);
+        // Stop asks are checked from the highest stop price
+        assert_eq!(queued_order_ids(&deps.storage, STOP_ASK_ORDER_QUEUE)[0], 2);
 
-        let handle_result = handle(&mut deps, mock_env("token2address", &[]), handle_msg.clone());
+        let handle_result = handle(&mut deps, mock_env("trigerer", &[]), HandleMsg::TriggerLimitOrders { max_orders: None });
         assert!(
             handle_result.is_ok(),
             "handle() failed: {}",
             handle_result.err().unwrap()
-        ); 
+        );
+        assert_eq!(handle_result.unwrap().messages.len(), 1);
+
+        let pending_swaps: Vec<PendingSwap> = load(&deps.storage, PENDING_SWAPS).unwrap();
+        assert_eq!(pending_swaps[0].order.id, 2);
+        assert_eq!(pending_swaps[0].order.order_type, OrderType::Stop);
+        assert_eq!(pending_swaps[0].offer_amount, Uint128(10000000));
+        assert_eq!(pending_swaps[0].expected_return, Uint128(900000000000000000));
     }
 
     #[test]
@@ -1092,7 +4579,8 @@ mod tests {
                         contract_addr: HumanAddr("token1address".to_string()),
                         token_code_hash: "token1hash".to_string()
                     }
-                )
+                ),
+                native_token: None
             },
             AssetInfo {
                 decimal_places: 18,
@@ -1105,6 +4593,7 @@ mod tests {
                         token_code_hash: "token2hash".to_string()
                     }
                 ),
+                native_token: None
             },
             HumanAddr("ammpairaddress".to_string()),
             "ammpairhash".to_string()
@@ -1133,6 +4622,7 @@ mod tests {
                             token_code_hash: "token1hash".to_string()
                         }
                     ),
+                    native_token: None
                 });
                 assert_eq!(assets_info[1],AssetInfo {
                         decimal_places: 18,
@@ -1145,9 +4635,133 @@ mod tests {
                                 token_code_hash: "token2hash".to_string()
                             }
                         ),
+                        native_token: None
                 });
             },
             _ => assert_eq!(true,false)
         }
     }
+    #[test]
+    fn test_query_order_book_depth() {
+        let (init_result, mut deps) = init_helper(
+            HumanAddr("factoryaddress".to_string()),
+            "factoryhash".to_string(),
+            "factorykey".to_string(),
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token1address".to_string()),
+                        token_code_hash: "token1hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            AssetInfo {
+                decimal_places: 18,
+                base_amount: Uint128(1000000000000000000),
+                fee_amount: Uint128(500000000000000000),
+                min_amount: Uint128(1500000000000000),
+                token: Some(
+                    Token {
+                        contract_addr: HumanAddr("token2address".to_string()),
+                        token_code_hash: "token2hash".to_string()
+                    }
+                ),
+                native_token: None
+            },
+            HumanAddr("ammpairaddress".to_string()),
+            "ammpairhash".to_string()
+        );
+        assert!(
+            init_result.is_ok(),
+            "Init failed: {}",
+            init_result.err().unwrap()
+        );
+
+        // Depth is private until the factory enables it
+        let query_result = query(&deps, QueryMsg::OrderBookDepth {});
+        assert!(query_result.is_err());
+
+        let handle_msg = HandleMsg::ChangeDepthConfig {
+            depth_config: Some(DepthConfig {
+                band_width: Price::from_atomics(1000000000000000000),
+                min_orders_per_band: 2
+            })
+        };
+        let handle_result = handle(&mut deps, mock_env("bob", &[]), handle_msg.clone());
+        assert!(handle_result.is_err());
+
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeDepthConfig {
+            depth_config: Some(DepthConfig {
+                band_width: Price::from_atomics(1000000000000000000),
+                min_orders_per_band: 1
+            })
+        });
+        assert!(handle_result.is_err());
+
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), handle_msg);
+        assert!(
+            handle_result.is_ok(),
+            "handle() failed: {}",
+            handle_result.err().unwrap()
+        );
+
+        // Two bids in the 3 band, a lone bid in the 2 band and two asks in the 5 band
+        let orders = vec![
+            ("bob", true, 4000000000000000000u128, 3100000000000000000u128),
+            ("alice", true, 2000000000000000000, 3400000000000000000),
+            ("charlie", true, 1000000000000000000, 2500000000000000000),
+            ("dave", false, 1000000000000000000, 5200000000000000000),
+            ("erin", false, 3000000000000000000, 5700000000000000000),
+        ];
+        for (owner, is_bid, amount, price) in orders {
+            let token_address = if is_bid { "token2address" } else { "token1address" };
+            let handle_msg = HandleMsg::Receive {
+                sender: HumanAddr(token_address.to_string()),
+                from: HumanAddr(owner.to_string()),
+                amount: Uint128(amount),
+                msg: Some(to_binary(&HandleMsg::CreateLimitOrder {
+                    is_bid,
+                    price: Price::from_atomics(price),
+                    expected_amount: Uint128(1000000000000000000),
+                    order_type: None,
+                    expires_at: None
+                }).unwrap())
+            };
+
+            let handle_result = handle(&mut deps, mock_env(token_address, &[]), handle_msg);
+            assert!(
+                handle_result.is_ok(),
+                "handle() failed: {}",
+                handle_result.err().unwrap()
+            );
+        }
+
+        let query_result = query(&deps, QueryMsg::OrderBookDepth {});
+        let result: QueryAnswer = from_binary(&query_result.unwrap()).unwrap();
+        match result {
+            QueryAnswer::OrderBookDepth { band_width, bids, asks } => {
+                assert_eq!(band_width, Price::from_atomics(1000000000000000000));
+                assert_eq!(bids, vec![DepthBand {
+                    price: Price::from_atomics(3000000000000000000),
+                    volume: Uint128(6000000000000000000)
+                }]);
+                assert_eq!(asks, vec![DepthBand {
+                    price: Price::from_atomics(5000000000000000000),
+                    volume: Uint128(4000000000000000000)
+                }]);
+            },
+            _ => assert_eq!(true,false)
+        }
+
+        // And private again once disabled
+        let handle_result = handle(&mut deps, mock_env("factoryaddress", &[]), HandleMsg::ChangeDepthConfig { depth_config: None });
+        assert!(handle_result.is_ok());
+        let query_result = query(&deps, QueryMsg::OrderBookDepth {});
+        assert!(query_result.is_err());
+    }
 }
\ No newline at end of file
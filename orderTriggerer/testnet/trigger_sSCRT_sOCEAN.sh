@@ -20,7 +20,7 @@ do
         result=$(secretcli q compute query $orderbook_address '{"check_order_book_trigger":{}}')
         echo Query Trigger Result: $result
         if [ $result = true ] ;then
-            secretcli tx compute execute $orderbook_address '{"trigger_limit_orders": {}}' --from a -y --gas 3000000 -b block
+            secretcli tx compute execute $orderbook_address '{"trigger_limit_orders": {"max_orders": 5}}' --from a -y --gas 3000000 -b block
         fi
         trigger_block=$last_block
     fi
@@ -204,7 +204,7 @@ export default ({
                                                         msg: btoa(JSON.stringify({
                                                             create_limit_order: {
                                                                 is_bid: limitOrderIsBidInput,
-                                                                price: "" + Math.floor(limitOrderPriceInput*Math.pow(10, tokensData.find((data: any) => data.dst_address === secretOrderBooks.secret_order_books[selectedAmmFactoryPairIndex].asset_infos[1].token.contract_addr).decimals)),
+                                                                price: "" + limitOrderPriceInput,
                                                                 expected_amount: "" + Math.floor(limitOrderExpectedAmount*Math.pow(10, tokensData.find((data: any) => data.dst_address === secretOrderBooks.secret_order_books[selectedAmmFactoryPairIndex].asset_infos[limitOrderIsBidInput ? 0 : 1].token.contract_addr).decimals)),
                                                             }
                                                         }))
@@ -10,48 +10,53 @@ export default ({
     viewKey,
     pair
 }: MyLimitOrdersProps) => {
-    const [activelimitOrderData, setActiveLimitOrderData] = useState<any>(null)
+    const [activeLimitOrdersData, setActiveLimitOrdersData] = useState<any[]>([])
     const [historyLimitOrdersData, setHistoryLimitOrdersData] = useState<any>(null)
     const [orderBookTokensData, setOrderBookTokensData] = useState<any>(null)
     const [ammPriceData, setAmmPriceData] = useState<any>(null)
     
     useEffect(() => {
         async function init() {
-            const limitOrderPromise = client.execute.queryContractSmart(pair.contract_addr, { 
-                get_active_limit_order: {
-                    user_address: client.accountData.address,
-                    user_viewkey: viewKey,
-                    //limit: PAGINATION_LIMIT,
-                    //offset: PAGINATION_OFFSET
-                }
-              })
-
-            const orderBookTokenDataPromise = client.execute.queryContractSmart(pair.contract_addr, { 
+            const orderBookTokenData = await client.execute.queryContractSmart(pair.contract_addr, { 
                 order_book_pair_info: {}
               })
 
-            const [limitOrder, orderBookTokenData] = await Promise.all([limitOrderPromise, orderBookTokenDataPromise]);
-
-            setActiveLimitOrderData(limitOrder.active_limit_order.active_limit_order)
+            setActiveLimitOrdersData(await getActiveLimitOrders())
             setOrderBookTokensData(orderBookTokenData.order_book_pair)
             setAmmPriceData(await getAmmPrice(orderBookTokenData.order_book_pair))
 
             setInterval(async () => {
-                const limitOrder = await client.execute.queryContractSmart(pair.contract_addr, { 
-                    get_active_limit_order: {
-                        user_address: client.accountData.address,
-                        user_viewkey: viewKey,
-                        //limit: PAGINATION_LIMIT,
-                        //offset: PAGINATION_OFFSET
-                    }
-                  })
-                setActiveLimitOrderData(limitOrder.active_limit_order.active_limit_order);
+                setActiveLimitOrdersData(await getActiveLimitOrders());
                 setAmmPriceData(await getAmmPrice(orderBookTokenData.order_book_pair))
             },12000)
           }
         init()
     }, [])
 
+    // A user can have several active orders, list their ids and load each one by id
+    const getActiveLimitOrders = async () => {
+        const limitOrders = await client.execute.queryContractSmart(pair.contract_addr, { 
+            get_active_limit_orders: {
+                user_address: client.accountData.address,
+                user_viewkey: viewKey
+            }
+          })
+        const orderIds: number[] = limitOrders.active_limit_orders.active_limit_orders.map((limitOrder: any) => limitOrder.id)
+
+        const activeLimitOrders = await Promise.all(orderIds.map((orderId: number) =>
+            client.execute.queryContractSmart(pair.contract_addr, { 
+                get_active_limit_order: {
+                    user_address: client.accountData.address,
+                    user_viewkey: viewKey,
+                    order_id: orderId
+                }
+              })
+        ))
+        return activeLimitOrders
+            .map((limitOrder: any) => limitOrder.active_limit_order.active_limit_order)
+            .filter((limitOrder: any) => limitOrder)
+    }
+
     const getAmmPrice = async (orderBookTokenData: any) => {
         return client.execute.queryContractSmart(orderBookTokenData.amm_pair_address, { 
             simulation: {
@@ -81,7 +86,7 @@ export default ({
         const token1Data = tokensData.find((data: any) => data.dst_address === orderBookTokensData.assets_info[0].token.contract_addr);
         const token2Data = tokensData.find((data: any) => data.dst_address === orderBookTokensData.assets_info[1].token.contract_addr);
         if (type === "order") {
-            return Math.round(limitOrderData.price/Math.pow(10,orderBookTokensData.assets_info[1].decimal_places) * 100000) / 100000 + " " + token2Data.display_props.symbol + " per " + token1Data.display_props.symbol 
+            return Math.round(limitOrderData.price * 100000) / 100000 + " " + token2Data.display_props.symbol + " per " + token1Data.display_props.symbol 
         } else if (type === "amm") {
             return Math.round(ammPriceData.return_amount/Math.pow(10,orderBookTokensData.assets_info[1].decimal_places) * 100000) / 100000 + " " + token2Data.display_props.symbol + " per " + token1Data.display_props.symbol 
         } else if (type === "triggered") {
@@ -117,9 +122,9 @@ export default ({
         return "Expected (~): " + amount + " " + findTokenData(index).display_props.symbol
     }
 
-    const rowStyle = activelimitOrderData ? {
-        backgroundColor: activelimitOrderData.status === "Filled" ? "#Cfffbc" : "#Fff4ad"
-    } : undefined
+    const rowStyle = (activelimitOrderData: any) => ({
+        backgroundColor: activelimitOrderData.status === "filled" ? "#Cfffbc" : "#Fff4ad"
+    })
 
     return (
         <div>
@@ -139,8 +144,8 @@ export default ({
                 </thead>
                 <tbody>
                 {
-                    activelimitOrderData && 
-                    <tr key={pair.contract_addr} style={rowStyle}>
+                    activeLimitOrdersData.map((activelimitOrderData: any) =>
+                    <tr key={activelimitOrderData.id} style={rowStyle(activelimitOrderData)}>
                         <td>{new Date(activelimitOrderData.timestamp*1000).toLocaleString()}</td>
                         {orderBookTokensData && tokensData && <td>{pairDisplay()}</td>}
                         <td>{activelimitOrderData.is_bid ? "Buy" : "Sell"}</td>
@@ -155,7 +160,7 @@ export default ({
                             <div>
                                 {
                                     ammPriceData && 
-                                    activelimitOrderData.status === "Filled" ? 
+                                    activelimitOrderData.status === "filled" ? 
                                     displayPrice("triggered", activelimitOrderData)
                                     : " - "}
                             </div>
@@ -164,7 +169,9 @@ export default ({
                         {activelimitOrderData && <td>{<Button onClick={ async () => {
                             try{
                                 await client.execute.execute(pair.contract_addr, { 
-                                    cancel_limit_order: {}
+                                    cancel_limit_order: {
+                                        order_id: activelimitOrderData.id
+                                    }
                                 })
                                 remountMyLimitOrders()
                             } catch (e) {
@@ -175,6 +182,7 @@ export default ({
                             {activelimitOrderData && orderBookTokensData && displayBalance(0, activelimitOrderData) + "  and  " + displayBalance(1, activelimitOrderData)}
                         </Button>}</td>}
                     </tr>
+                    )
                 }
                 </tbody>
             </Table>